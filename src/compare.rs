@@ -0,0 +1,92 @@
+use crate::llm::PaperMetadata;
+use crate::preview;
+use std::time::Duration;
+
+/// What one model extracted from the paper, and how long it took
+#[derive(Debug)]
+pub struct Run {
+    pub model: String,
+    pub elapsed: Duration,
+    pub metadata: Result<PaperMetadata, String>,
+}
+
+/// Lay the runs out as a table with one model per line, in the order given
+/// A failed run shows its error in place of the fields
+pub fn render_table(runs: &[Run]) -> String {
+    let lines: Vec<[String; 5]> = runs
+        .iter()
+        .map(|run| {
+            let latency = format!("{:.1}s", run.elapsed.as_secs_f64());
+            match &run.metadata {
+                Ok(metadata) => [
+                    run.model.clone(),
+                    latency,
+                    metadata.first_author.clone(),
+                    metadata.year.clone(),
+                    metadata.title.clone(),
+                ],
+                Err(e) => [run.model.clone(), latency, String::new(), String::new(), format!("failed: {}", e)],
+            }
+        })
+        .collect();
+    let cells: Vec<[&str; 5]> = lines.iter().map(|line| line.each_ref().map(String::as_str)).collect();
+    preview::align_columns(["Model", "Latency", "Author", "Year", "Title"], &cells)
+}
+
+/// How many distinct author/year/title answers the successful runs gave
+pub fn distinct_answers(runs: &[Run]) -> usize {
+    let mut answers: Vec<(String, &str, String)> = runs
+        .iter()
+        .filter_map(|run| run.metadata.as_ref().ok())
+        .map(|metadata| {
+            (
+                metadata.first_author.to_lowercase(),
+                metadata.year.as_str(),
+                metadata.title.to_lowercase(),
+            )
+        })
+        .collect();
+    answers.sort();
+    answers.dedup();
+    answers.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(model: &str, seconds: f64, title: &str) -> Run {
+        Run {
+            model: model.to_string(),
+            elapsed: Duration::from_secs_f64(seconds),
+            metadata: Ok(PaperMetadata {
+                first_author: "Vaswani".to_string(),
+                year: "2017".to_string(),
+                title: title.to_string(),
+                ..PaperMetadata::default()
+            }),
+        }
+    }
+
+    #[test]
+    fn test_render_table() {
+        let runs = vec![
+            run("llama3.2", 2.34, "Attention Is All You Need"),
+            run("qwen2.5:14b", 8.0, "attention is all you need"),
+            Run {
+                model: "mistral".to_string(),
+                elapsed: Duration::from_secs(31),
+                metadata: Err("Failed to parse metadata".to_string()),
+            },
+        ];
+
+        let table = render_table(&runs);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "Model        Latency  Author   Year  Title");
+        assert_eq!(lines[2], "llama3.2     2.3s     Vaswani  2017  Attention Is All You Need");
+        assert_eq!(lines[4], "mistral      31.0s                   failed: Failed to parse metadata");
+
+        // Differences in case alone are the same answer
+        assert_eq!(distinct_answers(&runs), 1);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::filename::{self, NamingOptions, NonLatin, TieBreak};
+use crate::ui;
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the per-directory settings file
+pub const CONFIG_FILE: &str = ".paper-renamer.toml";
+
+/// Settings file written by init-config, with every key commented out at its
+/// default; keys without one are left empty, with an example in their comment
+const DEFAULT_CONFIG: &str = r#"# paper-renamer settings
+#
+# These apply to every run. A .paper-renamer.toml in a folder overrides them for
+# the PDFs under it, and PAPER_RENAMER_* environment variables and options given
+# on the command line override both.
+# Keys are named like the command-line options; uncomment a line to change it.
+# Keys shown without a value are unset by default; give one to set them.
+
+# Ollama model to use, e.g. "llama3.2" (unset: detected automatically)
+# model =
+
+# Filename template, or the preset "citekey". Placeholders: {author},
+# {first_author_full}, {year}, {title}, {title_word}, {version}, {category},
+# {type}, {publisher}, {doi}
+# format = "{author}-{year}-{title}"
+
+# Titles in CJK, Arabic or Cyrillic script: "keep", "translit" (romanize) or
+# "romaji" (romanize Japanese kana only)
+# non-latin = "keep"
+
+# Strip accents from the author name only, keeping them in the title
+# strip-accents-in-author-only = false
+
+# Cut the title part of the filename to at most this many characters, at a
+# word boundary, e.g. 80 (unset: no limit)
+# title-max-length =
+
+# Drop one-letter words (e.g. "a") from the title part of the filename
+# drop-single-char-words = false
+
+# Drop numbers left at the end of the title (e.g. a stray page number)
+# trim-trailing-numbers = false
+
+# Shorten the title to the first letter of each significant word
+# title-acronym = false
+
+# Rejoin words hyphenated across line breaks and collapse whitespace in the title
+# normalize-whitespace-in-title = false
+
+# Start every name with this many hex characters of the file's SHA-256, e.g. 8
+# (unset: no hash)
+# hash-prefix =
+
+# When the LLM and the embedded metadata give the same title at different
+# lengths (e.g. with and without the subtitle), keep the "shortest" or the
+# "complete" one (unset: the source precedence decides)
+# name-tie-break =
+
+# Symbols and messages of the terminal output, e.g. "OK" and "!" where the
+# defaults show up as boxes. Only read from this file, not from a folder's
+# .paper-renamer.toml
+# success-symbol = "✓"
+# error-symbol = "⚠"
+
+# Shown after a rename; {old} and {new} stand for the old and new name
+# success-message = "File renamed successfully!\n  {old} -> {new}"
+
+# Shown for an error; {message} stands for the error
+# error-message = "Error: {message}"
+"#;
+
+/// Settings for the papers of one collection, read from a `.paper-renamer.toml`
+/// Keys are named like the command-line options they stand in for
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct DirConfig {
+    pub model: Option<String>,
+    pub format: Option<String>,
+    pub non_latin: Option<NonLatin>,
+    pub strip_accents_in_author_only: Option<bool>,
+    pub title_max_length: Option<usize>,
+    pub drop_single_char_words: Option<bool>,
+    pub trim_trailing_numbers: Option<bool>,
+    pub title_acronym: Option<bool>,
+    pub normalize_whitespace_in_title: Option<bool>,
+    pub hash_prefix: Option<usize>,
+    pub name_tie_break: Option<TieBreak>,
+    pub success_symbol: Option<String>,
+    pub error_symbol: Option<String>,
+    pub success_message: Option<String>,
+    pub error_message: Option<String>,
+}
+
+impl DirConfig {
+    /// Apply the settings to the naming options, except those whose option was
+    /// given on the command line or through an environment variable (`explicit`
+    /// holds their argument ids)
+    pub fn apply(&self, naming: &mut NamingOptions, explicit: &HashSet<String>) {
+        let free = |id: &str| !explicit.contains(id);
+
+        if let Some(format) = self.format.clone().filter(|_| free("format")) {
+            naming.format = filename::expand_preset(&format);
+        }
+        if let Some(non_latin) = self.non_latin.filter(|_| free("non_latin")) {
+            naming.non_latin = non_latin;
+        }
+        if let Some(ascii) = self.strip_accents_in_author_only.filter(|_| free("strip_accents_in_author_only")) {
+            naming.ascii_author = ascii;
+        }
+        if let Some(max) = self.title_max_length.filter(|_| free("title_max_length")) {
+            naming.max_title_length = Some(max);
+        }
+        if let Some(drop) = self.drop_single_char_words.filter(|_| free("drop_single_char_words")) {
+            naming.drop_single_char_words = drop;
+        }
+        if let Some(trim) = self.trim_trailing_numbers.filter(|_| free("trim_trailing_numbers")) {
+            naming.trim_trailing_numbers = trim;
+        }
+        if let Some(acronym) = self.title_acronym.filter(|_| free("title_acronym")) {
+            naming.title_acronym = acronym;
+        }
+        if let Some(normalize) = self.normalize_whitespace_in_title.filter(|_| free("normalize_whitespace_in_title")) {
+            naming.normalize_title_whitespace = normalize;
+        }
+        if let Some(len) = self.hash_prefix.filter(|_| free("hash_prefix")) {
+            naming.hash_prefix = Some(len);
+        }
+        if let Some(tie_break) = self.name_tie_break.filter(|_| free("name_tie_break") && free("prefer_shorter_name")) {
+            naming.name_tie_break = Some(tie_break);
+        }
+    }
+
+    /// The symbols and message templates to show, the built-in ones where not set
+    /// Only the user's settings are read for these
+    pub fn messages(&self) -> ui::Messages {
+        let defaults = ui::Messages::default();
+        ui::Messages {
+            success_symbol: self.success_symbol.clone().unwrap_or(defaults.success_symbol),
+            error_symbol: self.error_symbol.clone().unwrap_or(defaults.error_symbol),
+            success_message: self.success_message.clone().unwrap_or(defaults.success_message),
+            error_message: self.error_message.clone().unwrap_or(defaults.error_message),
+        }
+    }
+
+    /// The model to use instead of the run's, unless --model or
+    /// PAPER_RENAMER_MODEL was given
+    pub fn model(&self, explicit: &HashSet<String>) -> Option<&str> {
+        self.model.as_deref().filter(|_| !explicit.contains("model"))
+    }
+}
+
+/// Find the settings file nearest to a PDF: in its directory or the closest
+/// parent directory that has one. Returns the file's path and its settings
+pub fn find_for(file_path: &str) -> Result<Option<(PathBuf, DirConfig)>> {
+    let absolute = std::path::absolute(file_path)
+        .with_context(|| format!("Failed to resolve path: {}", file_path))?;
+
+    let Some(path) = absolute
+        .ancestors()
+        .skip(1)
+        .map(|dir| dir.join(CONFIG_FILE))
+        .find(|candidate| candidate.is_file())
+    else {
+        return Ok(None);
+    };
+
+    let config = read_config(&path)?;
+    Ok(Some((path, config)))
+}
+
+/// Where the user's own settings live, e.g. `~/.config/paper-renamer/config.toml` on Linux
+pub fn user_config_path() -> Result<PathBuf> {
+    let config_dir = dirs::config_dir().context("Failed to locate a config directory")?;
+    Ok(config_dir.join("paper-renamer").join("config.toml"))
+}
+
+/// The user's own settings, if they have a settings file
+pub fn user_config() -> Result<Option<(PathBuf, DirConfig)>> {
+    let path = user_config_path()?;
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let config = read_config(&path)?;
+    Ok(Some((path, config)))
+}
+
+/// Write a settings file listing every key at its default, commented out
+/// An existing file is only replaced with `force`
+pub fn write_default_config(path: &Path, force: bool) -> Result<()> {
+    if path.exists() && !force {
+        anyhow::bail!("{} already exists; pass --force to overwrite it", path.display());
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(path, DEFAULT_CONFIG).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+fn read_config(path: &Path) -> Result<DirConfig> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let config: DirConfig =
+        toml::from_str(&contents).with_context(|| format!("Invalid settings in {}", path.display()))?;
+    if let Some(format) = &config.format {
+        filename::validate_format(&filename::expand_preset(format)).with_context(|| format!("Invalid format in {}", path.display()))?;
+    }
+    if let Some(template) = &config.success_message {
+        ui::check_template(template, &["old", "new"])
+            .with_context(|| format!("Invalid success-message in {}", path.display()))?;
+    }
+    if let Some(template) = &config.error_message {
+        ui::check_template(template, &["message"])
+            .with_context(|| format!("Invalid error-message in {}", path.display()))?;
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_for_walks_up() {
+        let temp_dir = TempDir::new().unwrap();
+        let collection = temp_dir.path().join("collection");
+        fs::create_dir_all(collection.join("2020")).unwrap();
+        fs::write(
+            collection.join(CONFIG_FILE),
+            "format = \"{year}_{title}\"\nnon-latin = \"translit\"\n",
+        )
+        .unwrap();
+
+        let pdf = collection.join("2020/paper.pdf");
+        let (path, config) = find_for(pdf.to_str().unwrap()).unwrap().unwrap();
+        assert_eq!(path, collection.join(CONFIG_FILE));
+        assert_eq!(config.format.as_deref(), Some("{year}_{title}"));
+        assert_eq!(config.non_latin, Some(NonLatin::Translit));
+
+        // Outside the collection there is nothing to find
+        let other = temp_dir.path().join("other.pdf");
+        assert!(find_for(other.to_str().unwrap()).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_read_config_rejects_unknown_keys() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(CONFIG_FILE);
+        fs::write(&path, "fromat = \"{title}\"\n").unwrap();
+        assert!(read_config(&path).is_err());
+
+        fs::write(&path, "format = \"{nope}\"\n").unwrap();
+        assert!(read_config(&path).is_err());
+
+        fs::write(&path, "success-message = \"{from} -> {to}\"\n").unwrap();
+        assert!(read_config(&path).is_err());
+    }
+
+    #[test]
+    fn test_default_config_lists_every_key() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("paper-renamer/config.toml");
+        write_default_config(&path, false).unwrap();
+        assert!(write_default_config(&path, false).is_err());
+        write_default_config(&path, true).unwrap();
+
+        // Everything is commented out, so the file changes nothing
+        assert_eq!(read_config(&path).unwrap(), DirConfig::default());
+
+        // Uncommented, every key with a value is valid and holds the real default
+        let uncommented: String = DEFAULT_CONFIG
+            .lines()
+            .filter_map(|line| line.strip_prefix("# "))
+            .filter(|line| line.contains(" = "))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        let config: DirConfig = toml::from_str(&uncommented).unwrap();
+        assert_eq!(config.format.as_deref(), Some(filename::DEFAULT_FORMAT));
+        assert_eq!(config.non_latin, Some(NonLatin::default()));
+        let defaults = [
+            config.strip_accents_in_author_only == Some(false),
+            config.drop_single_char_words == Some(false),
+            config.trim_trailing_numbers == Some(false),
+            config.title_acronym == Some(false),
+            config.normalize_whitespace_in_title == Some(false),
+        ];
+        assert!(defaults.iter().all(|&listed| listed));
+        assert_eq!(config.messages(), ui::Messages::default());
+
+        // Keys that are unset by default are listed without a value
+        assert_eq!(
+            (config.model, config.title_max_length, config.hash_prefix, config.name_tie_break),
+            (None, None, None, None)
+        );
+        for key in ["model", "title-max-length", "hash-prefix", "name-tie-break"] {
+            assert!(DEFAULT_CONFIG.lines().any(|line| line == format!("# {} =", key)), "{}", key);
+        }
+    }
+
+    #[test]
+    fn test_apply_keeps_command_line_options() {
+        let config = DirConfig {
+            format: Some("{year}-{title}".to_string()),
+            title_acronym: Some(true),
+            ..DirConfig::default()
+        };
+        let mut naming = NamingOptions::default();
+        let explicit = HashSet::from(["format".to_string()]);
+        config.apply(&mut naming, &explicit);
+
+        assert_eq!(naming.format, filename::DEFAULT_FORMAT);
+        assert!(naming.title_acronym);
+    }
+}
@@ -0,0 +1,129 @@
+use crate::llm::PaperMetadata;
+use crate::renamer::RenamePlan;
+use anyhow::{Context, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Key under which two files count as the same paper: the author, year and
+/// title reduced to lowercase ASCII letters and digits, so differences in
+/// accents, punctuation, case and spacing don't matter
+fn paper_key(metadata: &PaperMetadata) -> Option<String> {
+    let normalize = |value: &str| -> String {
+        any_ascii::any_ascii(value)
+            .to_lowercase()
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .collect()
+    };
+
+    let title = normalize(&metadata.title);
+    if title.is_empty() {
+        return None;
+    }
+    Some(format!("{}|{}|{}", normalize(&metadata.first_author), normalize(&metadata.year), title))
+}
+
+/// Find plans whose extracted metadata describes the same paper
+/// Returns groups of plan indices with more than one member, in plan order;
+/// the first index of each group is the copy to keep
+pub fn duplicate_groups(plans: &[RenamePlan]) -> Vec<Vec<usize>> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut by_key: HashMap<String, usize> = HashMap::new();
+
+    for (index, plan) in plans.iter().enumerate() {
+        let Some(key) = plan.metadata.as_ref().and_then(paper_key) else {
+            continue;
+        };
+        match by_key.get(&key) {
+            Some(&group) => groups[group].push(index),
+            None => {
+                by_key.insert(key, groups.len());
+                groups.push(vec![index]);
+            }
+        }
+    }
+
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// Move a file into the quarantine directory, keeping its name
+/// A name already taken there gets a `-2`, `-3`, ... suffix
+pub fn quarantine(source: &str, dir: &Path) -> Result<PathBuf> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create quarantine directory: {}", dir.display()))?;
+
+    let name = Path::new(source)
+        .file_name()
+        .with_context(|| format!("Invalid file path: {}", source))?
+        .to_string_lossy()
+        .into_owned();
+    let (stem, extension) = name.rsplit_once('.').unwrap_or((&name, ""));
+
+    let mut target = dir.join(&name);
+    for n in 2.. {
+        if !target.exists() {
+            break;
+        }
+        target = dir.join(if extension.is_empty() {
+            format!("{}-{}", stem, n)
+        } else {
+            format!("{}-{}.{}", stem, n, extension)
+        });
+    }
+
+    fs::rename(source, &target)
+        .with_context(|| format!("Failed to move {} to {}", source, target.display()))?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn plan(source: &str, author: &str, year: &str, title: &str) -> RenamePlan {
+        RenamePlan {
+            source: source.to_string(),
+            new_filename: format!("{}.pdf", source),
+            metadata: Some(PaperMetadata {
+                first_author: author.to_string(),
+                year: year.to_string(),
+                title: title.to_string(),
+                ..PaperMetadata::default()
+            }),
+            ..RenamePlan::default()
+        }
+    }
+
+    #[test]
+    fn test_duplicate_groups() {
+        let plans = vec![
+            plan("a", "Vaswani", "2017", "Attention Is All You Need"),
+            plan("b", "He", "2015", "Deep Residual Learning"),
+            plan("c", "vaswani", "2017", "Attention is all you need."),
+            plan("d", "Müller", "2020", "Über Lernen"),
+            plan("e", "Muller", "2020", "Uber Lernen"),
+            plan("f", "He", "2016", "Deep Residual Learning"),
+        ];
+
+        assert_eq!(duplicate_groups(&plans), vec![vec![0, 2], vec![3, 4]]);
+    }
+
+    #[test]
+    fn test_quarantine_keeps_existing_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path().join("duplicates");
+        let first = temp_dir.path().join("paper.pdf");
+        let second = temp_dir.path().join("other/paper.pdf");
+        fs::create_dir(temp_dir.path().join("other")).unwrap();
+        fs::write(&first, "one").unwrap();
+        fs::write(&second, "two").unwrap();
+
+        assert_eq!(quarantine(first.to_str().unwrap(), &dir).unwrap(), dir.join("paper.pdf"));
+        assert_eq!(quarantine(second.to_str().unwrap(), &dir).unwrap(), dir.join("paper-2.pdf"));
+        assert_eq!(fs::read_to_string(dir.join("paper.pdf")).unwrap(), "one");
+        assert!(!second.exists());
+    }
+}
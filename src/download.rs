@@ -0,0 +1,137 @@
+use anyhow::{Context, Result};
+use reqwest::blocking::Client;
+use reqwest::header::CONTENT_TYPE;
+use std::fs;
+use std::io::Read;
+use std::time::Duration;
+use tempfile::TempPath;
+
+/// Identifies the tool to the servers it downloads from, with where to find
+/// out more, as publishers and repositories ask of automated clients
+const USER_AGENT: &str = concat!(
+    "paper-renamer/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/wwlorey/paper-renamer)"
+);
+
+/// Largest PDF that will be downloaded
+const MAX_DOWNLOAD_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Check whether an input is an http(s) URL rather than a local path
+pub fn is_url(input: &str) -> bool {
+    let lower = input.to_ascii_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// Download a PDF into a hidden temporary file in the current directory
+/// Keeping it in the current directory lets the renamed result land there
+/// with a plain rename. The file is deleted when the returned path is
+/// dropped, unless it has been renamed by then.
+pub fn download_pdf(url: &str) -> Result<TempPath> {
+    let client = Client::builder()
+        .timeout(Duration::from_secs(120))
+        .user_agent(USER_AGENT)
+        .build()
+        .context("Failed to create HTTP client")?;
+
+    let response = client
+        .get(url)
+        .send()
+        .with_context(|| format!("Failed to download {}", url))?
+        .error_for_status()
+        .with_context(|| format!("Failed to download {}", url))?;
+
+    if let Some(content_type) = response.headers().get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) {
+        check_content_type(content_type)
+            .with_context(|| format!("{} does not point to a PDF", url))?;
+    }
+
+    if response.content_length().is_some_and(|len| len > MAX_DOWNLOAD_BYTES) {
+        anyhow::bail!("{} is larger than the {} MB download limit", url, MAX_DOWNLOAD_BYTES / 1024 / 1024);
+    }
+
+    // The declared length can be missing or wrong, so cap the read as well
+    let mut bytes = Vec::new();
+    response
+        .take(MAX_DOWNLOAD_BYTES + 1)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to download {}", url))?;
+
+    if bytes.len() as u64 > MAX_DOWNLOAD_BYTES {
+        anyhow::bail!("{} is larger than the {} MB download limit", url, MAX_DOWNLOAD_BYTES / 1024 / 1024);
+    }
+    if !bytes.starts_with(b"%PDF-") {
+        anyhow::bail!("{} does not point to a PDF", url);
+    }
+
+    let path = tempfile::Builder::new()
+        .prefix(".paper-renamer-download-")
+        .suffix(".pdf")
+        .tempfile_in(".")
+        .context("Failed to create a file for the download")?
+        .into_temp_path();
+    fs::write(&path, bytes).context("Failed to save the downloaded PDF")?;
+
+    Ok(path)
+}
+
+/// Reject responses that are clearly not a PDF (e.g. a login page)
+/// Servers often label PDFs as generic binary data, so that is accepted too
+fn check_content_type(content_type: &str) -> Result<()> {
+    let mime = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    match mime.as_str() {
+        "application/pdf" | "application/x-pdf" | "application/octet-stream" | "binary/octet-stream" | "" => Ok(()),
+        other => anyhow::bail!("the server returned {}", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_url() {
+        assert!(is_url("https://arxiv.org/pdf/1706.03762"));
+        assert!(is_url("HTTP://example.com/paper.pdf"));
+        assert!(!is_url("paper.pdf"));
+        assert!(!is_url("/home/user/https-paper.pdf"));
+    }
+
+    #[test]
+    fn test_download_sends_user_agent() {
+        use std::io::{BufRead, BufReader, Write};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/paper.pdf", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut user_agent = String::new();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                if let Some(value) = line.to_ascii_lowercase().strip_prefix("user-agent:") {
+                    user_agent = value.trim().to_string();
+                }
+                line.clear();
+            }
+            let body = b"%PDF-1.4 contents";
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/pdf\r\nContent-Length: {}\r\n\r\n", body.len())
+                .unwrap();
+            stream.write_all(body).unwrap();
+            user_agent
+        });
+
+        let path = download_pdf(&url).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"%PDF-1.4 contents");
+        assert_eq!(server.join().unwrap(), USER_AGENT.to_ascii_lowercase());
+    }
+
+    #[test]
+    fn test_check_content_type() {
+        assert!(check_content_type("application/pdf").is_ok());
+        assert!(check_content_type("application/octet-stream").is_ok());
+        assert!(check_content_type("Application/PDF; charset=binary").is_ok());
+        assert!(check_content_type("text/html; charset=utf-8").is_err());
+    }
+}
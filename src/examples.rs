@@ -0,0 +1,53 @@
+use crate::filename::{self, NamingOptions};
+use crate::llm::PaperMetadata;
+
+/// Sample papers covering the cases naming options care about
+fn samples() -> Vec<PaperMetadata> {
+    let paper = |author: &str, full: &str, year: &str, title: &str, version: &str| PaperMetadata {
+        first_author: author.to_string(),
+        first_author_full: full.to_string(),
+        year: year.to_string(),
+        title: title.to_string(),
+        version: version.to_string(),
+        ..PaperMetadata::default()
+    };
+
+    vec![
+        paper("Vaswani", "Ashish Vaswani", "2017", "Attention Is All You Need", ""),
+        paper("Müller", "Jörg Müller", "2020", "Über die Grenzen des Lernens", ""),
+        paper("He", "Kaiming He", "2015", "Deep Residual Learning for Image Recognition", "v1"),
+        paper("Li", "Wei Li", "2021", "深度学习综述", ""),
+        paper(
+            "Smith",
+            "Jane Smith",
+            "2019",
+            "A Study of X: Methods, Results and Open Problems in Volume 12",
+            "",
+        ),
+    ]
+}
+
+/// Print the filenames the sample papers would get under the given settings
+pub fn print_examples(options: &NamingOptions) {
+    println!("Format: {}\n", options.format);
+
+    for metadata in samples() {
+        println!("  {} ({}): {}", metadata.title, metadata.year, metadata.first_author_full);
+        println!("    → {}\n", filename::generate_filename(&metadata, options));
+    }
+
+    println!("Try changing --format, --non-latin, --strip-accents-in-author-only or the title options to compare.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_samples_produce_valid_filenames() {
+        for metadata in samples() {
+            let name = filename::generate_filename(&metadata, &NamingOptions::default());
+            assert!(filename::validate_filename(&name, filename::DEFAULT_MAX_FILENAME_BYTES), "{}", name);
+        }
+    }
+}
@@ -1,30 +1,695 @@
 use crate::llm::PaperMetadata;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+/// Default filename template, matching the standard naming convention
+pub const DEFAULT_FORMAT: &str = "{author}-{year}-{title}";
+
+/// Template of the `citekey` preset: a BibTeX-style key such as `vaswani2017attention`
+pub const CITEKEY_FORMAT: &str = "{author}{year}{title_word}";
+
+/// Named templates that --format accepts in place of a template
+const PRESETS: &[(&str, &str)] = &[("citekey", CITEKEY_FORMAT)];
+
+/// Longest filename, in bytes, that common filesystems accept
+pub const DEFAULT_MAX_FILENAME_BYTES: usize = 255;
+
+/// Longest full path, in bytes, that the platform accepts (PATH_MAX or MAX_PATH,
+/// less the terminating NUL)
+#[cfg(windows)]
+pub const MAX_PATH_BYTES: usize = 259;
+#[cfg(target_os = "macos")]
+pub const MAX_PATH_BYTES: usize = 1023;
+#[cfg(not(any(windows, target_os = "macos")))]
+pub const MAX_PATH_BYTES: usize = 4095;
+
+/// Placeholders that may appear in a filename template
+const PLACEHOLDERS: &[&str] = &[
+    "author",
+    "first_author_full",
+    "year",
+    "title",
+    "title_word",
+    "version",
+    "category",
+    "type",
+    "publisher",
+    "doi",
+];
+
+/// Titles with fewer significant words than this are kept whole by --title-acronym
+const ACRONYM_MIN_WORDS: usize = 3;
+
+/// Words that don't count as significant in a title, skipped when abbreviating
+/// it to an acronym or picking the word for a citation key
+const STOPWORDS: &[&str] = &[
+    "a", "an", "the", "of", "for", "and", "or", "in", "on", "at", "to", "by", "with", "from", "via",
+];
+
+/// How titles written in a non-Latin script are turned into filenames
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NonLatin {
+    /// Keep the original characters
+    #[default]
+    Keep,
+    /// Romanize CJK, Arabic and Cyrillic titles (e.g. Chinese to pinyin)
+    Translit,
+    /// Romanize Japanese kana only, keeping kanji and other scripts
+    Romaji,
+}
+
+/// Which title to keep when the LLM and the embedded metadata give the same title
+/// at different lengths, e.g. with and without its subtitle
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TieBreak {
+    /// The one giving the shorter filename
+    Shortest,
+    /// The one giving the longer, more complete filename
+    Complete,
+}
+
+/// Writing system a title is predominantly written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Script {
+    Latin,
+    Cjk,
+    Arabic,
+    Cyrillic,
+}
+
+/// A function turning text into its ASCII romanization
+pub type Transliterator = fn(&str) -> String;
+
+/// Options controlling how filenames are generated from metadata
+#[derive(Debug, Clone)]
+pub struct NamingOptions {
+    /// Filename template (without the .pdf extension)
+    pub format: String,
+    /// Transliterate the author fields to ASCII, leaving the title untouched
+    pub ascii_author: bool,
+    /// Handling of titles in CJK, Arabic or Cyrillic script
+    pub non_latin: NonLatin,
+    /// Extension appended to generated names, without the dot
+    pub extension: String,
+    /// Cut the title slug to at most this many characters, at a word boundary
+    pub max_title_length: Option<usize>,
+    /// Word appended to a title slug that `max_title_length` cut short (e.g. "etc")
+    pub truncation_token: Option<String>,
+    /// Drop one-letter words (e.g. "a") from the title slug
+    pub drop_single_char_words: bool,
+    /// Articles dropped from the start of the title slug (lowercase); empty keeps them
+    pub leading_articles: Vec<String>,
+    /// Reduce the title slug to the first letters of its significant words
+    pub title_acronym: bool,
+    /// Drop numeric-only words from the end of the title slug
+    pub trim_trailing_numbers: bool,
+    /// Longest filename in bytes, including the extension
+    pub max_filename_bytes: usize,
+    /// Choice between two titles that differ only in length; None keeps the source precedence
+    pub name_tie_break: Option<TieBreak>,
+    /// Rejoin words hyphenated across line breaks and collapse whitespace in the title
+    pub normalize_title_whitespace: bool,
+    /// Find/replace rules applied to the title, in order, before it is sanitized
+    pub title_replacements: Vec<TitleReplacement>,
+    /// Characters spelled out in the title before it is sanitized (e.g. "α" as "alpha"),
+    /// longest first; see `load_translit_map`
+    pub title_transliterations: Vec<(String, String)>,
+    /// Start the name with this many hex characters of the file's content hash
+    pub hash_prefix: Option<usize>,
+    /// Pattern every final filename (with its extension) must match
+    pub require_match: Option<Regex>,
+    /// How a 4-digit year is written: a pattern with `{yyyy}` (the whole year)
+    /// and/or `{yy}` (its last two digits)
+    pub year_format: String,
+}
+
+/// One `--replace-in-title` rule
+#[derive(Debug, Clone)]
+pub enum TitleReplacement {
+    /// Replace every occurrence of a plain string
+    Literal { find: String, replace: String },
+    /// Replace every match of a regex; `$1` etc. refer to capture groups
+    Regex { find: Regex, replace: String },
+}
+
+impl TitleReplacement {
+    fn apply(&self, title: &str) -> String {
+        match self {
+            TitleReplacement::Literal { find, replace } => title.replace(find.as_str(), replace),
+            TitleReplacement::Regex { find, replace } => find.replace_all(title, replace.as_str()).into_owned(),
+        }
+    }
+}
+
+/// Built-in `--translit-map` entries: Greek letters common in ML and physics
+/// titles, and math symbols that sanitizing would otherwise drop
+const DEFAULT_TRANSLIT_MAP: &[(&str, &str)] = &[
+    ("α", "alpha"),
+    ("β", "beta"),
+    ("γ", "gamma"),
+    ("δ", "delta"),
+    ("ε", "epsilon"),
+    ("ζ", "zeta"),
+    ("η", "eta"),
+    ("θ", "theta"),
+    ("κ", "kappa"),
+    ("λ", "lambda"),
+    ("μ", "mu"),
+    ("ν", "nu"),
+    ("ξ", "xi"),
+    ("π", "pi"),
+    ("ρ", "rho"),
+    ("σ", "sigma"),
+    ("τ", "tau"),
+    ("φ", "phi"),
+    ("χ", "chi"),
+    ("ψ", "psi"),
+    ("ω", "omega"),
+    ("Γ", "Gamma"),
+    ("Δ", "Delta"),
+    ("Θ", "Theta"),
+    ("Λ", "Lambda"),
+    ("Π", "Pi"),
+    ("Σ", "Sigma"),
+    ("Φ", "Phi"),
+    ("Ψ", "Psi"),
+    ("Ω", "Omega"),
+    ("∞", " infinity "),
+    ("√", " sqrt "),
+    ("∑", " sum "),
+    ("∂", " partial "),
+    ("∇", " nabla "),
+    ("≤", " leq "),
+    ("≥", " geq "),
+    ("±", " pm "),
+];
+
+/// The `--translit-map` substitutions: the built-in map, extended and
+/// overridden by the entries of the TOML file at `path` (`"α" = "alpha"`).
+/// Longer keys come first, so a multi-character entry wins over its parts
+pub fn load_translit_map(path: Option<&str>) -> Result<Vec<(String, String)>> {
+    let mut map: BTreeMap<String, String> = DEFAULT_TRANSLIT_MAP
+        .iter()
+        .map(|(from, to)| (from.to_string(), to.to_string()))
+        .collect();
+
+    if let Some(path) = path {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read transliteration map: {}", path))?;
+        let custom: BTreeMap<String, String> =
+            toml::from_str(&contents).with_context(|| format!("Invalid transliteration map in {}", path))?;
+        if let Some((from, _)) = custom.iter().find(|(from, _)| from.is_empty()) {
+            anyhow::bail!("Empty key {:?} in transliteration map {}", from, path);
+        }
+        map.extend(custom);
+    }
+
+    let mut map: Vec<(String, String)> = map.into_iter().collect();
+    map.sort_by_key(|(from, _)| std::cmp::Reverse(from.chars().count()));
+    Ok(map)
+}
+
+/// Parse a `pattern=>replacement` rule given on the command line
+/// A `re:` prefix makes the pattern a regex; otherwise it is matched literally
+pub fn parse_title_replacement(rule: &str) -> Result<TitleReplacement> {
+    let (find, replace) = rule
+        .split_once("=>")
+        .with_context(|| format!("Title replacement must look like 'pattern=>replacement', got '{}'", rule))?;
+
+    if let Some(pattern) = find.strip_prefix("re:") {
+        let find = Regex::new(pattern).with_context(|| format!("Invalid regex in '{}'", rule))?;
+        return Ok(TitleReplacement::Regex {
+            find,
+            replace: replace.to_string(),
+        });
+    }
+
+    if find.is_empty() {
+        anyhow::bail!("Title replacement has an empty pattern: '{}'", rule);
+    }
+    Ok(TitleReplacement::Literal {
+        find: find.to_string(),
+        replace: replace.to_string(),
+    })
+}
+
+impl Default for NamingOptions {
+    fn default() -> Self {
+        Self {
+            format: DEFAULT_FORMAT.to_string(),
+            ascii_author: false,
+            non_latin: NonLatin::Keep,
+            extension: "pdf".to_string(),
+            max_title_length: None,
+            truncation_token: None,
+            drop_single_char_words: false,
+            leading_articles: Vec::new(),
+            title_acronym: false,
+            trim_trailing_numbers: false,
+            max_filename_bytes: DEFAULT_MAX_FILENAME_BYTES,
+            name_tie_break: None,
+            normalize_title_whitespace: false,
+            title_replacements: Vec::new(),
+            title_transliterations: Vec::new(),
+            hash_prefix: None,
+            require_match: None,
+            year_format: "{yyyy}".to_string(),
+        }
+    }
+}
+
+/// A parsed piece of a filename template
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Literal(&'a str),
+    Placeholder(&'a str),
+}
 
 /// Generate a sanitized filename from paper metadata
-/// Format: <first-author-last-name><year><paper-title>.pdf
+/// Format: <first-author-last-name><year><paper-title>.pdf by default,
+/// or whatever template is configured in the naming options
 /// Rules:
 /// - All lowercase
 /// - Dashes (-) instead of spaces
 /// - No special characters
-pub fn generate_filename(metadata: &PaperMetadata) -> String {
-    let author = sanitize(&metadata.first_author);
-    let year = sanitize(&metadata.year);
-    let title = sanitize(&metadata.title);
+pub fn generate_filename(metadata: &PaperMetadata, options: &NamingOptions) -> String {
+    let segments = parse_format(&options.format);
+
+    let author = |value: &str| {
+        if options.ascii_author {
+            sanitize(&transliterate(value))
+        } else {
+            sanitize(value)
+        }
+    };
+
+    let mut title = if options.normalize_title_whitespace {
+        normalize_whitespace(&metadata.title)
+    } else {
+        metadata.title.clone()
+    };
+    for replacement in &options.title_replacements {
+        title = replacement.apply(&title);
+    }
+    for (from, to) in &options.title_transliterations {
+        title = title.replace(from.as_str(), to);
+    }
+
+    let title_slug = if metadata.title_slug.is_empty() {
+        sanitize(&romanize_title(&title, options.non_latin))
+    } else {
+        sanitize(&metadata.title_slug)
+    };
+
+    let mut stem = render(&segments, |name| match name {
+        "author" => author(&metadata.first_author),
+        "first_author_full" => author(&metadata.first_author_full),
+        "year" => sanitize(&format_year(&metadata.year, &options.year_format)),
+        "title" => tidy_title(&title_slug, options),
+        "title_word" => title_word(&title_slug),
+        "version" => sanitize(&metadata.version),
+        "category" => sanitize(&metadata.category.replace('.', "-")),
+        "type" => sanitize(&metadata.doc_type),
+        "publisher" => sanitize(&metadata.publisher),
+        "doi" => sanitize(&doi_suffix(&metadata.doi).replace(|c: char| !c.is_alphanumeric(), "-")),
+        _ => String::new(),
+    });
+
+    // A content hash prefix makes names stable and unique across a library
+    if let Some(len) = options.hash_prefix.filter(|&len| len > 0) {
+        let prefix: String = metadata.content_hash.chars().take(len).collect();
+        if !prefix.is_empty() {
+            stem = if stem.is_empty() { prefix } else { format!("{}-{}", prefix, stem) };
+        }
+    }
+
+    // Shorten over-long names here rather than failing later at rename time
+    let max_stem = options.max_filename_bytes.saturating_sub(options.extension.len() + 1);
+    format!("{}.{}", truncate_stem(&stem, max_stem), options.extension)
+}
 
-    format!("{}-{}-{}.pdf", author, year, title)
+/// The part of a DOI after the registrant prefix, e.g. `3292500.3330701`
+/// for `10.1145/3292500.3330701`; anything else is returned whole
+fn doi_suffix(doi: &str) -> &str {
+    doi.split_once('/').map_or(doi, |(_, suffix)| suffix)
+}
+
+/// Cut a filename stem to at most `max_bytes` bytes, at a dash unless that
+/// would throw away more than half of it
+fn truncate_stem(stem: &str, max_bytes: usize) -> &str {
+    if stem.len() <= max_bytes {
+        return stem;
+    }
+
+    let mut end = max_bytes;
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    match stem[..end].rfind('-') {
+        Some(dash) if dash > end / 2 => &stem[..dash],
+        _ => &stem[..end],
+    }
+}
+
+/// Resolve a preset name given to --format (`citekey`) to its template;
+/// anything else is returned unchanged
+pub fn expand_preset(format: &str) -> String {
+    PRESETS
+        .iter()
+        .find(|(name, _)| *name == format)
+        .map_or(format, |(_, template)| template)
+        .to_string()
+}
+
+/// Whether names from this template are citation keys, which are told apart
+/// with letters (`smith2020a`) rather than numbers
+pub fn is_citekey(format: &str) -> bool {
+    format == CITEKEY_FORMAT
+}
+
+/// Check a final filename against the --require-match pattern, if any
+pub fn check_required_match(filename: &str, options: &NamingOptions) -> Result<()> {
+    match &options.require_match {
+        Some(pattern) if !pattern.is_match(filename) => {
+            anyhow::bail!("{} does not match --require-match '{}'", filename, pattern)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check whether a filename has a PDF extension, in any letter case
+pub fn has_pdf_extension(filename: &str) -> bool {
+    pdf_extension(filename).is_some()
+}
+
+/// The PDF extension of a filename exactly as written (e.g. "PDF" for `Paper.PDF`)
+pub fn pdf_extension(filename: &str) -> Option<&str> {
+    let (stem, extension) = filename.rsplit_once('.')?;
+    (!stem.is_empty() && extension.eq_ignore_ascii_case("pdf")).then_some(extension)
+}
+
+/// Give an edited filename exactly one PDF extension, written as `extension`:
+/// `name`, `name.PDF` and `name.pdf.pdf` all become `name.pdf`
+pub fn normalize_extension(filename: &str, extension: &str) -> String {
+    let mut stem = filename.trim();
+    while let Some(inner) = pdf_extension(stem).and_then(|_| stem.get(..stem.len() - 4)) {
+        stem = inner;
+    }
+    format!("{}.{}", stem, extension)
+}
+
+/// Folder for papers whose first author is unknown, with `--author-subdir`
+pub const UNSORTED_FOLDER: &str = "unsorted";
+
+/// The title length (see `max_title_length`) that keeps a generated name within
+/// `max_path_bytes` once it is put in a folder whose path takes `dir_bytes`
+/// (separator included); None if the name fits as it is
+/// Only the title is shortened; a path that is too long even without one is an error
+pub fn fit_title_to_path(
+    metadata: &PaperMetadata,
+    options: &NamingOptions,
+    dir_bytes: usize,
+    max_path_bytes: usize,
+) -> Result<Option<usize>> {
+    let name = generate_filename(metadata, options);
+    if dir_bytes + name.len() <= max_path_bytes {
+        return Ok(None);
+    }
+
+    // The title can't be longer than the whole name
+    let longest = options.max_title_length.unwrap_or(usize::MAX).min(name.chars().count());
+    for max_title_length in (0..longest).rev() {
+        let shortened = NamingOptions {
+            max_title_length: Some(max_title_length),
+            ..options.clone()
+        };
+        if dir_bytes + generate_filename(metadata, &shortened).len() <= max_path_bytes {
+            return Ok(Some(max_title_length));
+        }
+    }
+    anyhow::bail!(
+        "The folder path is too long: no name fits within the platform's limit of {} bytes",
+        max_path_bytes
+    )
+}
+
+/// Of two titles that say the same at different lengths (one's slug continues the
+/// other's, e.g. after a subtitle colon), the index of the one whose filename the
+/// tie-break prefers; None without a tie-break, for different titles, or when both
+/// give names of the same length
+pub fn break_title_tie(metadata: &PaperMetadata, titles: [&str; 2], options: &NamingOptions) -> Option<usize> {
+    let tie_break = options.name_tie_break?;
+
+    let slugs = titles.map(sanitize);
+    let extends = |long: &str, short: &str| long.strip_prefix(short).is_some_and(|rest| rest.starts_with('-'));
+    if slugs.iter().any(String::is_empty) || !(extends(&slugs[0], &slugs[1]) || extends(&slugs[1], &slugs[0])) {
+        return None;
+    }
+
+    let lengths = titles.map(|title| {
+        let candidate = PaperMetadata {
+            title: title.to_string(),
+            title_slug: String::new(),
+            ..metadata.clone()
+        };
+        generate_filename(&candidate, options).len()
+    });
+    let shorter = match lengths[0].cmp(&lengths[1]) {
+        std::cmp::Ordering::Less => 0,
+        std::cmp::Ordering::Greater => 1,
+        std::cmp::Ordering::Equal => return None,
+    };
+    Some(match tie_break {
+        TieBreak::Shortest => shorter,
+        TieBreak::Complete => 1 - shorter,
+    })
+}
+
+/// Name of the folder a paper is filed under with `--author-subdir`: the first
+/// author's last name, sanitized like the `{author}` placeholder
+pub fn author_folder(metadata: &PaperMetadata, options: &NamingOptions) -> String {
+    let author = if options.ascii_author {
+        sanitize(&transliterate(&metadata.first_author))
+    } else {
+        sanitize(&metadata.first_author)
+    };
+    if author.is_empty() || author == "unknown" {
+        UNSORTED_FOLDER.to_string()
+    } else {
+        author
+    }
+}
+
+/// Check that a filename template only uses known placeholders
+/// and contains at least one of them
+pub fn validate_format(format: &str) -> Result<()> {
+    let segments = parse_format(format);
+
+    let mut has_placeholder = false;
+    for segment in &segments {
+        if let Segment::Placeholder(name) = segment {
+            if !PLACEHOLDERS.contains(name) {
+                anyhow::bail!(
+                    "Unknown placeholder '{{{}}}' in format. Available placeholders: {}",
+                    name,
+                    PLACEHOLDERS
+                        .iter()
+                        .map(|p| format!("{{{}}}", p))
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                );
+            }
+            has_placeholder = true;
+        }
+    }
+
+    if !has_placeholder {
+        anyhow::bail!(
+            "Format must contain at least one placeholder, e.g. {}, or be a preset: {}",
+            DEFAULT_FORMAT,
+            PRESETS.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/// Recover placeholder values from a file that is already (partly) named
+/// after the template, e.g. `2020-deep-learning.pdf` under the default
+/// format gives year "2020" and title "deep-learning" but no author.
+/// Every placeholder and separator is optional, mirroring how `render`
+/// collapses empty placeholders.
+pub fn parse_existing(filename: &str, format: &str) -> BTreeMap<String, String> {
+    let stem = match pdf_extension(filename) {
+        Some(extension) => &filename[..filename.len() - extension.len() - 1],
+        None => filename,
+    };
+
+    let mut pattern = String::from("^");
+    let mut seen = Vec::new();
+    for segment in parse_format(format) {
+        match segment {
+            Segment::Literal(text) => pattern.push_str(&format!("(?:{})?", regex::escape(text))),
+            Segment::Placeholder(name) => {
+                let value = match name {
+                    "author" => r"\p{L}[\p{L}']*",
+                    "first_author_full" => r"\p{L}[\p{L}']*-\p{L}[\p{L}']*",
+                    "year" => r"\d{4}",
+                    "version" => r"v\d+",
+                    "category" => r"[a-z]+(?:-[a-z]+){0,2}",
+                    "type" => r"journal|conference|preprint|thesis",
+                    _ => r"[\p{L}\p{N}][\p{L}\p{N}-]*?",
+                };
+                // Only the first occurrence of a placeholder is captured
+                if seen.contains(&name) {
+                    pattern.push_str(&format!("(?:{})?", value));
+                } else {
+                    pattern.push_str(&format!("(?:(?P<{}>{}))?", name, value));
+                    seen.push(name);
+                }
+            }
+        }
+    }
+    pattern.push('$');
+
+    let Ok(regex) = Regex::new(&pattern) else {
+        return BTreeMap::new();
+    };
+    let Some(captures) = regex.captures(stem) else {
+        return BTreeMap::new();
+    };
+
+    seen.iter()
+        .filter_map(|name| Some((name.to_string(), captures.name(name)?.as_str().to_string())))
+        .collect()
+}
+
+/// Placeholders of a template that have no value in `known`
+pub fn missing_fields(format: &str, known: &BTreeMap<String, String>) -> Vec<String> {
+    let mut missing = Vec::new();
+    for segment in parse_format(format) {
+        if let Segment::Placeholder(name) = segment {
+            if !known.contains_key(name) && !missing.iter().any(|m| m == name) {
+                missing.push(name.to_string());
+            }
+        }
+    }
+    missing
+}
+
+/// Keep values recovered from an existing filename over extracted ones
+/// With `keep_title_case`, an extracted title stays as the display title and the
+/// recovered one, lowercased by sanitizing, is only used in the filename
+pub fn apply_existing(metadata: &mut PaperMetadata, known: &BTreeMap<String, String>, keep_title_case: bool) {
+    for (name, value) in known {
+        match name.as_str() {
+            "title" if keep_title_case && !metadata.title.trim().is_empty() => metadata.title_slug = value.clone(),
+            "author" => metadata.first_author = value.clone(),
+            "first_author_full" => metadata.first_author_full = value.clone(),
+            "year" => metadata.year = value.clone(),
+            "title" => metadata.title = value.clone(),
+            "version" => metadata.version = value.clone(),
+            "category" => metadata.category = value.clone(),
+            "type" => metadata.doc_type = value.clone(),
+            "publisher" => metadata.publisher = value.clone(),
+            "doi" => metadata.doi = value.clone(),
+            _ => {}
+        }
+    }
+}
+
+/// Split a template into literal text and `{placeholder}` segments
+/// An unterminated `{` is treated as literal text
+fn parse_format(format: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = format;
+
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+
+        if start > 0 {
+            segments.push(Segment::Literal(&rest[..start]));
+        }
+        segments.push(Segment::Placeholder(&rest[start + 1..start + len]));
+        rest = &rest[start + len + 1..];
+    }
+
+    if !rest.is_empty() {
+        segments.push(Segment::Literal(rest));
+    }
+
+    segments
+}
+
+/// Render parsed segments, collapsing empty placeholders cleanly:
+/// the separator before an empty placeholder is dropped, or the one
+/// after it if nothing has been written yet
+fn render<F>(segments: &[Segment], value: F) -> String
+where
+    F: Fn(&str) -> String,
+{
+    let mut output = String::new();
+    let mut pending = String::new();
+    let mut written_placeholder = false;
+    let mut skip_next_literal = false;
+
+    for segment in segments {
+        match segment {
+            Segment::Literal(text) => {
+                if skip_next_literal {
+                    skip_next_literal = false;
+                } else {
+                    pending.push_str(text);
+                }
+            }
+            Segment::Placeholder(name) => {
+                let rendered = value(name);
+                if rendered.is_empty() {
+                    if written_placeholder {
+                        pending.clear();
+                    } else {
+                        skip_next_literal = true;
+                    }
+                    continue;
+                }
+
+                output.push_str(&pending);
+                output.push_str(&rendered);
+                pending.clear();
+                written_placeholder = true;
+                skip_next_literal = false;
+            }
+        }
+    }
+
+    output.push_str(&pending);
+    output
 }
 
 /// Sanitize a string according to the naming convention:
+/// - Compose accents with their letters (NFC), so decomposed text (NFD) gives
+///   the same result instead of losing its accents as non-alphanumeric marks
 /// - Convert to lowercase
 /// - Replace spaces with dashes
 /// - Remove special characters (keep only alphanumeric and dashes)
 /// - Remove multiple consecutive dashes
 /// - Trim leading/trailing dashes
-fn sanitize(s: &str) -> String {
-    s.to_lowercase()
+pub fn sanitize(s: &str) -> String {
+    s.nfc()
+        .collect::<String>()
+        .to_lowercase()
         // Replace spaces and underscores with dashes
-        .replace(' ', "-")
-        .replace('_', "-")
+        .replace([' ', '_'], "-")
         // Remove all characters except alphanumeric and dashes
         .chars()
         .filter(|c| c.is_alphanumeric() || *c == '-')
@@ -36,19 +701,313 @@ fn sanitize(s: &str) -> String {
         .join("-")
 }
 
-/// Validate that a filename is safe and doesn't contain path traversal attempts
-pub fn validate_filename(filename: &str) -> bool {
-    !filename.contains("..")
+/// Undo line-break artifacts from PDF extraction in a title
+/// A word split as "Neu- ral" is rejoined, but only when a lowercase letter
+/// precedes the hyphen and the next word starts lowercase, so compounds like
+/// "Self-Attention" or "GPT- 4" are left alone. Runs of whitespace become one space.
+fn normalize_whitespace(title: &str) -> String {
+    let line_break_hyphen = Regex::new(r"(\p{Ll})-\s+(\p{Ll})").unwrap();
+    let joined = line_break_hyphen.replace_all(title, "$1$2");
+    joined.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Apply the optional slug quality controls to a sanitized title
+/// A control that would remove every word is skipped, so the title never
+/// disappears entirely
+fn tidy_title(slug: &str, options: &NamingOptions) -> String {
+    let mut words: Vec<&str> = slug.split('-').filter(|w| !w.is_empty()).collect();
+
+    // Only one article goes, and only before at least two more words, so a
+    // short title such as "The End" keeps its meaning
+    if words.len() > 2 && options.leading_articles.iter().any(|article| article == words[0]) {
+        words.remove(0);
+    }
+
+    if options.drop_single_char_words && words.iter().any(|w| w.chars().count() > 1) {
+        words.retain(|w| w.chars().count() > 1);
+    }
+
+    if options.trim_trailing_numbers {
+        while words.len() > 1 && words.last().is_some_and(|w| w.chars().all(|c| c.is_numeric())) {
+            words.pop();
+        }
+    }
+
+    let mut title = match options.title_acronym.then(|| acronym(&words)).flatten() {
+        Some(acronym) => acronym,
+        None => words.join("-"),
+    };
+
+    if let Some(max) = options.max_title_length {
+        if title.chars().count() > max {
+            // The truncation token and its dash count towards the limit
+            let reserved = options.truncation_token.as_ref().map_or(0, |token| token.chars().count() + 1);
+            let budget = max.saturating_sub(reserved);
+
+            // Cut at the last word boundary that fits, or mid-word if the
+            // first word alone is too long
+            let cut: String = title.chars().take(budget).collect();
+            title = match cut.rfind('-') {
+                Some(end) if title.chars().nth(budget) != Some('-') => cut[..end].to_string(),
+                _ => cut.trim_end_matches('-').to_string(),
+            };
+            if let Some(token) = &options.truncation_token {
+                title = if title.is_empty() {
+                    token.chars().take(max).collect()
+                } else {
+                    format!("{}-{}", title, token)
+                };
+            }
+        }
+    }
+
+    title
+}
+
+/// Default articles for --strip-leading-articles
+pub const DEFAULT_LEADING_ARTICLES: &str = "a,an,the";
+
+/// Parse the comma-separated articles of --strip-leading-articles, compared
+/// against the title slug, so they are sanitized the same way
+pub fn parse_leading_articles(list: &str) -> Result<Vec<String>> {
+    let articles: Vec<String> = list.split(',').map(sanitize).filter(|article| !article.is_empty()).collect();
+    if articles.is_empty() {
+        anyhow::bail!("No articles to strip in '{}'", list);
+    }
+    Ok(articles)
+}
+
+/// Check a --title-word-limit-with-ellipsis-token marker and turn it into a slug
+/// word, so it joins the title with a single dash like any other word
+pub fn parse_truncation_token(token: &str) -> Result<String> {
+    let slug = sanitize(token);
+    if slug.is_empty() {
+        anyhow::bail!("Truncation token needs at least one letter or digit, got '{}'", token);
+    }
+    Ok(slug)
+}
+
+/// Turn a --year-format value into a year pattern: `full` is `{yyyy}`,
+/// `short` is `{yy}`, and anything else must be a pattern using one of them
+/// (e.g. `ad{yyyy}`)
+pub fn parse_year_format(format: &str) -> Result<String> {
+    let pattern = match format.trim() {
+        "full" => "{yyyy}",
+        "short" => "{yy}",
+        pattern => pattern,
+    };
+    if !pattern.contains("{yyyy}") && !pattern.contains("{yy}") {
+        anyhow::bail!(
+            "Year format must be full, short or a pattern containing {{yyyy}} or {{yy}}, got '{}'",
+            format
+        );
+    }
+    Ok(pattern.to_string())
+}
+
+/// Write a year with a --year-format pattern
+/// Only a 4-digit year is reformatted: anything else (an empty year, "n.d.",
+/// a placeholder) is kept as it is, so shortening can never leave it empty
+/// or turn it into digits that were never there
+fn format_year(year: &str, pattern: &str) -> String {
+    let year = year.trim();
+    if year.len() != 4 || !year.chars().all(|c| c.is_ascii_digit()) {
+        return year.to_string();
+    }
+    pattern.replace("{yyyy}", year).replace("{yy}", &year[2..])
+}
+
+/// The first significant word of a title slug, e.g. `attention` for
+/// `the-attention-mechanism`; the first word if every word is a stopword
+fn title_word(slug: &str) -> String {
+    let words: Vec<&str> = slug.split('-').filter(|word| !word.is_empty()).collect();
+    words
+        .iter()
+        .find(|word| !STOPWORDS.contains(word))
+        .or(words.first())
+        .map(|word| word.to_string())
+        .unwrap_or_default()
+}
+
+/// The first letter of each significant word of a title slug, e.g. `aiayn`
+/// for `attention-is-all-you-need`; None if the title is too short to abbreviate
+fn acronym(words: &[&str]) -> Option<String> {
+    let significant: Vec<&str> = words
+        .iter()
+        .copied()
+        .filter(|word| !STOPWORDS.contains(word))
+        .collect();
+    if significant.len() < ACRONYM_MIN_WORDS {
+        return None;
+    }
+    Some(significant.iter().filter_map(|word| word.chars().next()).collect())
+}
+
+/// Detect the script a title is predominantly written in
+/// Only letters are counted, so digits and punctuation don't sway the result
+pub fn detect_script(text: &str) -> Script {
+    let (mut latin, mut cjk, mut arabic, mut cyrillic) = (0, 0, 0, 0);
+
+    for c in text.chars().filter(|c| c.is_alphabetic()) {
+        match c as u32 {
+            0x0400..=0x052F => cyrillic += 1,
+            0x0600..=0x06FF | 0x0750..=0x077F | 0x08A0..=0x08FF | 0xFB50..=0xFDFF | 0xFE70..=0xFEFF => {
+                arabic += 1
+            }
+            0x1100..=0x11FF
+            | 0x3040..=0x30FF
+            | 0x3400..=0x4DBF
+            | 0x4E00..=0x9FFF
+            | 0xAC00..=0xD7AF
+            | 0xF900..=0xFAFF => cjk += 1,
+            _ => latin += 1,
+        }
+    }
+
+    let counts = [
+        (Script::Latin, latin),
+        (Script::Cjk, cjk),
+        (Script::Arabic, arabic),
+        (Script::Cyrillic, cyrillic),
+    ];
+
+    counts
+        .iter()
+        .filter(|(_, count)| *count > 0)
+        .max_by_key(|(_, count)| *count)
+        .map(|(script, _)| *script)
+        .unwrap_or(Script::Latin)
+}
+
+/// Romanize a non-Latin title according to the chosen mode
+/// Latin-script titles are returned unchanged so their accents survive
+fn romanize_title(title: &str, mode: NonLatin) -> String {
+    if detect_script(title) == Script::Latin {
+        return title.to_string();
+    }
+
+    match transliterator(mode) {
+        Some(transliterate) => transliterate(title),
+        None => title.to_string(),
+    }
+}
+
+/// Pick the transliteration function for a mode, if any
+fn transliterator(mode: NonLatin) -> Option<Transliterator> {
+    match mode {
+        NonLatin::Keep => None,
+        NonLatin::Translit => Some(translit_words),
+        NonLatin::Romaji => Some(romaji_kana),
+    }
+}
+
+/// Romanize every non-ASCII word, keeping word boundaries
+/// Each CJK character becomes its own syllable (e.g. "深度学习" -> "shen du xue xi")
+fn translit_words(text: &str) -> String {
+    text.chars()
+        .map(|c| {
+            if c.is_ascii() {
+                c.to_string()
+            } else {
+                let romanized = any_ascii::any_ascii_char(c);
+                if detect_script(&c.to_string()) == Script::Cjk {
+                    format!(" {} ", romanized)
+                } else {
+                    romanized.to_string()
+                }
+            }
+        })
+        .collect::<String>()
+        .to_lowercase()
+}
+
+/// Romanize Hiragana and Katakana, leaving kanji and other scripts as they are
+/// Small ya/yu/yo merge with the preceding syllable (ニュ -> "nyu")
+fn romaji_kana(text: &str) -> String {
+    let mut output = String::new();
+
+    for c in text.chars() {
+        match c {
+            'ゃ' | 'ゅ' | 'ょ' | 'ャ' | 'ュ' | 'ョ' => {
+                let romanized = any_ascii::any_ascii_char(c).to_lowercase();
+                if output.ends_with('i') {
+                    output.pop();
+                }
+                // シャ is "sha", not "shya"
+                if output.ends_with("sh") || output.ends_with("ch") || output.ends_with('j') {
+                    output.push_str(&romanized[1..]);
+                } else {
+                    output.push_str(&romanized);
+                }
+            }
+            '\u{3040}'..='\u{30FF}' => output.push_str(&any_ascii::any_ascii_char(c).to_lowercase()),
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Transliterate a string to ASCII by stripping diacritics
+/// ("Müller" becomes "Muller"). Letters without a decomposition are mapped
+/// to their common ASCII spelling; anything else is left for `sanitize`
+fn transliterate(s: &str) -> String {
+    let mut output = String::with_capacity(s.len());
+
+    for c in s.nfd().filter(|c| !is_combining_mark(*c)) {
+        match c {
+            'ß' => output.push_str("ss"),
+            'æ' => output.push_str("ae"),
+            'Æ' => output.push_str("AE"),
+            'œ' => output.push_str("oe"),
+            'Œ' => output.push_str("OE"),
+            'ø' => output.push('o'),
+            'Ø' => output.push('O'),
+            'ł' => output.push('l'),
+            'Ł' => output.push('L'),
+            'đ' => output.push('d'),
+            'Đ' => output.push('D'),
+            'ı' => output.push('i'),
+            _ => output.push(c),
+        }
+    }
+
+    output
+}
+
+/// Validate that a filename is safe and doesn't contain path traversal attempts,
+/// and that it fits within the filesystem's filename length limit
+pub fn validate_filename(filename: &str, max_bytes: usize) -> bool {
+    filename.len() <= max_bytes
+        && !filename.contains("..")
         && !filename.contains('/')
         && !filename.contains('\\')
         && !filename.is_empty()
-        && filename.ends_with(".pdf")
+        && has_pdf_extension(filename)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn vaswani() -> PaperMetadata {
+        PaperMetadata {
+            first_author: "Vaswani".to_string(),
+            first_author_full: "Ashish Vaswani".to_string(),
+            year: "2017".to_string(),
+            title: "Attention Is All You Need".to_string(),
+            version: String::new(),
+            ..PaperMetadata::default()
+        }
+    }
+
+    fn with_format(format: &str) -> NamingOptions {
+        NamingOptions {
+            format: format.to_string(),
+            ..NamingOptions::default()
+        }
+    }
+
     #[test]
     fn test_sanitize() {
         assert_eq!(sanitize("Hello World"), "hello-world");
@@ -58,24 +1017,661 @@ mod tests {
         assert_eq!(sanitize("Vaswani"), "vaswani");
     }
 
+    #[test]
+    fn test_sanitize_normalization_forms() {
+        // "Café Müller" precomposed (NFC) and decomposed (NFD)
+        let nfc = "Caf\u{e9} M\u{fc}ller";
+        let nfd = "Cafe\u{301} Mu\u{308}ller";
+        assert_eq!(sanitize(nfc), "café-müller");
+        assert_eq!(sanitize(nfd), "café-müller");
+    }
+
     #[test]
     fn test_generate_filename() {
+        let filename = generate_filename(&vaswani(), &NamingOptions::default());
+        assert_eq!(filename, "vaswani-2017-attention-is-all-you-need.pdf");
+    }
+
+    #[test]
+    fn test_generate_filename_first_author_full() {
+        let options = with_format("{first_author_full}-{year}-{title}");
+        assert_eq!(
+            generate_filename(&vaswani(), &options),
+            "ashish-vaswani-2017-attention-is-all-you-need.pdf"
+        );
+
+        // {author} keeps producing the last name only
+        let options = with_format("{author}_{year}");
+        assert_eq!(generate_filename(&vaswani(), &options), "vaswani_2017.pdf");
+    }
+
+    #[test]
+    fn test_generate_filename_collapses_empty_placeholders() {
+        let mut metadata = vaswani();
+        metadata.first_author_full = String::new();
+
+        let options = with_format("{year}-{first_author_full}-{title}");
+        assert_eq!(
+            generate_filename(&metadata, &options),
+            "2017-attention-is-all-you-need.pdf"
+        );
+
+        let options = with_format("{first_author_full}-{year}");
+        assert_eq!(generate_filename(&metadata, &options), "2017.pdf");
+    }
+
+    #[test]
+    fn test_generate_filename_version() {
+        let options = with_format("{author}-{year}-{title}-{version}");
+        let mut metadata = vaswani();
+
+        // No version detected: the trailing separator disappears with it
+        assert_eq!(
+            generate_filename(&metadata, &options),
+            "vaswani-2017-attention-is-all-you-need.pdf"
+        );
+
+        metadata.version = "v2".to_string();
+        assert_eq!(
+            generate_filename(&metadata, &options),
+            "vaswani-2017-attention-is-all-you-need-v2.pdf"
+        );
+    }
+
+    #[test]
+    fn test_parse_title_replacement() {
+        assert!(matches!(
+            parse_title_replacement("(Extended Version)=>").unwrap(),
+            TitleReplacement::Literal { ref find, ref replace } if find == "(Extended Version)" && replace.is_empty()
+        ));
+        assert!(matches!(
+            parse_title_replacement(r"re:\s*\(.*\)$=>").unwrap(),
+            TitleReplacement::Regex { .. }
+        ));
+        assert!(parse_title_replacement("no arrow").is_err());
+        assert!(parse_title_replacement("=>x").is_err());
+        assert!(parse_title_replacement("re:(unclosed=>").is_err());
+    }
+
+    #[test]
+    fn test_generate_filename_title_replacements() {
+        let rules = ["(Extended Version)=>", r"re:\s*[—-]\s*Preprint$=>", "Transformers=>Transformer", "Transformer=>TF"];
+        let options = NamingOptions {
+            title_replacements: rules.iter().map(|rule| parse_title_replacement(rule).unwrap()).collect(),
+            ..NamingOptions::default()
+        };
+
+        let mut metadata = vaswani();
+        metadata.title = "Efficient Transformers (Extended Version) — Preprint".to_string();
+
+        // Applied in order: the third rule's output is rewritten by the fourth
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017-efficient-tf.pdf");
+    }
+
+    #[test]
+    fn test_translit_map() {
+        let options = NamingOptions {
+            title_transliterations: load_translit_map(None).unwrap(),
+            ..NamingOptions::default()
+        };
+        let mut metadata = vaswani();
+        metadata.title = "α-Divergence Variational Inference".to_string();
+        assert_eq!(
+            generate_filename(&metadata, &options),
+            "vaswani-2017-alpha-divergence-variational-inference.pdf"
+        );
+        metadata.title = "β-VAE and Δ-Learning in O(√n)".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017-beta-vae-and-delta-learning-in-o-sqrt-n.pdf");
+
+        // A user map adds entries and overrides built-in ones, longest first
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("translit.toml");
+        std::fs::write(&path, "\"β\" = \"b\"\n\"ℏω\" = \"phonon\"\n").unwrap();
+        let map = load_translit_map(path.to_str()).unwrap();
+        assert_eq!(map[0], ("ℏω".to_string(), "phonon".to_string()));
+        assert!(map.contains(&("β".to_string(), "b".to_string())));
+        assert!(map.contains(&("α".to_string(), "alpha".to_string())));
+
+        std::fs::write(&path, "\"\" = \"x\"\n").unwrap();
+        assert!(load_translit_map(path.to_str()).is_err());
+    }
+
+    #[test]
+    fn test_generate_filename_category() {
+        let options = with_format("{category}-{author}-{year}");
+        let mut metadata = vaswani();
+
+        // Not an arXiv paper: the leading separator disappears with it
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017.pdf");
+
+        metadata.category = "cs.CL".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "cs-cl-vaswani-2017.pdf");
+    }
+
+    #[test]
+    fn test_generate_filename_type() {
+        let options = with_format("{author}-{year}-{type}");
+        let mut metadata = vaswani();
+
+        // Undetermined: the trailing separator disappears with it
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017.pdf");
+
+        metadata.doc_type = "conference".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017-conference.pdf");
+    }
+
+    #[test]
+    fn test_generate_filename_publisher() {
+        let options = with_format("{publisher}-{author}-{year}");
+        let mut metadata = vaswani();
+
+        // Unknown publisher: the leading separator disappears with it
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017.pdf");
+
+        metadata.publisher = "Taylor & Francis".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "taylor-francis-vaswani-2017.pdf");
+    }
+
+    #[test]
+    fn test_generate_filename_doi() {
+        let options = with_format("{author}-{year}-{doi}");
+        let mut metadata = vaswani();
+
+        // No DOI on the page: the trailing separator disappears with it
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017.pdf");
+
+        metadata.doi = "10.1145/3292500.3330701".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017-3292500-3330701.pdf");
+
+        metadata.doi = "10.1016/j.cell.2020.01.001".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017-j-cell-2020-01-001.pdf");
+    }
+
+    #[test]
+    fn test_transliterate() {
+        assert_eq!(transliterate("Müller"), "Muller");
+        assert_eq!(transliterate("Gödel Łukasiewicz"), "Godel Lukasiewicz");
+        assert_eq!(transliterate("Straße"), "Strasse");
+    }
+
+    #[test]
+    fn test_generate_filename_ascii_author() {
         let metadata = PaperMetadata {
-            first_author: "Vaswani".to_string(),
-            year: "2017".to_string(),
-            title: "Attention Is All You Need".to_string(),
+            first_author: "Müller".to_string(),
+            first_author_full: "Jörg Müller".to_string(),
+            year: "2020".to_string(),
+            title: "Über etwas".to_string(),
+            version: String::new(),
+            ..PaperMetadata::default()
         };
 
-        let filename = generate_filename(&metadata);
-        assert_eq!(filename, "vaswani-2017-attention-is-all-you-need.pdf");
+        let options = NamingOptions::default();
+        assert_eq!(generate_filename(&metadata, &options), "müller-2020-über-etwas.pdf");
+
+        let options = NamingOptions {
+            ascii_author: true,
+            ..NamingOptions::default()
+        };
+        assert_eq!(generate_filename(&metadata, &options), "muller-2020-über-etwas.pdf");
+
+        let options = NamingOptions {
+            format: "{first_author_full}-{title}".to_string(),
+            ascii_author: true,
+            ..NamingOptions::default()
+        };
+        assert_eq!(generate_filename(&metadata, &options), "jorg-muller-über-etwas.pdf");
+    }
+
+    #[test]
+    fn test_detect_script() {
+        assert_eq!(detect_script("Attention Is All You Need"), Script::Latin);
+        assert_eq!(detect_script("深度学习"), Script::Cjk);
+        assert_eq!(detect_script("ニューラルネットワーク"), Script::Cjk);
+        assert_eq!(detect_script("Глубокое обучение"), Script::Cyrillic);
+        assert_eq!(detect_script("التعلم العميق"), Script::Arabic);
+        assert_eq!(detect_script("2020"), Script::Latin);
+    }
+
+    #[test]
+    fn test_generate_filename_non_latin() {
+        let metadata = PaperMetadata {
+            first_author: "Li".to_string(),
+            first_author_full: String::new(),
+            year: "2020".to_string(),
+            title: "深度学习".to_string(),
+            version: String::new(),
+            ..PaperMetadata::default()
+        };
+
+        let keep = NamingOptions::default();
+        assert_eq!(generate_filename(&metadata, &keep), "li-2020-深度学习.pdf");
+
+        let translit = NamingOptions {
+            non_latin: NonLatin::Translit,
+            ..NamingOptions::default()
+        };
+        assert_eq!(generate_filename(&metadata, &translit), "li-2020-shen-du-xue-xi.pdf");
+
+        let russian = PaperMetadata {
+            title: "Глубокое обучение".to_string(),
+            ..metadata
+        };
+        assert_eq!(
+            generate_filename(&russian, &translit),
+            "li-2020-glubokoe-obuchenie.pdf"
+        );
+    }
+
+    #[test]
+    fn test_romaji_keeps_kanji() {
+        assert_eq!(romaji_kana("ニューラル"), "nyuraru");
+        assert_eq!(romaji_kana("しゃしん"), "shashin");
+        assert_eq!(romaji_kana("深層"), "深層");
+    }
+
+    #[test]
+    fn test_validate_format() {
+        assert!(validate_format(DEFAULT_FORMAT).is_ok());
+        assert!(validate_format("{first_author_full}_{year}").is_ok());
+        assert!(validate_format("{journal}-{year}").is_err());
+        assert!(validate_format("no-placeholders").is_err());
     }
 
     #[test]
     fn test_validate_filename() {
-        assert!(validate_filename("valid-filename.pdf"));
-        assert!(!validate_filename("../etc/passwd.pdf"));
-        assert!(!validate_filename("path/to/file.pdf"));
-        assert!(!validate_filename(""));
-        assert!(!validate_filename("no-extension"));
+        let max = DEFAULT_MAX_FILENAME_BYTES;
+        assert!(validate_filename("valid-filename.pdf", max));
+        assert!(!validate_filename("../etc/passwd.pdf", max));
+        assert!(!validate_filename("path/to/file.pdf", max));
+        assert!(!validate_filename("", max));
+        assert!(!validate_filename("no-extension", max));
+        assert!(validate_filename("Upper-Case.PDF", max));
+        assert!(!validate_filename(&format!("{}.pdf", "a".repeat(252)), max));
+    }
+
+    #[test]
+    fn test_generate_filename_fits_length_limit() {
+        let mut metadata = vaswani();
+        metadata.title = "Towards a Comprehensive Understanding of Everything ".repeat(8);
+        assert!(metadata.title.len() > 300);
+
+        let name = generate_filename(&metadata, &NamingOptions::default());
+        assert!(name.len() <= DEFAULT_MAX_FILENAME_BYTES);
+        // Cut at a word boundary, keeping the extension
+        assert!(name.ends_with(".pdf"));
+        assert!(!name.contains("-.pdf"));
+        assert!(validate_filename(&name, DEFAULT_MAX_FILENAME_BYTES));
+
+        // Multi-byte characters are never split
+        metadata.title = "über".repeat(100);
+        let name = generate_filename(&metadata, &NamingOptions::default());
+        assert!(name.len() <= DEFAULT_MAX_FILENAME_BYTES);
+        assert!(name.starts_with("vaswani-2017-über"));
+
+        let options = NamingOptions {
+            max_filename_bytes: 20,
+            ..NamingOptions::default()
+        };
+        assert_eq!(generate_filename(&vaswani(), &options), "vaswani-2017.pdf");
+    }
+
+    #[test]
+    fn test_pdf_extension() {
+        assert!(has_pdf_extension("paper.pdf"));
+        assert!(has_pdf_extension("Paper.PDF"));
+        assert!(has_pdf_extension("Paper.Pdf"));
+        assert!(!has_pdf_extension("paper.pdf.part"));
+        assert!(!has_pdf_extension(".pdf"));
+        assert_eq!(pdf_extension("Paper.PDF"), Some("PDF"));
+        assert_eq!(pdf_extension("notes.txt"), None);
+    }
+
+    #[test]
+    fn test_normalize_extension() {
+        assert_eq!(normalize_extension("name", "pdf"), "name.pdf");
+        assert_eq!(normalize_extension("name.PDF", "pdf"), "name.pdf");
+        assert_eq!(normalize_extension("name.pdf.pdf", "pdf"), "name.pdf");
+        assert_eq!(normalize_extension(" name.Pdf.PDF ", "pdf"), "name.pdf");
+        // Other dots are part of the name
+        assert_eq!(normalize_extension("smith-v1.2", "pdf"), "smith-v1.2.pdf");
+        // With --keep-extension-case the original spelling is the one to use
+        assert_eq!(normalize_extension("name.pdf", "PDF"), "name.PDF");
+    }
+
+    #[test]
+    fn test_author_folder() {
+        let options = NamingOptions::default();
+        assert_eq!(author_folder(&vaswani(), &options), "vaswani");
+
+        let author = |name: &str| PaperMetadata {
+            first_author: name.to_string(),
+            ..vaswani()
+        };
+        assert_eq!(author_folder(&author("van der Berg"), &options), "van-der-berg");
+        assert_eq!(author_folder(&author(""), &options), "unsorted");
+        assert_eq!(author_folder(&author("Unknown"), &options), "unsorted");
+    }
+
+    #[test]
+    fn test_generate_filename_extension() {
+        let options = NamingOptions {
+            extension: "PDF".to_string(),
+            ..NamingOptions::default()
+        };
+        assert_eq!(
+            generate_filename(&vaswani(), &options),
+            "vaswani-2017-attention-is-all-you-need.PDF"
+        );
+    }
+
+    #[test]
+    fn test_normalize_whitespace() {
+        assert_eq!(normalize_whitespace("Neu- ral Net-\nworks"), "Neural Networks");
+        assert_eq!(normalize_whitespace("Deep   Learning\n for\tVision"), "Deep Learning for Vision");
+        // Genuine compounds and capitalized continuations are kept
+        assert_eq!(normalize_whitespace("State-of-the-art Self- Attention"), "State-of-the-art Self- Attention");
+        assert_eq!(normalize_whitespace("GPT- 4"), "GPT- 4");
+
+        let mut metadata = vaswani();
+        metadata.title = "Convolu- tional Neu- ral Net- works".to_string();
+        let options = NamingOptions {
+            normalize_title_whitespace: true,
+            ..NamingOptions::default()
+        };
+        assert_eq!(
+            generate_filename(&metadata, &options),
+            "vaswani-2017-convolutional-neural-networks.pdf"
+        );
+        assert_eq!(
+            generate_filename(&metadata, &NamingOptions::default()),
+            "vaswani-2017-convolu-tional-neu-ral-net-works.pdf"
+        );
+    }
+
+    #[test]
+    fn test_parse_existing() {
+        let known = parse_existing("2020-deep-learning.pdf", DEFAULT_FORMAT);
+        assert_eq!(known.get("author"), None);
+        assert_eq!(known.get("year").map(String::as_str), Some("2020"));
+        assert_eq!(known.get("title").map(String::as_str), Some("deep-learning"));
+        assert_eq!(missing_fields(DEFAULT_FORMAT, &known), ["author"]);
+
+        let known = parse_existing("vaswani-2017.PDF", DEFAULT_FORMAT);
+        assert_eq!(known.get("author").map(String::as_str), Some("vaswani"));
+        assert_eq!(known.get("year").map(String::as_str), Some("2017"));
+        assert_eq!(missing_fields(DEFAULT_FORMAT, &known), ["title"]);
+
+        let known = parse_existing("attention-is-all-you-need-2017.pdf", "{title}-{year}");
+        assert_eq!(known.get("title").map(String::as_str), Some("attention-is-all-you-need"));
+        assert!(missing_fields("{title}-{year}", &known).is_empty());
+
+        // A name that doesn't follow the template yields nothing
+        assert!(parse_existing("scan 01.pdf", "{year}_{title}").is_empty());
+    }
+
+    #[test]
+    fn test_apply_existing_keeps_filename_parts() {
+        let mut metadata = vaswani();
+        metadata.title = "Attention Is All You Need (Extended)".to_string();
+        let existing = parse_existing("2017-attention-is-all-you-need.pdf", DEFAULT_FORMAT);
+        apply_existing(&mut metadata, &existing, false);
+        assert_eq!(
+            generate_filename(&metadata, &NamingOptions::default()),
+            "vaswani-2017-attention-is-all-you-need.pdf"
+        );
+        assert_eq!(metadata.title, "attention-is-all-you-need");
+
+        // The filename is the same, but the title keeps its casing
+        let mut metadata = vaswani();
+        metadata.title = "Attention Is All You Need (Extended)".to_string();
+        apply_existing(&mut metadata, &existing, true);
+        assert_eq!(
+            generate_filename(&metadata, &NamingOptions::default()),
+            "vaswani-2017-attention-is-all-you-need.pdf"
+        );
+        assert_eq!(metadata.title, "Attention Is All You Need (Extended)");
+    }
+
+    #[test]
+    fn test_max_title_length() {
+        let options = |max| NamingOptions {
+            format: "{title}".to_string(),
+            max_title_length: Some(max),
+            ..NamingOptions::default()
+        };
+
+        // Cut at a word boundary rather than mid-word
+        assert_eq!(generate_filename(&vaswani(), &options(15)), "attention-is.pdf");
+        // An exact fit keeps the last word
+        assert_eq!(generate_filename(&vaswani(), &options(16)), "attention-is-all.pdf");
+        // A first word longer than the limit is cut mid-word
+        assert_eq!(generate_filename(&vaswani(), &options(5)), "atten.pdf");
+        assert_eq!(
+            generate_filename(&vaswani(), &options(100)),
+            "attention-is-all-you-need.pdf"
+        );
+    }
+
+    #[test]
+    fn test_break_title_tie() {
+        let titles = ["Attention Is All You Need", "Attention Is All You Need: Transformers for Translation"];
+        let options = |tie_break| NamingOptions {
+            name_tie_break: tie_break,
+            ..NamingOptions::default()
+        };
+
+        assert_eq!(break_title_tie(&vaswani(), titles, &options(None)), None);
+        assert_eq!(break_title_tie(&vaswani(), titles, &options(Some(TieBreak::Shortest))), Some(0));
+        assert_eq!(break_title_tie(&vaswani(), titles, &options(Some(TieBreak::Complete))), Some(1));
+
+        // Titles that differ in content are left to the source precedence
+        let different = ["Attention Is All You Need", "Attention Networks"];
+        assert_eq!(break_title_tie(&vaswani(), different, &options(Some(TieBreak::Shortest))), None);
+        // "Attention" isn't the same title as "Attentional Models"
+        let prefix = ["Attention", "Attentional Models"];
+        assert_eq!(break_title_tie(&vaswani(), prefix, &options(Some(TieBreak::Shortest))), None);
+
+        // A title cut to the same length either way leaves nothing to break
+        let cut = NamingOptions {
+            max_title_length: Some(9),
+            ..options(Some(TieBreak::Complete))
+        };
+        assert_eq!(break_title_tie(&vaswani(), titles, &cut), None);
+    }
+
+    #[test]
+    fn test_fit_title_to_path() {
+        let options = NamingOptions::default();
+        // "vaswani-2017-attention-is-all-you-need.pdf" is 42 bytes
+        assert_eq!(fit_title_to_path(&vaswani(), &options, 100, 142).unwrap(), None);
+
+        // One byte over the limit costs the last word, not the folders or the extension
+        let max = fit_title_to_path(&vaswani(), &options, 101, 142).unwrap().unwrap();
+        let shortened = NamingOptions {
+            max_title_length: Some(max),
+            ..options.clone()
+        };
+        assert_eq!(generate_filename(&vaswani(), &shortened), "vaswani-2017-attention-is-all-you.pdf");
+
+        // Not even the author and year fit
+        assert!(fit_title_to_path(&vaswani(), &options, 100, 110).is_err());
+    }
+
+    #[test]
+    fn test_truncation_token() {
+        let options = |max| NamingOptions {
+            format: "{title}".to_string(),
+            max_title_length: Some(max),
+            truncation_token: Some(parse_truncation_token("-Etc.-").unwrap()),
+            ..NamingOptions::default()
+        };
+
+        // A cut title gets the marker, with one dash before it, and the
+        // marker's room is taken from the title
+        assert_eq!(generate_filename(&vaswani(), &options(16)), "attention-is-etc.pdf");
+        assert_eq!(generate_filename(&vaswani(), &options(15)), "attention-etc.pdf");
+        assert_eq!(generate_filename(&vaswani(), &options(13)), "attention-etc.pdf");
+        assert_eq!(generate_filename(&vaswani(), &options(9)), "atten-etc.pdf");
+        // Too little room for any of the title leaves the marker alone
+        assert_eq!(generate_filename(&vaswani(), &options(4)), "etc.pdf");
+        assert_eq!(generate_filename(&vaswani(), &options(2)), "et.pdf");
+        for max in 1..25 {
+            let title = tidy_title("attention-is-all-you-need", &options(max));
+            assert!(title.chars().count() <= max, "{} is longer than {}", title, max);
+        }
+        // A title that fits is left alone
+        assert_eq!(generate_filename(&vaswani(), &options(25)), "attention-is-all-you-need.pdf");
+
+        assert!(parse_truncation_token("...").is_err());
+    }
+
+    #[test]
+    fn test_generate_filename_hash_prefix() {
+        let options = NamingOptions {
+            hash_prefix: Some(6),
+            ..NamingOptions::default()
+        };
+        let mut metadata = vaswani();
+
+        // No hash computed: the name is left as it is
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017-attention-is-all-you-need.pdf");
+
+        metadata.content_hash = "a1b2c3d4e5f6".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "a1b2c3-vaswani-2017-attention-is-all-you-need.pdf");
+    }
+
+    #[test]
+    fn test_year_format() {
+        assert_eq!(format_year("2017", &parse_year_format("full").unwrap()), "2017");
+        assert_eq!(format_year("2017", &parse_year_format("short").unwrap()), "17");
+        assert_eq!(format_year("2017", &parse_year_format("ad{yyyy}").unwrap()), "ad2017");
+
+        // Nothing but a 4-digit year is cut short
+        assert_eq!(format_year("n.d.", "{yy}"), "n.d.");
+        assert_eq!(format_year("", "{yy}"), "");
+
+        assert!(parse_year_format("yy").is_err());
+        assert!(parse_year_format("{year}").is_err());
+
+        let options = NamingOptions {
+            year_format: parse_year_format("short").unwrap(),
+            ..NamingOptions::default()
+        };
+        assert_eq!(generate_filename(&vaswani(), &options), "vaswani-17-attention-is-all-you-need.pdf");
+    }
+
+    #[test]
+    fn test_citekey_preset() {
+        let options = NamingOptions {
+            format: expand_preset("citekey"),
+            ..NamingOptions::default()
+        };
+        validate_format(&options.format).unwrap();
+        let mut metadata = vaswani();
+        assert_eq!(generate_filename(&metadata, &options), "vaswani2017attention.pdf");
+
+        // Leading stopwords are skipped
+        metadata.title = "The Annotated Transformer".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "vaswani2017annotated.pdf");
+
+        assert!(is_citekey(&options.format));
+        assert_eq!(expand_preset("{year}-{title}"), "{year}-{title}");
+    }
+
+    #[test]
+    fn test_check_required_match() {
+        let options = NamingOptions {
+            require_match: Some(Regex::new(r"^[a-z]+-\d{4}-[a-z0-9-]+\.pdf$").unwrap()),
+            ..NamingOptions::default()
+        };
+        assert!(check_required_match("vaswani-2017-attention.pdf", &options).is_ok());
+
+        let error = check_required_match("vaswani-nd-attention.pdf", &options).unwrap_err();
+        assert!(error.to_string().contains("vaswani-nd-attention.pdf does not match"));
+
+        // Without a pattern anything goes
+        assert!(check_required_match("Anything.pdf", &NamingOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_title_acronym() {
+        let options = NamingOptions {
+            title_acronym: true,
+            ..NamingOptions::default()
+        };
+        let mut metadata = vaswani();
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017-aiayn.pdf");
+
+        // Stopwords are skipped
+        metadata.title = "A Survey of Methods for Graph Neural Networks".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017-smgnn.pdf");
+
+        // Titles with fewer than three significant words are kept as they are
+        metadata.title = "The Transformer Network".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "vaswani-2017-the-transformer-network.pdf");
+    }
+
+    #[test]
+    fn test_drop_single_char_words() {
+        let mut metadata = vaswani();
+        metadata.title = "A Survey of X-Ray Imaging: Part B".to_string();
+        let options = NamingOptions {
+            format: "{title}".to_string(),
+            drop_single_char_words: true,
+            ..NamingOptions::default()
+        };
+        assert_eq!(generate_filename(&metadata, &options), "survey-of-ray-imaging-part.pdf");
+
+        // A title made only of one-letter words is kept
+        metadata.title = "R".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "r.pdf");
+    }
+
+    #[test]
+    fn test_strip_leading_articles() {
+        let mut metadata = vaswani();
+        let mut options = NamingOptions {
+            format: "{title}".to_string(),
+            leading_articles: parse_leading_articles(DEFAULT_LEADING_ARTICLES).unwrap(),
+            ..NamingOptions::default()
+        };
+        let mut title = |title: &str, options: &NamingOptions| {
+            metadata.title = title.to_string();
+            generate_filename(&metadata, options)
+        };
+
+        assert_eq!(title("The Transformer Architecture", &options), "transformer-architecture.pdf");
+        assert_eq!(title("A Survey of Methods", &options), "survey-of-methods.pdf");
+        assert_eq!(title("An Empirical Study", &options), "empirical-study.pdf");
+        // Only a single leading article goes
+        assert_eq!(title("The A Team Revisited", &options), "a-team-revisited.pdf");
+        // Too short to lose a word
+        assert_eq!(title("The End", &options), "the-end.pdf");
+        // Not an article, just a word starting like one
+        assert_eq!(title("Theory of Mind", &options), "theory-of-mind.pdf");
+
+        options.leading_articles = parse_leading_articles("Der, Die ,das").unwrap();
+        assert_eq!(title("Die Grenzen des Lernens", &options), "grenzen-des-lernens.pdf");
+        assert_eq!(title("The Transformer Architecture", &options), "the-transformer-architecture.pdf");
+
+        assert!(parse_leading_articles(" , ").is_err());
+    }
+
+    #[test]
+    fn test_trim_trailing_numbers() {
+        let mut metadata = vaswani();
+        metadata.title = "Proceedings of NeurIPS 2017 12 3".to_string();
+        let options = NamingOptions {
+            format: "{title}".to_string(),
+            trim_trailing_numbers: true,
+            ..NamingOptions::default()
+        };
+        assert_eq!(generate_filename(&metadata, &options), "proceedings-of-neurips.pdf");
+
+        // Numbers inside the title are left alone, and a numeric-only title is kept
+        metadata.title = "Web 2 0 Design".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "web-2-0-design.pdf");
+        metadata.title = "1984".to_string();
+        assert_eq!(generate_filename(&metadata, &options), "1984.pdf");
     }
 }
@@ -0,0 +1,82 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Papers found in a directory of per-paper subfolders (`Some Title/paper.pdf`)
+#[derive(Debug, Default)]
+pub struct PaperFolders {
+    /// The single PDF of each usable subfolder
+    pub papers: Vec<String>,
+    /// Subfolders that were left alone, with the reason
+    pub skipped: Vec<(String, String)>,
+}
+
+/// Find the one PDF inside each immediate subfolder of `dir`
+/// Subfolders with no PDF or with several are skipped, since it isn't
+/// clear which file the folder is named after
+pub fn find_papers(dir: &str) -> Result<PaperFolders> {
+    let mut subfolders = read_dir_sorted(Path::new(dir))?;
+    subfolders.retain(|path| path.is_dir());
+
+    let mut found = PaperFolders::default();
+    for subfolder in subfolders {
+        let pdfs: Vec<PathBuf> = read_dir_sorted(&subfolder)?
+            .into_iter()
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(crate::filename::has_pdf_extension)
+            })
+            .collect();
+
+        let subfolder = subfolder.to_string_lossy().into_owned();
+        match pdfs.as_slice() {
+            [pdf] => found.papers.push(pdf.to_string_lossy().into_owned()),
+            [] => found.skipped.push((subfolder, "no PDF in folder".to_string())),
+            _ => found
+                .skipped
+                .push((subfolder, format!("{} PDFs in folder, expected one", pdfs.len()))),
+        }
+    }
+
+    Ok(found)
+}
+
+fn read_dir_sorted(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+    paths.sort();
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_find_papers() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for folder in ["Attention", "Empty", "Several"] {
+            fs::create_dir(root.join(folder)).unwrap();
+        }
+        File::create(root.join("Attention/paper.PDF")).unwrap();
+        File::create(root.join("Attention/notes.txt")).unwrap();
+        File::create(root.join("Several/a.pdf")).unwrap();
+        File::create(root.join("Several/b.pdf")).unwrap();
+        File::create(root.join("loose.pdf")).unwrap();
+
+        let found = find_papers(root.to_str().unwrap()).unwrap();
+        assert_eq!(found.papers, [root.join("Attention/paper.PDF").to_string_lossy()]);
+        assert_eq!(found.skipped.len(), 2);
+        assert!(found.skipped[0].0.ends_with("Empty"));
+        assert_eq!(found.skipped[1].1, "2 PDFs in folder, expected one");
+    }
+}
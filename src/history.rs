@@ -0,0 +1,283 @@
+use crate::renamer::{self, ApplyError, RenamePlan, RenameRecord};
+use crate::report;
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How the rename log is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum LogFormat {
+    /// One JSON object per line
+    #[default]
+    Jsonl,
+    /// Comma-separated values with a header row
+    Csv,
+    /// One human-readable line per rename
+    Plain,
+}
+
+/// Where and how renames are logged
+#[derive(Debug, Clone)]
+pub struct RenameLog {
+    pub path: String,
+    pub format: LogFormat,
+}
+
+/// Whether a logged rename happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogStatus {
+    Renamed,
+    Failed,
+}
+
+impl LogStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            LogStatus::Renamed => "renamed",
+            LogStatus::Failed => "failed",
+        }
+    }
+}
+
+/// One line of the rename log
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogEntry {
+    /// UTC time of the rename, e.g. `2024-05-01T12:30:00Z`
+    pub timestamp: String,
+    pub from: PathBuf,
+    pub to: PathBuf,
+    /// Ollama model that extracted the metadata; empty when no LLM was used
+    pub model: String,
+    pub status: LogStatus,
+}
+
+impl RenameLog {
+    /// Append the outcome of an apply phase to the log
+    /// Every completed rename is logged as renamed; after a failure, the plans
+    /// that were not carried out are logged as failed with their intended target
+    pub fn record(
+        &self,
+        model: &str,
+        plans: &[RenamePlan],
+        applied: &std::result::Result<Vec<RenameRecord>, ApplyError>,
+    ) -> Result<()> {
+        let timestamp = timestamp(SystemTime::now());
+        let entry = |from: PathBuf, to: PathBuf, status| LogEntry {
+            timestamp: timestamp.clone(),
+            from,
+            to,
+            model: model.to_string(),
+            status,
+        };
+
+        let completed = renamer::completed(applied);
+        let mut entries: Vec<LogEntry> = completed
+            .iter()
+            .map(|record| entry(record.from.clone(), record.to.clone(), LogStatus::Renamed))
+            .collect();
+        if applied.is_err() {
+            entries.extend(
+                plans
+                    .iter()
+                    .skip(completed.len())
+                    .map(|plan| entry(PathBuf::from(&plan.source), plan.target(), LogStatus::Failed)),
+            );
+        }
+
+        self.append(&entries)
+    }
+
+    fn append(&self, entries: &[LogEntry]) -> Result<()> {
+        if entries.is_empty() {
+            return Ok(());
+        }
+
+        // A CSV log gets its header only when it is first created
+        let is_new = !Path::new(&self.path).exists();
+        let mut contents = String::new();
+        if is_new && self.format == LogFormat::Csv {
+            contents.push_str("timestamp,from,to,model,status\n");
+        }
+        for entry in entries {
+            contents.push_str(&format_entry(entry, self.format)?);
+            contents.push('\n');
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .with_context(|| format!("Failed to open rename log: {}", self.path))?;
+        file.write_all(contents.as_bytes())
+            .with_context(|| format!("Failed to write rename log: {}", self.path))
+    }
+}
+
+/// Render one log entry as a single line, without the line break
+fn format_entry(entry: &LogEntry, format: LogFormat) -> Result<String> {
+    Ok(match format {
+        LogFormat::Jsonl => serde_json::to_string(entry)?,
+        LogFormat::Csv => [
+            entry.timestamp.clone(),
+            entry.from.to_string_lossy().into_owned(),
+            entry.to.to_string_lossy().into_owned(),
+            entry.model.clone(),
+            entry.status.as_str().to_string(),
+        ]
+        .iter()
+        .map(|field| report::csv_field(field))
+        .collect::<Vec<_>>()
+        .join(","),
+        LogFormat::Plain => {
+            let mut line = format!(
+                "{} {} {} -> {}",
+                entry.timestamp,
+                entry.status.as_str(),
+                entry.from.display(),
+                entry.to.display()
+            );
+            if !entry.model.is_empty() {
+                line.push_str(&format!(" (model: {})", entry.model));
+            }
+            line
+        }
+    })
+}
+
+/// Format a point in time as an ISO 8601 UTC timestamp, e.g. `2024-05-01T12:30:00Z`
+fn timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, secs_of_day) = (secs / 86_400, secs % 86_400);
+
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs_of_day / 3600,
+        secs_of_day % 3600 / 60,
+        secs_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::time::Duration;
+    use tempfile::TempDir;
+
+    fn entry() -> LogEntry {
+        LogEntry {
+            timestamp: "2024-05-01T12:30:00Z".to_string(),
+            from: PathBuf::from("/papers/a.pdf"),
+            to: PathBuf::from("/papers/smith-2020-deep, learning.pdf"),
+            model: "llama3.2".to_string(),
+            status: LogStatus::Renamed,
+        }
+    }
+
+    #[test]
+    fn test_timestamp() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_714_566_600)),
+            "2024-05-01T12:30:00Z"
+        );
+        // Leap day
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(1_709_164_800)),
+            "2024-02-29T00:00:00Z"
+        );
+    }
+
+    #[test]
+    fn test_format_entry() {
+        let json = format_entry(&entry(), LogFormat::Jsonl).unwrap();
+        assert_eq!(serde_json::from_str::<LogEntry>(&json).unwrap(), entry());
+
+        assert_eq!(
+            format_entry(&entry(), LogFormat::Csv).unwrap(),
+            "2024-05-01T12:30:00Z,/papers/a.pdf,\"/papers/smith-2020-deep, learning.pdf\",llama3.2,renamed"
+        );
+        assert_eq!(
+            format_entry(&entry(), LogFormat::Plain).unwrap(),
+            "2024-05-01T12:30:00Z renamed /papers/a.pdf -> /papers/smith-2020-deep, learning.pdf (model: llama3.2)"
+        );
+    }
+
+    #[test]
+    fn test_csv_log_header_written_once() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = RenameLog {
+            path: temp_dir.path().join("renames.csv").to_string_lossy().into_owned(),
+            format: LogFormat::Csv,
+        };
+
+        log.append(&[entry()]).unwrap();
+        log.append(&[entry()]).unwrap();
+
+        let contents = fs::read_to_string(&log.path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "timestamp,from,to,model,status");
+    }
+
+    #[test]
+    fn test_record_partial_apply() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = RenameLog {
+            path: temp_dir.path().join("renames.jsonl").to_string_lossy().into_owned(),
+            format: LogFormat::Jsonl,
+        };
+        let plans: Vec<RenamePlan> = ["a.pdf", "b.pdf", "c.pdf"]
+            .iter()
+            .map(|name| RenamePlan {
+                source: format!("/papers/{}", name),
+                new_filename: format!("new-{}", name),
+                ..RenamePlan::default()
+            })
+            .collect();
+        // The second rename failed after the first went through
+        let applied = Err(ApplyError {
+            completed: vec![RenameRecord {
+                from: PathBuf::from("/papers/a.pdf"),
+                to: PathBuf::from("/papers/new-a.pdf"),
+            }],
+            error: anyhow::anyhow!("Failed to rename /papers/b.pdf"),
+        });
+
+        log.record("llama3.2", &plans, &applied).unwrap();
+
+        let contents = fs::read_to_string(&log.path).unwrap();
+        let statuses: Vec<(LogStatus, PathBuf)> = contents
+            .lines()
+            .map(|line| serde_json::from_str::<LogEntry>(line).unwrap())
+            .map(|entry| (entry.status, entry.to))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                (LogStatus::Renamed, PathBuf::from("/papers/new-a.pdf")),
+                (LogStatus::Failed, PathBuf::from("/papers/new-b.pdf")),
+                (LogStatus::Failed, PathBuf::from("/papers/new-c.pdf")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,92 @@
+use crate::llm::PaperMetadata;
+use anyhow::{Context, Result};
+use std::io::{ErrorKind, Write};
+use std::process::{Command, Stdio};
+
+/// Run a user-supplied command on a proposed filename
+/// The command receives `{"filename": ..., "metadata": {...}}` as JSON on stdin
+/// and prints the filename to use on stdout. A non-zero exit means the file
+/// should be skipped, which is reported as `None`.
+pub fn post_process(command: &str, filename: &str, metadata: &PaperMetadata) -> Result<Option<String>> {
+    let input = serde_json::json!({
+        "filename": filename,
+        "metadata": metadata,
+    });
+
+    let mut child = shell(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("Failed to run post-process command: {}", command))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that ignores its input may exit before reading it
+        match stdin.write_all(format!("{}\n", input).as_bytes()) {
+            Err(e) if e.kind() != ErrorKind::BrokenPipe => {
+                return Err(e).context("Failed to write to post-process command");
+            }
+            _ => {}
+        }
+    }
+
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Failed to run post-process command: {}", command))?;
+
+    if !output.status.success() {
+        return Ok(None);
+    }
+
+    let new_filename = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if new_filename.is_empty() {
+        anyhow::bail!("Post-process command printed no filename: {}", command);
+    }
+
+    Ok(Some(new_filename))
+}
+
+#[cfg(unix)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("sh");
+    shell.arg("-c").arg(command);
+    shell
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.arg("/C").arg(command);
+    shell
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn metadata() -> PaperMetadata {
+        serde_json::from_str(r#"{"first_author": "Vaswani", "year": "2017", "title": "Attention Is All You Need"}"#)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_post_process_replaces_filename() {
+        let result = post_process(
+            "grep -q '\"first_author\":\"Vaswani\"' && echo custom.pdf",
+            "vaswani-2017-attention-is-all-you-need.pdf",
+            &metadata(),
+        )
+        .unwrap();
+        assert_eq!(result, Some("custom.pdf".to_string()));
+    }
+
+    #[test]
+    fn test_post_process_failure_skips_file() {
+        assert_eq!(post_process("exit 3", "a.pdf", &metadata()).unwrap(), None);
+    }
+
+    #[test]
+    fn test_post_process_requires_output() {
+        assert!(post_process("cat > /dev/null", "a.pdf", &metadata()).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::llm::PaperMetadata;
+use crate::renamer::RenameRecord;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A renamed paper and the metadata its name was generated from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Absolute path of the file
+    pub path: PathBuf,
+    pub metadata: PaperMetadata,
+}
+
+/// Read a library index written by `--index-out`
+pub fn read_index(path: &str) -> Result<Vec<IndexEntry>> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("Failed to read index: {}", path))?;
+    serde_json::from_str(&contents).with_context(|| format!("Failed to parse index: {}", path))
+}
+
+/// Add renamed files to an index, creating it if needed
+/// A file that is already indexed under its old or new path is replaced,
+/// so the index follows files across repeated renames
+pub fn record_renames(path: &str, renames: &[(RenameRecord, PaperMetadata)]) -> Result<()> {
+    let mut entries = if Path::new(path).exists() {
+        read_index(path)?
+    } else {
+        Vec::new()
+    };
+
+    for (record, metadata) in renames {
+        entries.retain(|entry| entry.path != record.from && entry.path != record.to);
+        entries.push(IndexEntry {
+            path: record.to.clone(),
+            metadata: metadata.clone(),
+        });
+    }
+
+    let contents = serde_json::to_string_pretty(&entries)?;
+    fs::write(path, contents).with_context(|| format!("Failed to write index: {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn metadata(title: &str) -> PaperMetadata {
+        PaperMetadata {
+            first_author: "Smith".to_string(),
+            year: "2020".to_string(),
+            title: title.to_string(),
+            ..PaperMetadata::default()
+        }
+    }
+
+    #[test]
+    fn test_record_renames_follows_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let index = temp_dir.path().join("library.json");
+        let index = index.to_str().unwrap();
+        let record = |from: &str, to: &str| RenameRecord {
+            from: temp_dir.path().join(from),
+            to: temp_dir.path().join(to),
+        };
+
+        record_renames(
+            index,
+            &[
+                (record("a.pdf", "smith-2020-one.pdf"), metadata("One")),
+                (record("b.pdf", "smith-2020-two.pdf"), metadata("Two")),
+            ],
+        )
+        .unwrap();
+        record_renames(index, &[(record("smith-2020-one.pdf", "2020-one.pdf"), metadata("One"))])
+            .unwrap();
+
+        let entries = read_index(index).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, temp_dir.path().join("smith-2020-two.pdf"));
+        assert_eq!(entries[1].path, temp_dir.path().join("2020-one.pdf"));
+        assert_eq!(entries[1].metadata.title, "One");
+    }
+}
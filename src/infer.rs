@@ -0,0 +1,219 @@
+use crate::filename;
+use crate::llm::PaperMetadata;
+use std::collections::HashMap;
+
+/// A run of letters and digits in a filename, with its byte range
+struct Token {
+    start: usize,
+    end: usize,
+    /// Lowercase ASCII form, for comparing with metadata
+    folded: String,
+}
+
+/// Lowercase ASCII form of a word, so `Müller` matches `muller`
+fn fold(word: &str) -> String {
+    any_ascii::any_ascii(word).to_lowercase()
+}
+
+/// The words of a metadata value, folded
+fn words(value: &str) -> Vec<String> {
+    value
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(fold)
+        .collect()
+}
+
+fn tokenize(stem: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in stem.char_indices().chain([(stem.len(), ' ')]) {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                tokens.push(Token {
+                    start: s,
+                    end: i,
+                    folded: fold(&stem[s..i]),
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// Find `needle` as consecutive unclaimed tokens; returns the token index range
+fn find_run(tokens: &[Token], claimed: &[bool], needle: &[String]) -> Option<(usize, usize)> {
+    if needle.is_empty() || needle.len() > tokens.len() {
+        return None;
+    }
+    (0..=tokens.len() - needle.len()).find_map(|i| {
+        let run = i..i + needle.len();
+        let matches = run.clone().all(|j| !claimed[j] && tokens[j].folded == needle[j - i]);
+        matches.then_some((run.start, run.end))
+    })
+}
+
+/// Find the title: the longest run of tokens spelling out the start of the
+/// title (names are often shortened), or a single token with the title's
+/// words run together (`AttentionIsAllYouNeed`)
+fn find_title(tokens: &[Token], claimed: &[bool], title: &[String]) -> Option<(usize, usize)> {
+    let minimum = title.len().min(2);
+    for len in (minimum..=title.len()).rev() {
+        if let Some(run) = find_run(tokens, claimed, &title[..len]) {
+            return Some(run);
+        }
+    }
+
+    let joined: String = title.concat();
+    tokens.iter().enumerate().find_map(|(i, token)| {
+        let long_enough = token.folded.len() >= 8 && title.len() >= minimum;
+        (!claimed[i] && long_enough && joined.starts_with(&token.folded)).then_some((i, i + 1))
+    })
+}
+
+/// Guess the template an existing filename follows, given the paper's metadata
+/// Each metadata field is looked up among the words of the name; the fields
+/// found become placeholders, in the order they appear and joined by the
+/// separators between them. Words that match no field are left out.
+/// Returns None if no field could be found
+pub fn infer_format(name: &str, metadata: &PaperMetadata) -> Option<String> {
+    let stem = match filename::pdf_extension(name) {
+        Some(extension) => &name[..name.len() - extension.len() - 1],
+        None => name,
+    };
+    let tokens = tokenize(stem);
+    let mut claimed = vec![false; tokens.len()];
+
+    // Longer fields first, so a last name inside the full name or a year-like
+    // word inside the title isn't taken for the field on its own
+    let fields: [(&str, Vec<String>); 8] = [
+        ("first_author_full", words(&metadata.first_author_full)),
+        ("title", words(&metadata.title)),
+        ("year", words(&metadata.year)),
+        ("author", words(&metadata.first_author)),
+        ("version", words(&metadata.version)),
+        ("category", words(&metadata.category)),
+        ("type", words(&metadata.doc_type)),
+        ("publisher", words(&metadata.publisher)),
+    ];
+
+    let mut found: Vec<(&str, usize, usize)> = Vec::new();
+    for (placeholder, value) in &fields {
+        // A one-word full name is just the last name
+        if *placeholder == "first_author_full" && value.len() < 2 {
+            continue;
+        }
+        let run = if *placeholder == "title" {
+            find_title(&tokens, &claimed, value)
+        } else {
+            find_run(&tokens, &claimed, value)
+        };
+        if let Some((first, last)) = run {
+            claimed[first..last].iter_mut().for_each(|taken| *taken = true);
+            found.push((placeholder, tokens[first].start, tokens[last - 1].end));
+        }
+    }
+    if found.is_empty() {
+        return None;
+    }
+    found.sort_by_key(|&(_, start, _)| start);
+
+    // Text around and between the fields is kept when it is punctuation only;
+    // between two fields, leftover words are dropped but their separator is kept
+    let punctuation = |text: &str| !text.chars().any(char::is_alphanumeric);
+    let separator = |text: &str| -> String {
+        if punctuation(text) {
+            return text.to_string();
+        }
+        text.chars()
+            .skip_while(|c| c.is_alphanumeric())
+            .take_while(|c| !c.is_alphanumeric())
+            .collect()
+    };
+
+    let mut format = String::new();
+    let prefix = &stem[..found[0].1];
+    if punctuation(prefix) {
+        format.push_str(prefix);
+    }
+    for (i, (placeholder, _, end)) in found.iter().enumerate() {
+        format.push_str(&format!("{{{}}}", placeholder));
+        match found.get(i + 1) {
+            Some((_, next, _)) => format.push_str(&separator(&stem[*end..*next])),
+            None if punctuation(&stem[*end..]) => format.push_str(&stem[*end..]),
+            None => {}
+        }
+    }
+    Some(format)
+}
+
+/// The template most examples agree on, with the number of examples behind it
+/// Ties go to the template seen first
+pub fn most_common(formats: &[String]) -> Option<(String, usize)> {
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for format in formats {
+        *counts.entry(format).or_default() += 1;
+    }
+    let best = counts.values().copied().max()?;
+    formats
+        .iter()
+        .find(|format| counts[format.as_str()] == best)
+        .map(|format| (format.clone(), best))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vaswani() -> PaperMetadata {
+        PaperMetadata {
+            first_author: "Vaswani".to_string(),
+            first_author_full: "Ashish Vaswani".to_string(),
+            year: "2017".to_string(),
+            title: "Attention Is All You Need".to_string(),
+            ..PaperMetadata::default()
+        }
+    }
+
+    #[test]
+    fn test_infer_format() {
+        let metadata = vaswani();
+        assert_eq!(
+            infer_format("Vaswani_2017_Attention Is All You Need.pdf", &metadata).as_deref(),
+            Some("{author}_{year}_{title}")
+        );
+        assert_eq!(
+            infer_format("[2017] attention-is-all-you-need (Vaswani).PDF", &metadata).as_deref(),
+            Some("[{year}] {title} ({author})")
+        );
+        assert_eq!(
+            infer_format("Ashish Vaswani - Attention Is All.pdf", &metadata).as_deref(),
+            Some("{first_author_full} - {title}")
+        );
+        assert_eq!(infer_format("scan0042.pdf", &metadata), None);
+    }
+
+    #[test]
+    fn test_infer_format_drops_unknown_words() {
+        let mut metadata = vaswani();
+        metadata.first_author = "Müller".to_string();
+        assert_eq!(
+            infer_format("muller.2017.NeurIPS.AttentionIsAllYouNeed.pdf", &metadata).as_deref(),
+            Some("{author}.{year}.{title}")
+        );
+    }
+
+    #[test]
+    fn test_most_common() {
+        let formats = vec![
+            "{author}_{year}".to_string(),
+            "{year}_{title}".to_string(),
+            "{year}_{title}".to_string(),
+        ];
+        assert_eq!(most_common(&formats), Some(("{year}_{title}".to_string(), 2)));
+        assert_eq!(most_common(&[]), None);
+    }
+}
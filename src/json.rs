@@ -0,0 +1,127 @@
+use crate::report::Entry;
+use crate::{llm, pdf, renamer};
+use pdf_extract::OutputError;
+use serde_json::{json, Value};
+
+/// Classify an error for programs reading --json output
+/// Kinds: `ollama_unavailable`, `no_text`, `garbled_text`, `unreadable_pdf`, `plan_conflict`
+/// and `other`; download failures are reported as `download_failed` by the caller
+pub fn error_kind(error: &anyhow::Error) -> &'static str {
+    for cause in error.chain() {
+        if cause.is::<llm::OllamaUnavailable>() {
+            return "ollama_unavailable";
+        }
+        if cause
+            .downcast_ref::<reqwest::Error>()
+            .is_some_and(|e| e.is_connect() || e.is_timeout())
+        {
+            return "ollama_unavailable";
+        }
+        if cause.is::<pdf::InsufficientText>() {
+            return "no_text";
+        }
+        if cause.is::<pdf::GarbledText>() {
+            return "garbled_text";
+        }
+        if cause.is::<OutputError>() {
+            return "unreadable_pdf";
+        }
+        if cause.is::<renamer::PlanConflicts>() {
+            return "plan_conflict";
+        }
+    }
+    "other"
+}
+
+/// The JSON object describing one error
+fn error_json(source: Option<&str>, kind: &str, error: &anyhow::Error) -> Value {
+    let mut object = json!({
+        "status": "error",
+        "kind": kind,
+        "message": format!("{:#}", error),
+    });
+    if let Some(source) = source {
+        object["source"] = json!(source);
+    }
+    object
+}
+
+/// Print an error as one line of JSON on stderr
+pub fn print_error(source: Option<&str>, kind: &str, error: &anyhow::Error) {
+    eprintln!("{}", error_json(source, kind, error));
+}
+
+/// The JSON object for the metadata extracted from one file
+fn metadata_json(source: &str, metadata: &llm::PaperMetadata) -> Value {
+    json!({
+        "source": source,
+        "status": "extracted",
+        "metadata": metadata,
+    })
+}
+
+/// Print the metadata of a file as one line of JSON on stdout, for --extract-only
+pub fn print_metadata(source: &str, metadata: &llm::PaperMetadata) {
+    println!("{}", metadata_json(source, metadata));
+}
+
+/// Print a file's outcome as one line of JSON on stdout
+pub fn print_entry(entry: &Entry) {
+    println!(
+        "{}",
+        json!({
+            "source": entry.source,
+            "status": entry.status.as_str(),
+            "new_name": entry.new_name,
+            "message": entry.message,
+        })
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_kind() {
+        let no_text = anyhow::Error::new(pdf::InsufficientText { found: 0, minimum: 200 });
+        assert_eq!(error_kind(&no_text), "no_text");
+
+        // The kind is found beneath added context
+        let down = anyhow::Error::new(llm::OllamaUnavailable("http://localhost:11434".to_string()))
+            .context("Failed to detect Ollama model");
+        assert_eq!(error_kind(&down), "ollama_unavailable");
+
+        let conflict = anyhow::Error::new(renamer::PlanConflicts(vec!["a.pdf: target file already exists".to_string()]));
+        assert_eq!(error_kind(&conflict), "plan_conflict");
+
+        assert_eq!(error_kind(&anyhow::anyhow!("something else")), "other");
+    }
+
+    #[test]
+    fn test_error_json() {
+        let error = anyhow::Error::new(pdf::InsufficientText { found: 0, minimum: 200 });
+        let object = error_json(Some("scan.pdf"), "no_text", &error);
+        assert_eq!(object["status"], "error");
+        assert_eq!(object["kind"], "no_text");
+        assert_eq!(object["source"], "scan.pdf");
+        assert!(object["message"].as_str().unwrap().starts_with("No text could be extracted"));
+
+        assert!(error_json(None, "other", &error).get("source").is_none());
+    }
+
+    #[test]
+    fn test_metadata_json() {
+        let metadata = llm::PaperMetadata {
+            first_author: "Vaswani".to_string(),
+            year: "2017".to_string(),
+            title: "Attention Is All You Need".to_string(),
+            ..llm::PaperMetadata::default()
+        };
+        let object = metadata_json("1706.03762.pdf", &metadata);
+        assert_eq!(object["status"], "extracted");
+        assert_eq!(object["source"], "1706.03762.pdf");
+        assert_eq!(object["metadata"]["first_author"], "Vaswani");
+        assert_eq!(object["metadata"]["title"], "Attention Is All You Need");
+    }
+}
@@ -1,12 +1,128 @@
 use anyhow::{Context, Result};
 use reqwest::blocking::Client;
-use serde::{Deserialize, Serialize};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct PaperMetadata {
     pub first_author: String,
+    #[serde(default)]
+    pub first_author_full: String,
     pub year: String,
     pub title: String,
+    /// Title part of the filename, used in place of the slug of `title`; set when
+    /// --fill-missing keeps the title from the existing name but `title` keeps the
+    /// paper's own casing for the index and JSON output
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub title_slug: String,
+    /// Preprint version marker (e.g. "v2"), detected from the text or filename
+    #[serde(default)]
+    pub version: String,
+    /// Primary arXiv category (e.g. "cs.LG"), detected from the arXiv stamp
+    #[serde(default)]
+    pub category: String,
+    /// Document type ("journal", "conference", "preprint" or "thesis"), detected
+    /// from cues on the first pages
+    #[serde(default)]
+    pub doc_type: String,
+    /// Publisher (e.g. "ACM"), from the page's copyright line, the embedded
+    /// metadata or the LLM
+    #[serde(default)]
+    pub publisher: String,
+    /// DOI (e.g. "10.1145/3292500.3330701"), detected from the page text
+    #[serde(default)]
+    pub doi: String,
+    /// Hex SHA-256 of the file's contents, only computed for --hash-prefix
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub content_hash: String,
+    /// Paper abstract, only requested from the LLM when it will be saved
+    #[serde(default, rename = "abstract")]
+    pub abstract_text: String,
+    /// Where the LLM found each field, keyed by field name; only requested for --explain
+    #[serde(default, deserialize_with = "deserialize_evidence", skip_serializing_if = "BTreeMap::is_empty")]
+    pub evidence: BTreeMap<String, String>,
+}
+
+/// Read the evidence notes leniently: a missing or oddly shaped object must
+/// not make an otherwise good extraction fail
+fn deserialize_evidence<'de, D>(deserializer: D) -> std::result::Result<BTreeMap<String, String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let serde_json::Value::Object(notes) = serde_json::Value::deserialize(deserializer)? else {
+        return Ok(BTreeMap::new());
+    };
+
+    Ok(notes
+        .into_iter()
+        .filter_map(|(field, note)| match note {
+            serde_json::Value::String(note) => Some((field, note)),
+            serde_json::Value::Null => None,
+            other => Some((field, other.to_string())),
+        })
+        .collect())
+}
+
+/// Default address of a local Ollama server
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+/// How much of an unparseable LLM response is quoted in the error
+const RESPONSE_PREVIEW_CHARS: usize = 300;
+
+/// Default maximum number of words in the extracted author last name
+pub const DEFAULT_MAX_AUTHOR_TOKENS: usize = 4;
+
+/// First Ollama release that honours `format: "json"` in generate requests
+pub const MIN_JSON_FORMAT_VERSION: (u32, u32, u32) = (0, 1, 9);
+
+/// Options controlling how metadata is extracted from the LLM
+#[derive(Debug, Clone)]
+pub struct ExtractOptions {
+    /// Require the response to be exactly one JSON object, with no salvaging
+    pub strict_json: bool,
+    /// Reject author fields with more words than this (e.g. a list of every author)
+    pub max_author_tokens: usize,
+    /// Also ask the LLM for the paper's abstract
+    pub include_abstract: bool,
+    /// Also ask the LLM where it found each field
+    pub include_evidence: bool,
+    /// Also ask the LLM for the publisher, for templates that use it
+    pub include_publisher: bool,
+    /// HTTP client shared by every Ollama request of the run (see `http_client`)
+    pub client: Client,
+    /// Print every raw LLM response
+    pub show_response: bool,
+    /// Save the full response to a new file in the temp folder when it can't be parsed
+    pub dump_failed_response: bool,
+    /// Sample with temperature 0 and a fixed seed, so repeated runs agree
+    pub deterministic: bool,
+    /// Ask Ollama to constrain the answer to JSON; off for servers too old
+    /// to support it, whose answers are left to the lenient parser
+    pub json_format: bool,
+    /// Prompt to send instead of the built-in one (see `metadata_prompt`),
+    /// set when the user edits it under --prompt-debug
+    pub prompt_template: Option<String>,
+}
+
+impl Default for ExtractOptions {
+    fn default() -> Self {
+        Self {
+            strict_json: false,
+            max_author_tokens: DEFAULT_MAX_AUTHOR_TOKENS,
+            include_abstract: false,
+            include_evidence: false,
+            include_publisher: false,
+            client: Client::new(),
+            show_response: false,
+            dump_failed_response: false,
+            deterministic: false,
+            json_format: true,
+            prompt_template: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -14,7 +130,17 @@ struct OllamaRequest {
     model: String,
     prompt: String,
     stream: bool,
-    format: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    format: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    options: Option<ModelOptions>,
+}
+
+/// Sampling parameters passed to the model
+#[derive(Debug, Serialize)]
+struct ModelOptions {
+    temperature: f32,
+    seed: u32,
 }
 
 #[derive(Debug, Deserialize)]
@@ -42,30 +168,81 @@ struct AvailableModelsResponse {
     models: Vec<AvailableModel>,
 }
 
+#[derive(Debug, Deserialize)]
+struct VersionResponse {
+    version: String,
+}
+
+/// Build the URL of an Ollama API endpoint
+fn endpoint(base_url: &str, path: &str) -> String {
+    format!("{}/api/{}", base_url.trim_end_matches('/'), path)
+}
+
+/// Parse a `Name: value` header given on the command line
+pub fn parse_header(header: &str) -> Result<(HeaderName, HeaderValue)> {
+    let (name, value) = header
+        .split_once(':')
+        .with_context(|| format!("Header must look like 'Name: value', got '{}'", header))?;
+    let name = HeaderName::from_bytes(name.trim().as_bytes())
+        .with_context(|| format!("Invalid header name in '{}'", header))?;
+    let mut value = HeaderValue::from_str(value.trim())
+        .with_context(|| format!("Invalid header value in '{}'", header))?;
+    // Keep tokens out of any debug output
+    value.set_sensitive(true);
+    Ok((name, value))
+}
+
+/// HTTP client that sends the configured extra headers with every request
+/// Build it once per run and pass it around: its connection pool lets a batch
+/// reuse one connection to Ollama instead of opening a new one per request
+pub fn http_client(headers: &HeaderMap) -> Result<Client> {
+    Client::builder()
+        .default_headers(headers.clone())
+        .build()
+        .context("Failed to create HTTP client")
+}
+
+/// Error for an Ollama server that can't be reached at the given URL
+#[derive(Debug, Clone, PartialEq)]
+pub struct OllamaUnavailable(pub String);
+
+impl std::fmt::Display for OllamaUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Cannot connect to Ollama at {}. Please start Ollama first:\n\n\
+            1. If Ollama is not installed, visit: https://ollama.ai\n\
+            2. If Ollama is installed, start it with: ollama serve\n\
+            3. Then pull a model, for example: ollama pull llama3.2",
+            self.0
+        )
+    }
+}
+
+impl std::error::Error for OllamaUnavailable {}
+
 /// Detect which Ollama model to use
 /// First checks for running models, then falls back to available models
-pub fn detect_ollama_model() -> Result<String> {
-    let client = Client::new();
-
+/// When `deterministic`, running models are ignored and the installed model
+/// that sorts first by name is used, so the choice doesn't depend on what
+/// happened to be loaded
+pub fn detect_ollama_model(base_url: &str, client: &Client, deterministic: bool) -> Result<String> {
     // Try to connect to Ollama first
     let health_check = client
-        .get("http://localhost:11434/api/tags")
+        .get(endpoint(base_url, "tags"))
         .send();
 
     if health_check.is_err() {
-        anyhow::bail!(
-            "Cannot connect to Ollama. Please start Ollama first:\n\n\
-            1. If Ollama is not installed, visit: https://ollama.ai\n\
-            2. If Ollama is installed, start it with: ollama serve\n\
-            3. Then pull a model, for example: ollama pull llama3.2"
-        );
+        return Err(OllamaUnavailable(base_url.to_string()).into());
     }
 
     // First, try to find a running model
-    if let Ok(response) = client
-        .get("http://localhost:11434/api/ps")
-        .send()
-    {
+    let running = if deterministic {
+        None
+    } else {
+        client.get(endpoint(base_url, "ps")).send().ok()
+    };
+    if let Some(response) = running {
         if response.status().is_success() {
             if let Ok(running_models) = response.json::<RunningModelsResponse>() {
                 if !running_models.models.is_empty() {
@@ -76,8 +253,29 @@ pub fn detect_ollama_model() -> Result<String> {
     }
 
     // If no models are running, check available models and load the first one
+    let mut names = installed_models(base_url, client)?;
+    if names.is_empty() {
+        anyhow::bail!(
+            "No Ollama models are installed. Please install a model first:\n\n\
+            For example:\n\
+            - ollama pull llama3.2\n\
+            - ollama pull llama3.2-vision\n\
+            - ollama pull mistral\n\n\
+            Visit https://ollama.ai/library for more models"
+        );
+    }
+
+    // Return the first available model
+    if deterministic {
+        names.sort();
+    }
+    Ok(names.swap_remove(0))
+}
+
+/// Names of the models installed in Ollama
+pub fn installed_models(base_url: &str, client: &Client) -> Result<Vec<String>> {
     let response = client
-        .get("http://localhost:11434/api/tags")
+        .get(endpoint(base_url, "tags"))
         .send()
         .context("Failed to get available models from Ollama")?;
 
@@ -88,56 +286,197 @@ pub fn detect_ollama_model() -> Result<String> {
     let available_models: AvailableModelsResponse = response
         .json()
         .context("Failed to parse available models response")?;
+    Ok(available_models.models.into_iter().map(|model| model.name).collect())
+}
 
-    if available_models.models.is_empty() {
-        anyhow::bail!(
-            "No Ollama models are installed. Please install a model first:\n\n\
-            For example:\n\
-            - ollama pull llama3.2\n\
-            - ollama pull llama3.2-vision\n\
-            - ollama pull mistral\n\n\
-            Visit https://ollama.ai/library for more models"
-        );
+/// Names of the models Ollama currently has loaded in memory
+pub fn running_models(base_url: &str, client: &Client) -> Result<Vec<String>> {
+    let response = client
+        .get(endpoint(base_url, "ps"))
+        .send()
+        .map_err(|_| OllamaUnavailable(base_url.to_string()))?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("Ollama API returned error status: {}", response.status());
     }
 
-    // Return the first available model
-    Ok(available_models.models[0].name.clone())
+    let running: RunningModelsResponse = response
+        .json()
+        .context("Failed to parse running models response")?;
+    Ok(running.models.into_iter().map(|model| model.name).collect())
+}
+
+/// Version of the Ollama server (e.g. "0.5.7"), or None if it doesn't say
+pub fn server_version(base_url: &str, client: &Client) -> Result<Option<String>> {
+    let response = client
+        .get(endpoint(base_url, "version"))
+        .send()
+        .map_err(|_| OllamaUnavailable(base_url.to_string()))?;
+
+    if !response.status().is_success() {
+        return Ok(None);
+    }
+    let version: VersionResponse = response.json().context("Failed to parse Ollama version response")?;
+    Ok(Some(version.version))
+}
+
+/// Whether an Ollama version supports `format: "json"`
+/// Development builds ("0.0.0") and versions that don't parse are assumed to be recent
+pub fn supports_json_format(version: &str) -> bool {
+    let release = version.trim().trim_start_matches('v');
+    let release = release.split(['-', '+']).next().unwrap_or_default();
+    let parts: Option<Vec<u32>> = release.split('.').map(|part| part.parse().ok()).collect();
+    match parts.as_deref() {
+        Some([0, 0, 0]) => true,
+        Some([major, minor, patch]) => (*major, *minor, *patch) >= MIN_JSON_FORMAT_VERSION,
+        _ => true,
+    }
 }
 
 /// Extract paper metadata using Ollama LLM
-pub fn extract_metadata_with_ollama(pdf_text: &str, model: &str) -> Result<PaperMetadata> {
-    let client = Client::new();
+/// `title_hint` is a likely title found from the page layout, given to the
+/// model as a grounding signal
+pub fn extract_metadata_with_ollama(
+    pdf_text: &str,
+    title_hint: Option<&str>,
+    base_url: &str,
+    model: &str,
+    options: &ExtractOptions,
+) -> Result<PaperMetadata> {
+    let template = match &options.prompt_template {
+        Some(template) => template.clone(),
+        None => metadata_prompt(options),
+    };
+    let hint = match title_hint {
+        Some(title) => format!(
+            "\nLikely title (the largest text on the title page; use it unless it is clearly not the title):\n{}\n",
+            title
+        ),
+        None => String::new(),
+    };
+    // The paper text goes in last, so nothing in it is taken for a marker
+    let prompt = template.replacen(TITLE_HINT, &hint, 1).replacen(PAPER_TEXT, pdf_text, 1);
 
-    let prompt = format!(
+    generate_metadata(prompt, base_url, model, options)
+}
+
+/// Stand in for the title hint and the paper text in a prompt from `metadata_prompt`
+pub const TITLE_HINT: &str = "{title_hint}";
+pub const PAPER_TEXT: &str = "{paper_text}";
+
+/// The prompt `extract_metadata_with_ollama` sends, with `TITLE_HINT` and
+/// `PAPER_TEXT` where the page-specific parts go
+pub fn metadata_prompt(options: &ExtractOptions) -> String {
+    // The abstract is long, so it is only requested when it will be used
+    let (abstract_field, abstract_rule) = if options.include_abstract {
+        (
+            ",\n  \"abstract\": \"Full abstract text\"",
+            "\n- For abstract: copy the paper's abstract verbatim, or use an empty string if there is none",
+        )
+    } else {
+        ("", "")
+    };
+
+    let (publisher_field, publisher_rule) = if options.include_publisher {
+        (
+            ",\n  \"publisher\": \"Publisher\"",
+            "\n- For publisher: the publishing organization (e.g. ACM, IEEE, Springer) if the page names one, or an empty string",
+        )
+    } else {
+        ("", "")
+    };
+
+    let (evidence_field, evidence_rule) = if options.include_evidence {
+        (
+            ",\n  \"evidence\": {\"first_author\": \"...\", \"year\": \"...\", \"title\": \"...\"}",
+            "\n- For evidence: for each field, say in a few words where you found it (e.g. \"largest heading on page 1\", \"copyright line\")",
+        )
+    } else {
+        ("", "")
+    };
+
+    format!(
         r#"You are analyzing the first page of an academic paper. Extract the following information and respond ONLY with valid JSON in this exact format:
 {{
   "first_author": "LastName",
+  "first_author_full": "FirstName LastName",
+  "year": "YYYY",
+  "title": "Full Paper Title"{}{}{}
+}}
+
+Rules:
+- For first_author: extract ONLY the last name of the first author
+- For first_author_full: extract the complete name of the first author as written
+- For year: extract the publication year as a 4-digit number
+- For title: extract the complete paper title{}{}{}
+- Respond with ONLY the JSON, no other text
+{}
+Paper text:
+{}
+
+JSON response:"#,
+        publisher_field,
+        abstract_field,
+        evidence_field,
+        publisher_rule,
+        abstract_rule,
+        evidence_rule,
+        TITLE_HINT,
+        PAPER_TEXT
+    )
+}
+
+/// Extract paper metadata from a citation string (e.g. copied from a web page)
+/// The citation is usually the whole answer, so no page-layout hint is given
+pub fn extract_metadata_from_citation(
+    citation: &str,
+    base_url: &str,
+    model: &str,
+    options: &ExtractOptions,
+) -> Result<PaperMetadata> {
+    let prompt = format!(
+        r#"You are reading a citation of an academic paper, in any style (APA, MLA, BibTeX, a reference list entry, ...). Extract the following information and respond ONLY with valid JSON in this exact format:
+{{
+  "first_author": "LastName",
+  "first_author_full": "FirstName LastName",
   "year": "YYYY",
   "title": "Full Paper Title"
 }}
 
 Rules:
 - For first_author: extract ONLY the last name of the first author
+- For first_author_full: the first author's name as written, with the first name first
 - For year: extract the publication year as a 4-digit number
-- For title: extract the complete paper title
+- For title: extract the complete paper title, without the venue, volume or pages
+- Use an empty string for anything the citation doesn't contain
 - Respond with ONLY the JSON, no other text
 
-Paper text:
+Citation:
 {}
 
 JSON response:"#,
-        pdf_text
+        citation
     );
 
+    generate_metadata(prompt, base_url, model, options)
+}
+
+/// Send a metadata extraction prompt to Ollama and parse its JSON answer
+fn generate_metadata(prompt: String, base_url: &str, model: &str, options: &ExtractOptions) -> Result<PaperMetadata> {
     let request = OllamaRequest {
         model: model.to_string(),
         prompt,
         stream: false,
-        format: "json".to_string(),
+        format: options.json_format.then(|| "json".to_string()),
+        options: options.deterministic.then_some(ModelOptions {
+            temperature: 0.0,
+            seed: 0,
+        }),
     };
 
-    let response = client
-        .post("http://localhost:11434/api/generate")
+    let response = options
+        .client
+        .post(endpoint(base_url, "generate"))
         .json(&request)
         .send()
         .context("Failed to send request to Ollama. Make sure Ollama is running (try: ollama serve)")?;
@@ -153,17 +492,172 @@ JSON response:"#,
         .json()
         .context("Failed to parse Ollama response")?;
 
-    let metadata: PaperMetadata = serde_json::from_str(&ollama_response.response)
-        .context("Failed to parse metadata from LLM response. The LLM may not have returned valid JSON.")?;
-
-    // Validate the extracted metadata
-    if metadata.first_author.is_empty() || metadata.year.is_empty() || metadata.title.is_empty() {
-        anyhow::bail!("LLM failed to extract all required metadata fields");
+    if options.show_response {
+        crate::ui::display_raw_response(&ollama_response.response);
     }
 
+    let metadata = match parse_metadata_response(&ollama_response.response, options.strict_json) {
+        Ok(metadata) => metadata,
+        Err(e) if options.dump_failed_response => {
+            // The parse error is what the user needs, so a failed save only adds a note
+            return Err(match dump_response(&ollama_response.response) {
+                Ok(path) => e.context(format!("Full response written to {}", path.display())),
+                Err(dump_error) => e.context(format!("The full response could not be saved: {:#}", dump_error)),
+            });
+        }
+        Err(e) => return Err(e),
+    };
+
+    // Empty fields are left for the caller, which may fill them in from the
+    // filename or by asking the user (see `empty_fields`)
     Ok(metadata)
 }
 
+/// Save an LLM response to a new, uniquely named file in the temp folder
+/// The file is created fresh, so a planted symlink isn't followed and
+/// concurrent runs don't overwrite each other's responses
+fn dump_response(response: &str) -> Result<PathBuf> {
+    let mut file = tempfile::Builder::new()
+        .prefix("paper-renamer-llm-response-")
+        .suffix(".txt")
+        .tempfile()
+        .context("Failed to create a file for the LLM response")?;
+    file.write_all(response.as_bytes())
+        .with_context(|| format!("Failed to write LLM response to {}", file.path().display()))?;
+    let (_, path) = file.keep().context("Failed to keep the LLM response file")?;
+    Ok(path)
+}
+
+/// Required fields the LLM left empty: "author", "year" and/or "title"
+pub fn empty_fields(metadata: &PaperMetadata) -> Vec<&'static str> {
+    [
+        ("author", &metadata.first_author),
+        ("year", &metadata.year),
+        ("title", &metadata.title),
+    ]
+    .into_iter()
+    .filter(|(_, value)| value.trim().is_empty())
+    .map(|(field, _)| field)
+    .collect()
+}
+
+/// Parse the metadata JSON out of an LLM response
+/// In strict mode the response must be exactly one JSON object. Otherwise
+/// markdown code fences and any text around the first `{...}` block are ignored,
+/// which salvages responses from chatty models at the cost of reproducibility
+pub fn parse_metadata_response(response: &str, strict: bool) -> Result<PaperMetadata> {
+    let json = if strict {
+        response.trim()
+    } else {
+        find_json_object(response).unwrap_or(response)
+    };
+
+    serde_json::from_str(json).with_context(|| {
+        let reason = if strict {
+            "The LLM did not return exactly one JSON object (--strict-json is set)."
+        } else {
+            "The LLM may not have returned valid JSON."
+        };
+        format!(
+            "Failed to parse metadata from LLM response. {} Response was: {}",
+            reason,
+            preview(response)
+        )
+    })
+}
+
+/// Shorten a response for an error message, keeping it on one line
+fn preview(response: &str) -> String {
+    let flat = response.split_whitespace().collect::<Vec<_>>().join(" ");
+    if flat.chars().count() > RESPONSE_PREVIEW_CHARS {
+        let cut: String = flat.chars().take(RESPONSE_PREVIEW_CHARS).collect();
+        format!("\"{}...\"", cut)
+    } else {
+        format!("\"{}\"", flat)
+    }
+}
+
+/// Find the first balanced `{...}` block in a string, skipping braces inside JSON strings
+fn find_json_object(text: &str) -> Option<&str> {
+    let start = text.find('{')?;
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (offset, c) in text[start..].char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&text[start..start + offset + 1]);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Check that the author field is a name rather than a sentence or author list
+/// Counts whitespace-separated words before sanitizing
+pub fn check_author(author: &str, max_tokens: usize) -> Result<()> {
+    let tokens = author.split_whitespace().count();
+    if tokens > max_tokens {
+        anyhow::bail!(
+            "Extracted author '{}' has {} words (limit {}); the LLM likely returned more than a last name",
+            author,
+            tokens,
+            max_tokens
+        );
+    }
+    Ok(())
+}
+
+/// Check that a year is a 4-digit number
+pub fn is_valid_year(year: &str) -> bool {
+    year.len() == 4 && year.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Whether a valid year falls outside the --min-year/--max-year window, both ends
+/// included; years that aren't 4 digits are left to `implausible_fields`
+pub fn year_out_of_range(year: &str, min_year: Option<u32>, max_year: Option<u32>) -> bool {
+    if !is_valid_year(year) {
+        return false;
+    }
+    let Ok(year) = year.parse::<u32>() else {
+        return false;
+    };
+    min_year.is_some_and(|min| year < min) || max_year.is_some_and(|max| year > max)
+}
+
+/// Fields ("author", "year", "title") that don't look trustworthy enough to
+/// rename without a human reviewing them: metadata is plausible with a 4-digit
+/// year, a title with real words and an author with letters
+pub fn implausible_fields(metadata: &PaperMetadata) -> Vec<&'static str> {
+    let year_ok = is_valid_year(&metadata.year);
+    let title_ok = metadata.title.chars().filter(|c| c.is_alphanumeric()).count() >= 3;
+    let author_ok = metadata.first_author.chars().any(|c| c.is_alphabetic());
+
+    [("author", author_ok), ("year", year_ok), ("title", title_ok)]
+        .into_iter()
+        .filter(|(_, ok)| !ok)
+        .map(|(field, _)| field)
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -175,5 +669,245 @@ mod tests {
         assert_eq!(metadata.first_author, "Smith");
         assert_eq!(metadata.year, "2020");
         assert_eq!(metadata.title, "Deep Learning");
+        assert_eq!(metadata.abstract_text, "");
+
+        let json = r#"{"first_author": "Smith", "year": "2020", "title": "Deep Learning", "abstract": "We study..."}"#;
+        let metadata: PaperMetadata = serde_json::from_str(json).unwrap();
+        assert_eq!(metadata.abstract_text, "We study...");
+    }
+
+    #[test]
+    fn test_evidence_is_lenient() {
+        let json = r#"{"first_author": "Smith", "year": "2020", "title": "Deep Learning",
+            "evidence": {"title": "largest heading on page 1", "year": 2020, "first_author": null}}"#;
+        let metadata: PaperMetadata = serde_json::from_str(json).unwrap();
+        assert_eq!(metadata.evidence["title"], "largest heading on page 1");
+        assert_eq!(metadata.evidence["year"], "2020");
+        assert!(!metadata.evidence.contains_key("first_author"));
+
+        let json = r#"{"first_author": "Smith", "year": "2020", "title": "Deep Learning", "evidence": "page 1"}"#;
+        let metadata: PaperMetadata = serde_json::from_str(json).unwrap();
+        assert!(metadata.evidence.is_empty());
+    }
+
+    #[test]
+    fn test_parse_metadata_response_fenced() {
+        let response = "```json\n{\"first_author\": \"Smith\", \"year\": \"2020\", \"title\": \"Deep {Learning}\"}\n```";
+
+        let metadata = parse_metadata_response(response, false).unwrap();
+        assert_eq!(metadata.first_author, "Smith");
+        assert_eq!(metadata.title, "Deep {Learning}");
+
+        assert!(parse_metadata_response(response, true).is_err());
+    }
+
+    #[test]
+    fn test_parse_metadata_response_surrounding_text() {
+        let response = "Here is the metadata: {\"first_author\": \"Smith\", \"year\": \"2020\", \"title\": \"Deep Learning\"} Hope this helps!";
+        assert!(parse_metadata_response(response, false).is_ok());
+        assert!(parse_metadata_response(response, true).is_err());
+
+        let exact = "{\"first_author\": \"Smith\", \"year\": \"2020\", \"title\": \"Deep Learning\"}";
+        assert!(parse_metadata_response(exact, true).is_ok());
+    }
+
+    #[test]
+    fn test_parse_failure_shows_response() {
+        let error = parse_metadata_response("Sorry, I can't\nread this paper.", false).unwrap_err();
+        assert!(format!("{:#}", error).contains("Response was: \"Sorry, I can't read this paper.\""));
+
+        let long = "x".repeat(1000);
+        let error = format!("{:#}", parse_metadata_response(&long, false).unwrap_err());
+        assert!(error.contains(&format!("\"{}...\"", "x".repeat(RESPONSE_PREVIEW_CHARS))));
+        assert!(!error.contains(&long));
+    }
+
+    #[test]
+    fn test_endpoint() {
+        assert_eq!(endpoint(DEFAULT_OLLAMA_URL, "tags"), "http://localhost:11434/api/tags");
+        assert_eq!(endpoint("http://gpu-box:11434/", "generate"), "http://gpu-box:11434/api/generate");
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let (name, value) = parse_header("Authorization: Bearer xyz").unwrap();
+        assert_eq!(name, "authorization");
+        assert_eq!(value, "Bearer xyz");
+
+        let (name, value) = parse_header("X-Api-Key:abc:def").unwrap();
+        assert_eq!(name, "x-api-key");
+        assert_eq!(value, "abc:def");
+
+        assert!(parse_header("Authorization Bearer xyz").is_err());
+        assert!(parse_header("Bad Name: value").is_err());
+    }
+
+    #[test]
+    fn test_check_author() {
+        assert!(check_author("Smith", DEFAULT_MAX_AUTHOR_TOKENS).is_ok());
+        assert!(check_author("van der Waals", DEFAULT_MAX_AUTHOR_TOKENS).is_ok());
+        assert!(check_author(
+            "Smith, Jones, Brown, Lee, Garcia and many others",
+            DEFAULT_MAX_AUTHOR_TOKENS
+        )
+        .is_err());
+        assert!(check_author("van der Waals", 2).is_err());
+    }
+
+    #[test]
+    fn test_request_options_only_when_deterministic() {
+        let mut request = OllamaRequest {
+            model: "llama3.2".to_string(),
+            prompt: String::new(),
+            stream: false,
+            format: Some("json".to_string()),
+            options: None,
+        };
+        assert!(!serde_json::to_string(&request).unwrap().contains("options"));
+
+        request.options = Some(ModelOptions { temperature: 0.0, seed: 0 });
+        let json: serde_json::Value = serde_json::to_value(&request).unwrap();
+        assert_eq!(json["options"]["temperature"], 0.0);
+        assert_eq!(json["options"]["seed"], 0);
+
+        // Servers too old for JSON mode get no format field at all
+        request.format = None;
+        assert!(!serde_json::to_string(&request).unwrap().contains("format"));
+    }
+
+    #[test]
+    fn test_supports_json_format() {
+        assert!(supports_json_format("0.5.7"));
+        assert!(supports_json_format("0.1.9"));
+        assert!(supports_json_format("v0.1.10-rc1"));
+        assert!(!supports_json_format("0.1.8"));
+        assert!(!supports_json_format("0.0.19"));
+        // Development builds and unknown schemes are given the benefit of the doubt
+        assert!(supports_json_format("0.0.0"));
+        assert!(supports_json_format("custom"));
+    }
+
+    #[test]
+    fn test_empty_fields() {
+        let mut metadata = PaperMetadata {
+            first_author: "Smith".to_string(),
+            year: "2020".to_string(),
+            title: "Deep Learning".to_string(),
+            ..PaperMetadata::default()
+        };
+        assert!(empty_fields(&metadata).is_empty());
+
+        metadata.first_author = " ".to_string();
+        metadata.title = String::new();
+        assert_eq!(empty_fields(&metadata), vec!["author", "title"]);
+    }
+
+    #[test]
+    fn test_implausible_fields() {
+        let mut metadata = PaperMetadata {
+            first_author: "Smith".to_string(),
+            first_author_full: "John Smith".to_string(),
+            year: "2020".to_string(),
+            title: "Deep Learning".to_string(),
+            version: String::new(),
+            ..PaperMetadata::default()
+        };
+        assert!(implausible_fields(&metadata).is_empty());
+
+        metadata.year = "n.d.".to_string();
+        assert_eq!(implausible_fields(&metadata), vec!["year"]);
+    }
+
+    #[test]
+    fn test_year_out_of_range() {
+        let window = (Some(2010), Some(2020));
+        // Both boundary years are accepted
+        assert!(!year_out_of_range("2010", window.0, window.1));
+        assert!(!year_out_of_range("2020", window.0, window.1));
+        assert!(year_out_of_range("2009", window.0, window.1));
+        assert!(year_out_of_range("2021", window.0, window.1));
+
+        // An open end accepts everything on that side
+        assert!(!year_out_of_range("1905", None, Some(2020)));
+        assert!(year_out_of_range("1905", Some(1950), None));
+        assert!(!year_out_of_range("2024", None, None));
+
+        // Years that aren't 4 digits are a different problem
+        assert!(!year_out_of_range("n.d.", window.0, window.1));
+    }
+
+    /// Fake Ollama server that answers each request with the next body, all
+    /// on a single connection; a client that reconnects gets no answer
+    fn serve_one_connection(bodies: Vec<&'static str>) -> String {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            for body in bodies {
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("content-length:") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+                reader.read_exact(&mut vec![0; length]).unwrap();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn test_client_reuses_connection() {
+        let url = serve_one_connection(vec![
+            r#"{"models": [{"name": "llama3.2"}]}"#,
+            r#"{"response": "{\"first_author\": \"Smith\", \"year\": \"2020\", \"title\": \"Deep Learning\"}"}"#,
+        ]);
+        let options = ExtractOptions {
+            client: Client::builder().timeout(std::time::Duration::from_secs(5)).build().unwrap(),
+            ..ExtractOptions::default()
+        };
+
+        assert_eq!(installed_models(&url, &options.client).unwrap(), vec!["llama3.2"]);
+        let metadata = extract_metadata_with_ollama("Deep Learning", None, &url, "llama3.2", &options).unwrap();
+        assert_eq!(metadata.first_author, "Smith");
+    }
+
+    #[test]
+    fn test_dump_response() {
+        let first = dump_response("not json").unwrap();
+        let second = dump_response("not json either").unwrap();
+        assert_ne!(first, second);
+        assert_eq!(std::fs::read_to_string(&first).unwrap(), "not json");
+        std::fs::remove_file(first).unwrap();
+        std::fs::remove_file(second).unwrap();
+    }
+
+    #[test]
+    fn test_metadata_prompt() {
+        let options = ExtractOptions {
+            include_abstract: true,
+            ..ExtractOptions::default()
+        };
+        let prompt = metadata_prompt(&options);
+        assert_eq!(prompt.matches(TITLE_HINT).count(), 1);
+        assert_eq!(prompt.matches(PAPER_TEXT).count(), 1);
+        assert!(prompt.ends_with("JSON response:"));
+        assert!(prompt.contains("\"abstract\""));
+        assert!(!metadata_prompt(&ExtractOptions::default()).contains("abstract"));
     }
 }
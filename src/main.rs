@@ -1,140 +1,2439 @@
+mod compare;
+mod config;
+mod dedupe;
+mod download;
+mod examples;
 mod filename;
+mod folders;
+mod history;
+mod hook;
+mod index;
+mod infer;
+mod json;
 mod llm;
 mod pdf;
+mod preview;
+mod provenance;
 mod renamer;
+mod report;
+mod resume;
+mod scan;
+mod selftest;
+mod sources;
+mod summary;
+#[macro_use]
 mod ui;
+mod watch;
 
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use std::collections::HashSet;
 
 #[derive(Parser, Debug)]
 #[command(name = "paper-renamer")]
 #[command(about = "Automatically rename academic paper PDFs using LLM-extracted metadata", long_about = None)]
+#[command(subcommand_negates_reqs = true)]
+#[command(after_help = "Examples:
+  paper-renamer paper.pdf                            Rename one paper interactively
+  paper-renamer papers/*.pdf                         Review and rename a whole folder at once
+  paper-renamer --format \"{year}-{title}\" paper.pdf  Use a custom filename layout
+  paper-renamer examples --format \"{year}-{title}\"   Preview a layout on sample papers")]
 struct Args {
-    /// Path to the PDF file to rename
-    #[arg(value_name = "FILE")]
-    file_path: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path(s) to the PDF file(s) to rename, or http(s) URL(s) to download them from
+    #[arg(value_name = "FILE", required_unless_present_any = ["watch", "folders", "show_original"])]
+    file_paths: Vec<String>,
+
+    /// Filename template, or the preset "citekey". Available placeholders: {author}, {first_author_full}, {year}, {title}, {title_word}, {version}, {category}, {type}, {publisher}, {doi}
+    #[arg(long, global = true, value_name = "TEMPLATE", env = "PAPER_RENAMER_FORMAT", default_value = filename::DEFAULT_FORMAT)]
+    format: String,
+
+    /// Ollama model to use (detected automatically if not set)
+    #[arg(long, env = "PAPER_RENAMER_MODEL", global = true)]
+    model: Option<String>,
+
+    /// Address of the Ollama server
+    #[arg(long, value_name = "URL", env = "PAPER_RENAMER_OLLAMA_URL", default_value = llm::DEFAULT_OLLAMA_URL, global = true)]
+    ollama_url: String,
+
+    /// Extra HTTP header for every Ollama request, as "Name: value" (repeatable),
+    /// e.g. "Authorization: Bearer <token>" for an authenticating proxy
+    #[arg(long, value_name = "HEADER", global = true)]
+    ollama_header: Vec<String>,
+
+    /// Strip accents from the author name only, keeping them in the title
+    #[arg(long, global = true)]
+    strip_accents_in_author_only: bool,
+
+    /// Let the PDF's embedded metadata (Title, Author, CreationDate) win over the LLM
+    /// wherever it has a value
+    #[arg(long, conflicts_with = "prefer_llm_over_embedded")]
+    prefer_embedded_over_llm: bool,
+
+    /// Let the LLM win over the PDF's embedded metadata, which then only fills
+    /// fields the LLM left empty (the default)
+    #[arg(long)]
+    prefer_llm_over_embedded: bool,
+
+    /// Take the title from the PDF's embedded metadata and the author and year from the
+    /// LLM, for publisher PDFs with an exact Title but a whole author list as Author
+    #[arg(long, conflicts_with_all = ["prefer_embedded_over_llm", "prefer_llm_over_embedded"])]
+    hybrid: bool,
+
+    /// When the LLM and the embedded metadata give the same title at different lengths
+    /// (e.g. with and without the subtitle), keep the one giving the shortest or the most
+    /// complete filename instead of following the source precedence
+    #[arg(long, value_name = "CHOICE", value_enum, global = true)]
+    name_tie_break: Option<filename::TieBreak>,
+
+    /// Same as --name-tie-break shortest
+    #[arg(long, conflicts_with = "name_tie_break", global = true)]
+    prefer_shorter_name: bool,
+
+    /// When the LLM, the embedded metadata, the outline and the largest heading propose
+    /// different titles, list them all and let you pick one or type your own
+    #[arg(long, conflicts_with_all = ["yes", "json", "watch", "dry_run"])]
+    interactive_title_selection: bool,
+
+    /// When confirming a rename, also offer to open the LLM prompt in $EDITOR,
+    /// change it and extract the metadata again from the same text
+    #[arg(long, conflicts_with_all = ["yes", "json", "watch", "dry_run"])]
+    prompt_debug: bool,
+
+    /// Make runs reproducible: sample with temperature 0 and a fixed seed, pick the
+    /// model by name instead of by what is loaded, and process files in sorted order
+    #[arg(long)]
+    deterministic: bool,
+
+    /// Require the LLM to answer with exactly one JSON object instead of
+    /// salvaging the first {...} block from a chatty or code-fenced response
+    #[arg(long)]
+    strict_json: bool,
+
+    /// Reject extracted author names longer than this many words
+    #[arg(long, value_name = "N", default_value_t = llm::DEFAULT_MAX_AUTHOR_TOKENS)]
+    max_author_tokens: usize,
+
+    /// Cut the title part of the filename to at most N characters, at a word boundary
+    #[arg(long, value_name = "N", global = true)]
+    title_max_length: Option<usize>,
+
+    /// When --title-max-length cuts a title short, end it with TOKEN to show it was cut
+    /// (e.g. deep-learning-survey-etc.pdf)
+    #[arg(long, value_name = "TOKEN", num_args = 0..=1, default_missing_value = "etc", global = true)]
+    title_word_limit_with_ellipsis_token: Option<String>,
+
+    /// Drop one-letter words (e.g. "a") from the title part of the filename
+    #[arg(long, global = true)]
+    drop_single_char_words: bool,
+
+    /// Drop a leading article from the title part of the filename when at least two words
+    /// follow; optionally give your own comma-separated list of articles
+    #[arg(long, value_name = "ARTICLES", num_args = 0..=1, default_missing_value = filename::DEFAULT_LEADING_ARTICLES, global = true)]
+    strip_leading_articles: Option<String>,
+
+    /// How to write the year in the filename: full (2017), short (17), or a pattern
+    /// using {yyyy} and {yy} (e.g. "ad{yyyy}"); years that aren't 4 digits are kept as they are
+    #[arg(long, value_name = "FORMAT", default_value = "full", global = true)]
+    year_format: String,
+
+    /// Drop numeric-only words from the end of the title part of the filename
+    #[arg(long, global = true)]
+    trim_trailing_numbers: bool,
+
+    /// Start every filename with the first N hex characters of the file's SHA-256 hash
+    /// (e.g. `a1b2c3-smith-2020-title.pdf`), for stable names in a content-addressed library
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=64), global = true)]
+    hash_prefix: Option<u8>,
+
+    /// Shorten the title part of the filename to the first letters of its significant words
+    /// ("Attention Is All You Need" becomes "aiayn"); titles under three such words are kept
+    #[arg(long, global = true)]
+    title_acronym: bool,
+
+    /// Rejoin title words split by a hyphen at a line break ("Neu- ral" becomes "Neural")
+    /// and collapse runs of whitespace
+    #[arg(long, global = true)]
+    normalize_whitespace_in_title: bool,
+
+    /// Find/replace applied to the title before it is sanitized, as "pattern=>replacement"
+    /// (repeatable, applied in order); prefix the pattern with "re:" for a regex
+    #[arg(long, value_name = "RULE", global = true)]
+    replace_in_title: Vec<String>,
+
+    /// Spell out Greek letters and math symbols in the title part of the filename
+    /// ("α-divergence" becomes alpha-divergence); FILE is a TOML map ("α" = "alpha")
+    /// extending or overriding the built-in one
+    #[arg(long, value_name = "FILE", num_args = 0..=1, default_missing_value = "", global = true, alias = "title-transliteration-map")]
+    translit_map: Option<String>,
+
+    /// Longest filename to generate, in bytes; longer names are shortened at a word boundary
+    #[arg(long, value_name = "BYTES", global = true, default_value_t = filename::DEFAULT_MAX_FILENAME_BYTES)]
+    max_filename_bytes: usize,
+
+    /// Only accept filenames (including the extension) matching this regex, e.g. your
+    /// library's naming policy; other names are re-prompted, or fail the file in a batch
+    #[arg(long, value_name = "REGEX", global = true)]
+    require_match: Option<String>,
+
+    /// Keep the original extension's letter case (e.g. .PDF) instead of writing .pdf
+    #[arg(long)]
+    keep_extension_case: bool,
+
+    /// Ask the LLM where it found each field and show it with the extracted metadata
+    #[arg(long)]
+    explain: bool,
+
+    /// Also extract the abstract and save it next to the renamed PDF as <name>.abstract.txt
+    #[arg(long)]
+    write_abstract: bool,
+
+    /// Pipe each proposed filename and its metadata (as JSON) to this shell command
+    /// and use the filename it prints instead; a non-zero exit skips the file
+    #[arg(long, value_name = "COMMAND")]
+    post_process_cmd: Option<String>,
+
+    /// When the LLM finds no valid year, use a year from the original filename
+    /// (e.g. `neurips2020_submission.pdf`)
+    #[arg(long)]
+    year_from_filename_fallback: bool,
+
+    /// Skip files whose extracted year is before YEAR, as a likely misextraction;
+    /// when confirming each file, warn and ask instead
+    #[arg(long, value_name = "YEAR")]
+    min_year: Option<u32>,
+
+    /// Skip files whose extracted year is after YEAR, as a likely misextraction;
+    /// when confirming each file, warn and ask instead
+    #[arg(long, value_name = "YEAR")]
+    max_year: Option<u32>,
+
+    /// Repair partly named files: keep the parts of the current name that match
+    /// --format and only fill in the missing ones; complete names are skipped
+    #[arg(long)]
+    fill_missing: bool,
+
+    /// With --fill-missing, keep the extracted title, in its original casing, as the title
+    /// in the index and JSON output, while the filename keeps the title from the existing name
+    #[arg(long, requires = "fill_missing")]
+    title_case_from_source: bool,
+
+    /// Resume an interrupted batch over the same files, skipping files already done
+    #[arg(long)]
+    resume: bool,
+
+    /// How to name papers whose title is in CJK, Arabic or Cyrillic script
+    #[arg(long, global = true, value_enum, env = "PAPER_RENAMER_NON_LATIN", default_value_t = filename::NonLatin::Keep)]
+    non_latin: filename::NonLatin,
+
+    /// Always skip the first page (e.g. a library cover sheet) when extracting text
+    #[arg(long, global = true)]
+    skip_cover: bool,
+
+    /// Treat PDFs with less extracted text than this (in characters) as unreadable
+    /// instead of letting the LLM guess from it
+    #[arg(long, value_name = "N", default_value_t = pdf::DEFAULT_MIN_TEXT_LENGTH, global = true)]
+    min_text_length: usize,
+
+    /// Treat extracted text as scrambled (a broken font mapping) when more than this share
+    /// of its words are implausible, between 0 and 1; 0 turns the check off
+    #[arg(long, value_name = "RATIO", default_value_t = pdf::DEFAULT_GIBBERISH_THRESHOLD, global = true)]
+    gibberish_threshold: f64,
+
+    /// Send about this many tokens of the paper's text to the LLM (estimated,
+    /// roughly four characters of English per token); 0 sends the whole text
+    #[arg(long, value_name = "N", default_value_t = pdf::DEFAULT_MAX_TOKENS, global = true)]
+    max_tokens: usize,
+
+    /// Only read this many pages from the start of each PDF, which keeps huge files
+    /// (e.g. supplementary data) fast; 0 reads the whole document
+    #[arg(long, value_name = "N", default_value_t = pdf::DEFAULT_MAX_PAGES, global = true)]
+    pages: u32,
+
+    /// Send the title page and the N-1 pages after it, each cut to --max-tokens on its own,
+    /// for titles and author lists that run onto the next page [default: the text is cut as a whole]
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), global = true)]
+    merge_pages: Option<u32>,
+
+    /// Ask the LLM up to N times per paper: while the author, year or title looks unreliable,
+    /// ask again with twice as much of the text (and pages) as the pass before
+    #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=6), global = true)]
+    context_passes: u32,
+
+    /// In batch mode, confirm every file with the interactive prompt instead of one bulk review
+    #[arg(long, conflicts_with = "yes")]
+    confirm_each: bool,
+
+    /// Only process papers of this type, detected from cues such as "Proceedings of" or
+    /// "PhD Thesis"; the others, and those whose type can't be told, are skipped
+    #[arg(long, value_name = "TYPE", value_enum, conflicts_with = "from_clipboard")]
+    only_type: Option<pdf::DocType>,
+
+    /// In batch mode, rename files whose metadata looks reliable without asking and
+    /// confirm only the others with the interactive prompt
+    #[arg(long, conflicts_with_all = ["yes", "confirm_each", "json", "watch", "dry_run"])]
+    confirm_low_confidence_only: bool,
+
+    /// Write a report of every file's outcome (Markdown, or CSV for a .csv path)
+    #[arg(long, value_name = "PATH")]
+    report: Option<String>,
+
+    /// Write aggregate statistics of the batch as one JSON object: file counts, total time,
+    /// and the number of extractions and average extraction time per model
+    #[arg(long, value_name = "PATH", conflicts_with = "watch")]
+    summary_json: Option<String>,
+
+    /// Copy each file to its new name and verify the copy's hash instead of renaming;
+    /// the original is kept unless --move is also given
+    #[arg(long, global = true)]
+    safe_mode: bool,
+
+    /// With --safe-mode, remove the original once the copy has been verified
+    #[arg(long = "move", global = true, requires = "safe_mode")]
+    move_original: bool,
+
+    /// Append every rename (timestamp, old and new path, model, status) to this log file
+    #[arg(long, value_name = "PATH", global = true)]
+    rename_log: Option<String>,
+
+    /// Format of the rename log
+    #[arg(long, value_enum, default_value_t, global = true, requires = "rename_log")]
+    rename_log_format: history::LogFormat,
+
+    /// Keep a JSON index of renamed files and their metadata, for later use with apply-index
+    #[arg(long, value_name = "PATH")]
+    index_out: Option<String>,
+
+    /// Show more detail: -v prints each raw LLM response, -vv also saves an
+    /// unparseable response to a file for inspection
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Rename without asking for confirmation
+    #[arg(short, long, global = true)]
+    yes: bool,
+
+    /// Headless mode for scripts (implies --yes): print one JSON object per file on
+    /// stdout and errors as JSON objects on stderr; progress messages go to stderr
+    #[arg(long, conflicts_with_all = ["watch", "confirm_each"])]
+    json: bool,
+
+    /// Watch a directory and automatically rename new PDFs as they appear (implies --yes)
+    #[arg(long, value_name = "DIR", conflicts_with_all = ["file_paths", "folders"])]
+    watch: Option<String>,
+
+    /// Rename the single PDF inside each subfolder of DIR (e.g. `Some Title/paper.pdf`)
+    #[arg(long, value_name = "DIR")]
+    folders: Option<String>,
+
+    /// After extraction, report files whose author, year and title match as probable
+    /// duplicates of the same paper, even if their contents differ
+    #[arg(long)]
+    dedupe_by_metadata: bool,
+
+    /// With --dedupe-by-metadata, rename only the first file of each duplicate group
+    /// and move the others into DIR
+    #[arg(long, value_name = "DIR", requires = "dedupe_by_metadata")]
+    quarantine_dir: Option<String>,
+
+    /// Move every file that could not be processed (unreadable PDF, no text, LLM failure)
+    /// into DIR, under its original name
+    #[arg(long, value_name = "DIR", conflicts_with = "watch")]
+    quarantine_failures: Option<String>,
+
+    /// What to do when the author, year or title can't be found: fail the file, ask for it,
+    /// use "unknown" in its place, or skip the file [default: prompt when confirming each
+    /// file, otherwise error]
+    #[arg(long, value_enum, value_name = "POLICY")]
+    on_missing_field: Option<MissingField>,
+
+    /// Take the author, year and title from a citation copied to the clipboard
+    /// instead of from the PDF's text (for a single PDF)
+    #[arg(long, conflicts_with_all = ["watch", "folders", "fill_missing"])]
+    from_clipboard: bool,
+
+    /// Use this first author (last name or full name) instead of the extracted one (for a single PDF)
+    #[arg(long, value_name = "NAME", conflicts_with = "watch")]
+    author: Option<String>,
+
+    /// Use this publication year instead of the extracted one (for a single PDF)
+    #[arg(long, value_name = "YEAR", conflicts_with = "watch")]
+    year: Option<String>,
+
+    /// Use this title instead of the extracted one (for a single PDF)
+    #[arg(long, value_name = "TITLE", conflicts_with = "watch")]
+    title: Option<String>,
+
+    /// Only extract and print each file's metadata (as JSON objects with --json),
+    /// without proposing or performing any rename
+    #[arg(long, conflicts_with_all = ["watch", "dry_run", "from_clipboard", "index_out"])]
+    extract_only: bool,
+
+    /// Plan every file and print one table of the proposed names and where their metadata
+    /// came from, flagging conflicts, skipped files and unreliable metadata, without renaming anything
+    #[arg(long, conflicts_with_all = ["watch", "resume"])]
+    dry_run: bool,
+
+    /// Before a batch, check Ollama, time one sample extraction, show the projected
+    /// total time and ask whether to go ahead
+    #[arg(long, conflicts_with_all = ["json", "watch"])]
+    preflight: bool,
+
+    /// With --folders, also rename each subfolder to match its PDF
+    #[arg(long, requires = "folders", conflicts_with = "safe_mode")]
+    rename_folders: bool,
+
+    /// Move each renamed file into a folder named after its first author, next to the
+    /// original (e.g. `smith/smith-2020-title.pdf`); unknown authors go to `unsorted/`
+    #[arg(long, conflicts_with = "rename_folders")]
+    author_subdir: bool,
+
+    /// Store each file's original name on the renamed file, in an extended attribute
+    /// (Linux, macOS) or an alternate data stream (Windows); see --show-original
+    #[arg(long, global = true)]
+    record_original: bool,
+
+    /// After each successful rename, also copy the renamed file into DIR (e.g. a
+    /// backup drive); an identical file already there is left as is
+    #[arg(long, value_name = "DIR", global = true)]
+    copy_on_success_to: Option<String>,
+
+    /// Print the original name stored on FILE by --record-original, then exit
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["file_paths", "watch", "folders"])]
+    show_original: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show the filenames a few sample papers would get with the current naming options
+    Examples,
+    /// Check that every PDF under DIR has extractable text, without calling the LLM
+    /// or renaming anything; lists the files that need OCR
+    Scan {
+        /// Directory to scan, including subdirectories
+        dir: String,
+    },
+    /// Rename the files in an --index-out index to match the current naming options,
+    /// reusing the stored metadata instead of extracting it again
+    ApplyIndex {
+        /// Index file written by --index-out
+        #[arg(long, value_name = "PATH")]
+        index: String,
+
+        /// Only show the renames that would be made
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Suggest a --format template from PDFs already named in your own scheme:
+    /// each paper's metadata is extracted and looked up in its current name
+    InferFormat {
+        /// Example PDFs, named the way you want new papers to be named
+        #[arg(value_name = "FILE", required = true)]
+        files: Vec<String>,
+    },
+    /// Extract the metadata of one PDF with several models and compare their answers
+    /// and latency side by side; nothing is renamed
+    CompareModels {
+        /// Ollama models to compare, separated by commas (e.g. llama3.2,qwen2.5:14b)
+        #[arg(long, value_name = "MODELS", value_delimiter = ',', required = true)]
+        models: Vec<String>,
+
+        /// PDF to extract the metadata from
+        #[arg(value_name = "FILE")]
+        file: String,
+    },
+    /// Render one page of a PDF to a PNG image, as the OCR and vision features see it
+    #[cfg(feature = "render")]
+    RenderPage {
+        /// PDF to render
+        #[arg(value_name = "FILE")]
+        file: String,
+
+        /// Page to render, starting at 1
+        #[arg(long, value_name = "N", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        page: u32,
+
+        /// Where to write the image
+        #[arg(long, value_name = "PATH", default_value = "page.png")]
+        output: String,
+    },
+    /// Check the naming pipeline on built-in sample data, without Ollama or a PDF,
+    /// to tell an installation problem from a model problem
+    Selftest,
+    /// Write a settings file listing every setting at its default, commented out,
+    /// to the user config path (e.g. ~/.config/paper-renamer/config.toml)
+    InitConfig {
+        /// Replace an existing settings file
+        #[arg(long)]
+        force: bool,
+    },
+}
+
+/// When the user is asked to confirm renames
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Confirmation {
+    /// Interactive prompt (with field edits) for every file
+    Each,
+    /// `Each` after the user answered "yes to all": the rest is planned unprompted
+    AcceptAll,
+    /// `Each` after the user answered "no to all": the rest is skipped
+    DeclineAll,
+    /// `Each` after the user chose "abort": nothing more is planned or renamed
+    Aborted,
+    /// One review of the whole batch after planning
+    Bulk,
+    /// Never ask; only plausible metadata is renamed
+    Never,
+    /// `Each` for files whose metadata looks unreliable; the rest is planned unprompted
+    LowConfidenceOnly,
+}
+
+impl Confirmation {
+    /// Whether someone is there to fix up a file's metadata in a prompt
+    fn prompts(self) -> bool {
+        matches!(self, Confirmation::Each | Confirmation::LowConfidenceOnly)
+    }
+}
+
+/// What to do when no source provides the author, year or title
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum MissingField {
+    /// Fail the file
+    Error,
+    /// Ask for the missing value
+    Prompt,
+    /// Use a literal placeholder ("unknown") in the name
+    Placeholder,
+    /// Leave the file as it is and go on with the batch
+    Skip,
+}
+
+/// Value used in names for a field no source provided, with --on-missing-field placeholder
+const MISSING_FIELD_PLACEHOLDER: &str = "unknown";
+
+/// Settings shared by every file processed in a run
+struct RunOptions {
+    model: String,
+    ollama_url: String,
+    text: pdf::TextOptions,
+    naming: filename::NamingOptions,
+    extract: llm::ExtractOptions,
+    confirmation: Confirmation,
+    keep_extension_case: bool,
+    post_process_cmd: Option<String>,
+    fill_missing: bool,
+    /// Keep the extracted title's casing when --fill-missing keeps the title from the filename
+    title_case_from_source: bool,
+    year_from_filename: bool,
+    /// Accepted years (--min-year, --max-year), both ends included
+    min_year: Option<u32>,
+    max_year: Option<u32>,
+    /// Why plan_file last skipped a file, when it wasn't simply declined
+    skip_reason: Option<&'static str>,
+    /// Files planned without asking under --confirm-low-confidence-only, for the summary
+    auto_planned: Vec<String>,
+    /// Skip papers of any other type (--only-type)
+    only_type: Option<pdf::DocType>,
+    transfer: renamer::Transfer,
+    precedence: sources::Precedence,
+    /// Pick the title among the sources' candidates (--interactive-title-selection)
+    select_title: bool,
+    /// Offer to edit the LLM prompt when confirming (--prompt-debug)
+    prompt_debug: bool,
+    show_sources: bool,
+    missing_field: MissingField,
+    /// Ids of the options given on the command line or through an environment
+    /// variable, which `.paper-renamer.toml` files don't override
+    explicit: HashSet<String>,
+    /// Citation read from the clipboard, used instead of the PDF's text
+    citation: Option<String>,
+    /// Fields given with --author, --year and --title
+    overrides: sources::FieldOverrides,
+    /// Run time and extraction timings, for --summary-json
+    usage: summary::Usage,
+    /// Most LLM queries per paper, each with more text, while the answer looks unreliable
+    context_passes: u32,
+    /// File each paper into a folder named after its first author
+    author_subdir: bool,
 }
 
 fn main() {
     if let Err(e) = run() {
-        ui::display_error(&format!("{:#}", e));
+        if ui::json_mode() {
+            json::print_error(None, json::error_kind(&e), &e);
+        } else {
+            ui::display_error(&format!("{:#}", e));
+        }
         std::process::exit(1);
     }
 }
 
+/// Ids of the options given on the command line or through an environment
+/// variable, which settings files don't override
+fn explicit_ids(matches: &clap::ArgMatches) -> HashSet<String> {
+    matches
+        .ids()
+        .filter(|id| {
+            matches!(
+                matches.value_source(id.as_str()),
+                Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+            )
+        })
+        .map(|id| id.to_string())
+        .collect()
+}
+
 fn run() -> Result<()> {
-    let args = Args::parse();
+    let usage = summary::Usage::start();
+    let matches = Args::command().get_matches();
+    let args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let explicit = explicit_ids(&matches);
+    ui::set_json_mode(args.json);
+
+    if let Some(path) = &args.show_original {
+        return match provenance::read_original(std::path::Path::new(path))? {
+            Some(original) => {
+                println!("{}", original);
+                Ok(())
+            }
+            None => anyhow::bail!("No original name recorded for {}", path),
+        };
+    }
+
+    // init-config doesn't read the user's settings, so it can replace a broken file;
+    // selftest runs on the defaults, so a broken file can't fail it
+    let user_config = match &args.command {
+        Some(Command::InitConfig { .. }) | Some(Command::Selftest) => None,
+        _ => config::user_config()?,
+    };
+
+    let format = filename::expand_preset(&args.format);
+    filename::validate_format(&format)?;
+
+    let mut naming = filename::NamingOptions {
+        format: format.clone(),
+        ascii_author: args.strip_accents_in_author_only,
+        non_latin: args.non_latin,
+        max_title_length: args.title_max_length,
+        truncation_token: args
+            .title_word_limit_with_ellipsis_token
+            .as_deref()
+            .map(filename::parse_truncation_token)
+            .transpose()?,
+        drop_single_char_words: args.drop_single_char_words,
+        leading_articles: args
+            .strip_leading_articles
+            .as_deref()
+            .map(filename::parse_leading_articles)
+            .transpose()?
+            .unwrap_or_default(),
+        trim_trailing_numbers: args.trim_trailing_numbers,
+        title_acronym: args.title_acronym,
+        hash_prefix: args.hash_prefix.map(usize::from),
+        max_filename_bytes: args.max_filename_bytes,
+        normalize_title_whitespace: args.normalize_whitespace_in_title,
+        title_replacements: args
+            .replace_in_title
+            .iter()
+            .map(|rule| filename::parse_title_replacement(rule))
+            .collect::<Result<_>>()?,
+        require_match: args
+            .require_match
+            .as_deref()
+            .map(|pattern| {
+                regex::Regex::new(pattern).with_context(|| format!("Invalid --require-match regex '{}'", pattern))
+            })
+            .transpose()?,
+        year_format: filename::parse_year_format(&args.year_format)?,
+        name_tie_break: if args.prefer_shorter_name {
+            Some(filename::TieBreak::Shortest)
+        } else {
+            args.name_tie_break
+        },
+        title_transliterations: match args.translit_map.as_deref() {
+            Some(path) => filename::load_translit_map(Some(path).filter(|path| !path.is_empty()))?,
+            None => Vec::new(),
+        },
+        ..filename::NamingOptions::default()
+    };
+    // The user's settings come before the defaults, and a collection's own
+    // .paper-renamer.toml before them; environment variables and the command
+    // line come before both
+    if let Some((path, config)) = &user_config {
+        say!("Using settings from {}", path.display());
+        config.apply(&mut naming, &explicit);
+        ui::set_messages(config.messages());
+    }
+    let format = naming.format.clone();
+
+    let transfer = match (args.safe_mode, args.move_original) {
+        (false, _) => renamer::Transfer::Rename,
+        (true, false) => renamer::Transfer::VerifiedCopy,
+        (true, true) => renamer::Transfer::VerifiedMove,
+    };
+
+    let rename_log = args.rename_log.clone().map(|path| history::RenameLog {
+        path,
+        format: args.rename_log_format,
+    });
+
+    if !(0.0..=1.0).contains(&args.gibberish_threshold) {
+        anyhow::bail!("--gibberish-threshold must be between 0 and 1, got {}", args.gibberish_threshold);
+    }
+    let text = pdf::TextOptions {
+        skip_cover: args.skip_cover,
+        min_text_length: args.min_text_length,
+        gibberish_threshold: args.gibberish_threshold,
+        max_tokens: args.max_tokens,
+        max_pages: args.pages,
+        merge_pages: args.merge_pages,
+    };
+
+    match &args.command {
+        Some(Command::InitConfig { force }) => {
+            let path = config::user_config_path()?;
+            config::write_default_config(&path, *force)?;
+            say!("Wrote default settings to {}", path.display());
+            say!("Uncomment and edit the settings you want to change.");
+            return Ok(());
+        }
+        Some(Command::Examples) => {
+            examples::print_examples(&naming);
+            return Ok(());
+        }
+        Some(Command::Selftest) => {
+            let checks = selftest::run_checks();
+            let failed = selftest::print_checks(&checks);
+            if failed > 0 {
+                anyhow::bail!("{} of {} self-test checks failed", failed, checks.len());
+            }
+            return Ok(());
+        }
+        #[cfg(feature = "render")]
+        Some(Command::RenderPage { file, page, output }) => {
+            let image = pdf::render_page(file, page - 1)?;
+            image.save(output).with_context(|| format!("Failed to write {}", output))?;
+            say!("Page {} of {} written to {} ({}x{})", page, file, output, image.width(), image.height());
+            return Ok(());
+        }
+        Some(Command::Scan { dir }) => {
+            let results = scan::scan_directory(dir, &text)?;
+            let problems = scan::print_scan(&results);
+            if problems > 0 {
+                anyhow::bail!("{} of {} PDFs have no usable text or could not be read", problems, results.len());
+            }
+            return Ok(());
+        }
+        Some(Command::ApplyIndex { index, dry_run }) => {
+            return apply_index(index, &args, &naming, transfer, rename_log.as_ref(), *dry_run);
+        }
+        // Need the Ollama options, so they run once those are set up
+        Some(Command::InferFormat { .. }) | Some(Command::CompareModels { .. }) | None => {}
+    }
+
+    // Each per-paper subfolder contributes its single PDF as another input
+    let paper_folders = match &args.folders {
+        Some(dir) => folders::find_papers(dir)?,
+        None => folders::PaperFolders::default(),
+    };
+    let mut file_paths: Vec<String> = args
+        .file_paths
+        .iter()
+        .chain(&paper_folders.papers)
+        .cloned()
+        .collect();
+    // Duplicate names are numbered in processing order, so fix that order
+    if args.deterministic {
+        file_paths.sort();
+        file_paths.dedup();
+    }
+
+    // A copied citation describes exactly one paper
+    let citation = if args.from_clipboard {
+        if file_paths.len() != 1 {
+            anyhow::bail!("--from-clipboard renames a single PDF, but {} were given", file_paths.len());
+        }
+        Some(ui::clipboard_text()?)
+    } else {
+        None
+    };
+
+    let overrides = sources::FieldOverrides {
+        author: args.author.clone(),
+        year: args.year.clone(),
+        title: args.title.clone(),
+    };
+    if overrides != sources::FieldOverrides::default() && file_paths.len() != 1 {
+        anyhow::bail!("--author, --year and --title apply to a single PDF, but {} were given", file_paths.len());
+    }
+    if let Some(year) = overrides.year.as_deref().filter(|year| !llm::is_valid_year(year.trim())) {
+        anyhow::bail!("--year must be four digits, e.g. 2020, got '{}'", year);
+    }
+    if let (Some(min_year), Some(max_year)) = (args.min_year, args.max_year) {
+        if min_year > max_year {
+            anyhow::bail!("--min-year {} is after --max-year {}", min_year, max_year);
+        }
+    }
+    for (option, value) in [("--author", &overrides.author), ("--title", &overrides.title)] {
+        if value.as_ref().is_some_and(|value| !value.chars().any(char::is_alphanumeric)) {
+            anyhow::bail!("{} needs at least one letter or digit", option);
+        }
+    }
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    for header in &args.ollama_header {
+        let (name, value) = llm::parse_header(header)?;
+        headers.append(name, value);
+    }
+    // One client for the whole run, so a batch reuses its connection to Ollama
+    let client = llm::http_client(&headers)?;
+
+    // Step 1: Detect which Ollama model to use, unless one was configured
+    // compare-models brings its own list of models
+    let configured_model = user_config
+        .as_ref()
+        .and_then(|(_, config)| config.model(&explicit))
+        .map(str::to_string)
+        .or_else(|| args.model.clone());
+    let model = match (configured_model, &args.command) {
+        (_, Some(Command::CompareModels { models, .. })) => models[0].clone(),
+        (Some(model), _) => {
+            say!("Using model: {}", model);
+            model
+        }
+        (None, _) => {
+            let spinner = ui::create_spinner("Detecting available Ollama model...");
+            let model = llm::detect_ollama_model(&args.ollama_url, &client, args.deterministic)
+                .context("Failed to detect Ollama model")?;
+            ui::finish_spinner(spinner, &format!("Using model: {}", model));
+            model
+        }
+    };
+
+    // JSON mode needs Ollama 0.1.9; older servers ignore or reject the field.
+    // An unreachable server is reported by the first request that needs it
+    let version = llm::server_version(&args.ollama_url, &client).ok().flatten();
+    let json_format = version.as_deref().is_none_or(llm::supports_json_format);
+    if let Some(version) = version.filter(|_| !json_format) {
+        ui::display_warning(&format!(
+            "Ollama {} is too old to constrain answers to JSON (needs {}.{}.{}); relying on lenient parsing",
+            version,
+            llm::MIN_JSON_FORMAT_VERSION.0,
+            llm::MIN_JSON_FORMAT_VERSION.1,
+            llm::MIN_JSON_FORMAT_VERSION.2
+        ));
+    }
+
+    // Watch mode has nobody to answer prompts
+    let confirmation = if args.dry_run {
+        // Nothing is renamed, so nothing needs confirming; the table is the review
+        Confirmation::Bulk
+    } else if args.yes || args.json || args.watch.is_some() {
+        Confirmation::Never
+    } else if args.confirm_low_confidence_only {
+        Confirmation::LowConfidenceOnly
+    } else if file_paths.len() > 1 && !args.confirm_each {
+        Confirmation::Bulk
+    } else {
+        Confirmation::Each
+    };
+    let missing_field = match args.on_missing_field {
+        Some(MissingField::Prompt) if confirmation == Confirmation::Never => {
+            anyhow::bail!("--on-missing-field prompt needs someone to answer; it can't be used with --yes, --json or --watch");
+        }
+        Some(policy) => policy,
+        None if confirmation.prompts() => MissingField::Prompt,
+        None => MissingField::Error,
+    };
+
+    let mut options = RunOptions {
+        model,
+        ollama_url: args.ollama_url.clone(),
+        text,
+        naming,
+        extract: llm::ExtractOptions {
+            strict_json: args.strict_json,
+            max_author_tokens: args.max_author_tokens,
+            include_abstract: args.write_abstract,
+            include_evidence: args.explain,
+            include_publisher: format.contains("{publisher}"),
+            client,
+            show_response: args.verbose >= 1,
+            dump_failed_response: args.verbose >= 2,
+            deterministic: args.deterministic,
+            json_format,
+            prompt_template: None,
+        },
+        confirmation,
+        keep_extension_case: args.keep_extension_case,
+        post_process_cmd: args.post_process_cmd.clone(),
+        fill_missing: args.fill_missing,
+        title_case_from_source: args.title_case_from_source,
+        year_from_filename: args.year_from_filename_fallback,
+        min_year: args.min_year,
+        max_year: args.max_year,
+        skip_reason: None,
+        auto_planned: Vec::new(),
+        only_type: args.only_type,
+        transfer,
+        precedence: if args.hybrid {
+            sources::Precedence::Hybrid
+        } else if args.prefer_embedded_over_llm {
+            sources::Precedence::Embedded
+        } else {
+            sources::Precedence::Llm
+        },
+        select_title: args.interactive_title_selection,
+        prompt_debug: args.prompt_debug,
+        show_sources: args.verbose >= 1,
+        missing_field,
+        explicit,
+        citation,
+        overrides,
+        usage,
+        context_passes: args.context_passes,
+        author_subdir: args.author_subdir,
+    };
+
+    match &args.command {
+        Some(Command::InferFormat { files }) => return infer_format(files, &options),
+        Some(Command::CompareModels { models, file }) => return compare_models(file, models, &options),
+        _ => {}
+    }
+
+    if args.extract_only {
+        return extract_only(&file_paths, &options);
+    }
+
+    if let Some(dir) = &args.watch {
+        say!("\nWatching {} for new PDFs (press Ctrl+C to stop)...", dir);
+        return watch::watch_directory(dir, |path| {
+            say!("\nNew PDF detected: {}", path.display());
+            let Some(plan) = plan_file(&path.to_string_lossy(), &mut options)? else {
+                return Ok(None);
+            };
+            let plans = [plan];
+            let renamed = apply(
+                &plans,
+                options.transfer,
+                args.record_original,
+                args.copy_on_success_to.as_deref(),
+                rename_log.as_ref(),
+                &options.model,
+            )?;
+            record_index(args.index_out.as_deref(), &plans, &renamed)?;
+            Ok(renamed.into_iter().next().map(|record| record.to))
+        });
+    }
+
+    let mut entries: Vec<report::Entry> = paper_folders
+        .skipped
+        .iter()
+        .map(|(folder, reason)| {
+            say!("\nSkipping folder {} ({})", folder, reason);
+            skipped(folder, reason)
+        })
+        .collect();
+    let mut failures = 0;
+
+    // URLs are downloaded into the current directory first and then handled
+    // like local files; a download that isn't renamed is removed at the end
+    let mut downloads = Vec::new();
+    let mut inputs = Vec::with_capacity(file_paths.len());
+    for input in &file_paths {
+        if !download::is_url(input) {
+            inputs.push(input.clone());
+            continue;
+        }
+
+        let spinner = ui::create_spinner(&format!("Downloading {}...", input));
+        match download::download_pdf(input) {
+            Ok(path) => {
+                ui::finish_spinner(spinner, &format!("Downloaded {}", input));
+                inputs.push(path.to_string_lossy().into_owned());
+                downloads.push(path);
+            }
+            Err(e) => {
+                spinner.finish_and_clear();
+                if file_paths.len() == 1 && args.report.is_none() && !args.json {
+                    return Err(e);
+                }
+
+                if args.json {
+                    json::print_error(Some(input), "download_failed", &e);
+                } else {
+                    ui::display_error(&format!("{:#}", e));
+                }
+                entries.push(report::Entry {
+                    source: input.clone(),
+                    status: report::Status::Failed,
+                    new_name: None,
+                    message: Some(format!("{:#}", e)),
+                });
+                failures += 1;
+                // Keep the URL in place so the report stays in input order
+                inputs.push(input.clone());
+            }
+        }
+    }
+
+    // Batches record their progress so an interrupted run can be resumed
+    let mut state = if inputs.len() > 1 && !args.dry_run {
+        Some(resume::BatchState::open(&inputs, args.resume)?)
+    } else {
+        None
+    };
+
+    if args.preflight {
+        let pending: Vec<&String> = inputs
+            .iter()
+            .filter(|input| !download::is_url(input))
+            .filter(|input| state.as_ref().and_then(|state| state.outcome(input)).is_none())
+            .collect();
+        if !preflight(&pending, &options)? {
+            ui::display_cancelled();
+            return Ok(());
+        }
+    }
+
+    // Plan phase: extract and confirm every file before touching the filesystem
+    let mut plans = Vec::new();
+    let mut failed_files = Vec::new();
+    for (index, file_path) in inputs.iter().enumerate() {
+        // A URL left in the inputs failed to download and was already reported
+        if download::is_url(file_path) {
+            continue;
+        }
+
+        match state.as_ref().and_then(|state| state.outcome(file_path)) {
+            Some(resume::Outcome::Planned {
+                new_filename,
+                subdir,
+                abstract_text,
+                metadata,
+            }) => {
+                plans.push(renamer::RenamePlan {
+                    source: file_path.clone(),
+                    new_filename,
+                    subdir,
+                    abstract_text,
+                    metadata: metadata.map(|metadata| *metadata),
+                    ..renamer::RenamePlan::default()
+                });
+                continue;
+            }
+            Some(_) => {
+                say!("\nSkipping {} (already done in an earlier run)", file_path);
+                entries.push(skipped(file_path, "already done in an earlier run"));
+                continue;
+            }
+            None => {}
+        }
+
+        if options.confirmation == Confirmation::DeclineAll {
+            if let Some(state) = state.as_mut() {
+                state.record(file_path, resume::Outcome::Skipped)?;
+            }
+            entries.push(skipped(file_path, "declined"));
+            continue;
+        }
+
+        match plan_file(file_path, &mut options) {
+            Ok(Some(plan)) => {
+                if let Some(state) = state.as_mut() {
+                    state.record(
+                        file_path,
+                        resume::Outcome::Planned {
+                            new_filename: plan.new_filename.clone(),
+                            subdir: plan.subdir.clone(),
+                            abstract_text: plan.abstract_text.clone(),
+                            metadata: plan.metadata.clone().map(Box::new),
+                        },
+                    )?;
+                }
+                plans.push(plan);
+            }
+            Ok(None) if options.confirmation == Confirmation::Aborted => {
+                return abort(&inputs[index..], &plans, entries, state, &args, &options.usage, &inputs);
+            }
+            Ok(None) => {
+                if let Some(state) = state.as_mut() {
+                    state.record(file_path, resume::Outcome::Skipped)?;
+                }
+                let reason = options.skip_reason.take().unwrap_or("declined");
+                entries.push(skipped(file_path, reason));
+            }
+            Err(e) if inputs.len() == 1 && args.report.is_none() && !args.json => return Err(e),
+            Err(e) => {
+                if args.json {
+                    json::print_error(Some(file_path), json::error_kind(&e), &e);
+                } else {
+                    ui::display_error(&format!("{}: {:#}", file_path, e));
+                }
+                entries.push(report::Entry {
+                    source: file_path.clone(),
+                    status: report::Status::Failed,
+                    new_name: None,
+                    message: Some(format!("{:#}", e)),
+                });
+                failures += 1;
+                failed_files.push(file_path.clone());
+            }
+        }
+    }
+
+    // Probable duplicates: the first file of each group is renamed as usual,
+    // the others are only reported or, with --quarantine-dir, set aside
+    let duplicate_groups: Vec<Vec<String>> = if args.dedupe_by_metadata {
+        dedupe::duplicate_groups(&plans)
+            .into_iter()
+            .map(|group| group.iter().map(|&index| plans[index].source.clone()).collect())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let mut quarantined = Vec::new();
+    if args.quarantine_dir.is_some() {
+        let extra: Vec<&String> = duplicate_groups.iter().flat_map(|group| &group[1..]).collect();
+        (quarantined, plans) = plans.into_iter().partition(|plan| extra.contains(&&plan.source));
+    }
+
+    if args.rename_folders {
+        for plan in &mut plans {
+            plan.rename_folder = paper_folders.papers.contains(&plan.source);
+        }
+    }
+
+    // Two papers can yield the same name; make every target unique up front
+    let suffix = renamer::DuplicateSuffix::for_format(&format);
+    for collision in renamer::resolve_duplicates(&mut plans, suffix) {
+        ui::display_warning(&format!("Duplicate name: {}", collision));
+    }
+
+    // Such a suffix can take a name outside the --require-match pattern
+    plans.retain(|plan| {
+        let Err(e) = filename::check_required_match(&plan.new_filename, &options.naming) else {
+            return true;
+        };
+        if args.json {
+            json::print_error(Some(&plan.source), json::error_kind(&e), &e);
+        } else {
+            ui::display_error(&format!("{}: {:#}", plan.source, e));
+        }
+        entries.push(report::Entry {
+            source: plan.source.clone(),
+            status: report::Status::Failed,
+            new_name: None,
+            message: Some(format!("{:#}", e)),
+        });
+        failures += 1;
+        false
+    });
+
+    if args.dry_run {
+        let rows = preview::rows(&plans, &quarantined, args.quarantine_dir.as_deref(), &entries);
+        say!("\nDry run, nothing was renamed:\n\n{}", preview::render_table(&rows));
+        return Ok(());
+    }
+
+    if options.confirmation == Confirmation::Bulk && !(plans.is_empty() && quarantined.is_empty()) {
+        let mut pairs = plans
+            .iter()
+            .map(|plan| Ok((renamer::get_filename(&plan.source)?, plan.proposed_name())))
+            .collect::<Result<Vec<_>>>()?;
+        for plan in &quarantined {
+            let dir = args.quarantine_dir.as_deref().unwrap_or_default();
+            pairs.push((renamer::get_filename(&plan.source)?, format!("{}/ (probable duplicate)", dir)));
+        }
+
+        if !ui::review_plan(&pairs)? {
+            ui::display_cancelled();
+            entries.extend(
+                plans
+                    .iter()
+                    .chain(&quarantined)
+                    .map(|plan| skipped(&plan.source, "cancelled in review")),
+            );
+            return write_report(&args, &options.usage, &inputs, entries);
+        }
+    }
+
+    if let Some(dir) = &args.quarantine_dir {
+        for plan in &quarantined {
+            match dedupe::quarantine(&plan.source, std::path::Path::new(dir)) {
+                Ok(target) => {
+                    say!("\nMoved probable duplicate {} to {}", plan.source, target.display());
+                    if let Some(state) = state.as_mut() {
+                        state.record(&plan.source, resume::Outcome::Skipped)?;
+                    }
+                    entries.push(skipped(
+                        &plan.source,
+                        &format!("probable duplicate, moved to {}", target.display()),
+                    ));
+                }
+                Err(e) => {
+                    if args.json {
+                        json::print_error(Some(&plan.source), json::error_kind(&e), &e);
+                    } else {
+                        ui::display_error(&format!("{:#}", e));
+                    }
+                    entries.push(report::Entry {
+                        source: plan.source.clone(),
+                        status: report::Status::Failed,
+                        new_name: None,
+                        message: Some(format!("{:#}", e)),
+                    });
+                    failures += 1;
+                }
+            }
+        }
+    }
+
+    // Failed files are set aside, so the source folder only holds processed ones
+    // and a later run doesn't pick them up again by accident
+    let mut moved_failures = Vec::new();
+    if let Some(dir) = &args.quarantine_failures {
+        for file_path in &failed_files {
+            match dedupe::quarantine(file_path, std::path::Path::new(dir)) {
+                Ok(target) => {
+                    if let Some(state) = state.as_mut() {
+                        state.record(file_path, resume::Outcome::Skipped)?;
+                    }
+                    if let Some(entry) = entries.iter_mut().rev().find(|entry| &entry.source == file_path) {
+                        let message = entry.message.take().unwrap_or_default();
+                        entry.message = Some(format!("{}; moved to {}", message, target.display()));
+                    }
+                    moved_failures.push(format!("{} -> {}", file_path, target.display()));
+                }
+                Err(e) => ui::display_error(&format!("Could not move {} aside: {:#}", file_path, e)),
+            }
+        }
+    }
+
+    // Apply phase: only runs once the complete plan is known to be conflict-free
+    let applied = apply(
+        &plans,
+        options.transfer,
+        args.record_original,
+        args.copy_on_success_to.as_deref(),
+        rename_log.as_ref(),
+        &options.model,
+    );
+
+    if !duplicate_groups.is_empty() {
+        say!("\nProbable duplicate papers (same author, year and title):");
+        for group in &duplicate_groups {
+            say!("  - {}", group.join(", "));
+        }
+        if let Some(dir) = &args.quarantine_dir {
+            say!("The first file of each group was kept, the others were moved to {}", dir);
+        }
+    }
+    if !moved_failures.is_empty() {
+        say!("\nFiles that could not be processed were moved aside:");
+        for moved in &moved_failures {
+            say!("  - {}", moved);
+        }
+    }
+    entries.extend(applied_entries(&plans, &applied));
+    write_report(&args, &options.usage, &inputs, entries)?;
+    let renamed = renamer::completed(&applied);
+    record_index(args.index_out.as_deref(), &plans, renamed)?;
+    if let Some(state) = state.as_mut() {
+        for plan in &plans[..renamed.len()] {
+            state.record(&plan.source, resume::Outcome::Renamed)?;
+        }
+    }
+    // The state is kept, so the files the failure stopped are retried with --resume
+    applied?;
+
+    let auto_applied: Vec<&renamer::RenamePlan> =
+        plans.iter().filter(|plan| options.auto_planned.contains(&plan.source)).collect();
+    if !auto_applied.is_empty() {
+        say!("\nRenamed without asking, as their metadata looked reliable:");
+        for plan in auto_applied {
+            say!("  - {} -> {}", renamer::get_filename(&plan.source)?, plan.proposed_name());
+        }
+    }
+
+    // Keep the state around so failed files are retried with --resume
+    if let Some(state) = state.filter(|_| failures == 0) {
+        state.finish()?;
+    }
+
+    if failures > 0 {
+        anyhow::bail!("{} file(s) could not be processed", failures);
+    }
 
+    Ok(())
+}
+
+/// Stop a batch the user aborted: nothing is renamed, the progress state is
+/// discarded (the settings were likely wrong) and the outcome so far is summarized
+/// `rest` holds the file being confirmed and every file after it
+fn abort(
+    rest: &[String],
+    plans: &[renamer::RenamePlan],
+    mut entries: Vec<report::Entry>,
+    state: Option<resume::BatchState>,
+    args: &Args,
+    usage: &summary::Usage,
+    inputs: &[String],
+) -> Result<()> {
+    let failures = entries.iter().filter(|entry| entry.status == report::Status::Failed).count();
+    let skipped_count = entries.len() - failures;
+    ui::display_aborted(plans.len(), skipped_count, failures, rest.len());
+
+    entries.extend(plans.iter().map(|plan| skipped(&plan.source, "aborted before renaming")));
+    entries.extend(rest.iter().map(|file_path| skipped(file_path, "not reached, batch aborted")));
+    if let Some(state) = state {
+        state.finish()?;
+    }
+    write_report(args, usage, inputs, entries)
+}
+
+/// Check Ollama and time one sample extraction, then show the projected
+/// total time of the batch and ask whether to go ahead
+/// The sample's result is discarded; the file is processed again in the batch
+fn preflight(pending: &[&String], options: &RunOptions) -> Result<bool> {
+    say!("\nPreflight check");
+
+    let running = llm::running_models(&options.ollama_url, &options.extract.client)?;
+    say!("  Ollama:       reachable at {}", options.ollama_url);
+    say!("  Model:        {}", options.model);
+    say!(
+        "  Loaded:       {}",
+        if running.is_empty() {
+            "none yet (the first file also pays for loading the model)".to_string()
+        } else {
+            running.join(", ")
+        }
+    );
+
+    let Some(sample) = pending.first() else {
+        say!("  Nothing left to process.");
+        return Ok(false);
+    };
+
+    let spinner = ui::create_spinner(&format!("Timing a sample extraction on {}...", sample));
+    let started = std::time::Instant::now();
+    let result = extract_metadata(sample, options);
+    let elapsed = started.elapsed();
+    spinner.finish_and_clear();
+
+    match result {
+        Ok(metadata) => say!("  Sample:       \"{}\" in {}", metadata.title, ui::format_duration(elapsed)),
+        Err(e) => say!("  Sample:       failed after {} ({:#})", ui::format_duration(elapsed), e),
+    }
+    say!(
+        "  Projected:    about {} for {} files",
+        ui::format_duration(elapsed * pending.len() as u32),
+        pending.len()
+    );
+
+    ui::confirm_start(pending.len())
+}
+
+/// The document type detected in a paper's text, or empty if undetermined
+fn doc_type(text: &str) -> String {
+    pdf::detect_doc_type(text).map(|doc_type| doc_type.as_str().to_string()).unwrap_or_default()
+}
+
+/// Extract a paper's metadata with the LLM, without prompts or fallbacks
+fn extract_metadata(file_path: &str, options: &RunOptions) -> Result<llm::PaperMetadata> {
+    let doc = pdf::load_document(file_path)?;
+    let text = pdf::extract_pdf_text(&doc, &options.text)?;
+    let title_hint = pdf::largest_text_block(&doc, text.title_page);
+    let mut metadata = llm::extract_metadata_with_ollama(
+        &text.text,
+        title_hint.as_deref(),
+        &options.ollama_url,
+        &options.model,
+        &options.extract,
+    )?;
+
+    // Read off the page, like in the full pipeline
+    let original_filename = renamer::get_filename(file_path)?;
+    metadata.doi = text.doi.unwrap_or_default();
+    metadata.version = pdf::detect_version(&text.text, &original_filename).unwrap_or_default();
+    metadata.category = pdf::detect_category(&text.text).unwrap_or_default();
+    metadata.doc_type = doc_type(&text.text);
+    Ok(metadata)
+}
+
+/// Print the metadata extracted from each file without renaming anything
+/// With --json, each file is one JSON object on stdout
+fn extract_only(files: &[String], options: &RunOptions) -> Result<()> {
+    let mut failures = 0;
+    for file in files {
+        let metadata = if download::is_url(file) {
+            Err(anyhow::anyhow!("--extract-only reads local files; download {} first", file))
+        } else {
+            let spinner = ui::create_spinner(&format!("Extracting metadata from {}...", file));
+            let metadata = extract_metadata(file, options);
+            spinner.finish_and_clear();
+            metadata
+        };
+
+        match metadata {
+            Ok(metadata) if ui::json_mode() => json::print_metadata(file, &metadata),
+            Ok(metadata) => {
+                say!("\n{}", file);
+                ui::display_metadata(&metadata);
+            }
+            Err(e) => {
+                failures += 1;
+                if ui::json_mode() {
+                    json::print_error(Some(file), json::error_kind(&e), &e);
+                } else {
+                    ui::display_error(&format!("{}: {:#}", file, e));
+                }
+            }
+        }
+    }
+
+    if failures > 0 {
+        anyhow::bail!("Could not extract metadata from {} of {} files", failures, files.len());
+    }
+    Ok(())
+}
+
+/// Infer the template each example file is named by and suggest the one most agree on
+fn infer_format(files: &[String], options: &RunOptions) -> Result<()> {
+    let mut formats = Vec::new();
+    for file in files {
+        let name = renamer::get_filename(file)?;
+        let spinner = ui::create_spinner(&format!("Extracting metadata from {}...", name));
+        let metadata = extract_metadata(file, options);
+        spinner.finish_and_clear();
+
+        match metadata.map(|metadata| infer::infer_format(&name, &metadata)) {
+            Ok(Some(format)) => {
+                say!("  {} -> {}", name, format);
+                formats.push(format);
+            }
+            Ok(None) => say!("  {} -> no author, year or title found in the name", name),
+            Err(e) => say!("  {} -> failed ({:#})", name, e),
+        }
+    }
+
+    let Some((format, count)) = infer::most_common(&formats) else {
+        anyhow::bail!("Could not infer a template from any of the {} examples", files.len());
+    };
+    filename::validate_format(&format)?;
+
+    say!("\nSuggested template (matches {} of {} examples):", count, files.len());
+    say!("  --format \"{}\"", format);
+    say!("\nNames are always lowercased with dashes between words, so only the order of the fields and the separators carry over.");
+    Ok(())
+}
+
+/// Extract one PDF's metadata with each model in turn and print what each found
+/// The text is extracted once, so every model sees the same input
+fn compare_models(file: &str, models: &[String], options: &RunOptions) -> Result<()> {
+    let name = renamer::get_filename(file)?;
+    let doc = pdf::load_document(file)?;
+    let text = pdf::extract_pdf_text(&doc, &options.text)?;
+    let title_hint = pdf::largest_text_block(&doc, text.title_page);
+
+    let mut runs = Vec::new();
+    for model in models {
+        let spinner = ui::create_spinner(&format!("Extracting metadata from {} with {}...", name, model));
+        let started = std::time::Instant::now();
+        let metadata = llm::extract_metadata_with_ollama(
+            &text.text,
+            title_hint.as_deref(),
+            &options.ollama_url,
+            model,
+            &options.extract,
+        );
+        let elapsed = started.elapsed();
+        spinner.finish_and_clear();
+
+        runs.push(compare::Run {
+            model: model.clone(),
+            elapsed,
+            metadata: metadata.map_err(|e| format!("{:#}", e)),
+        });
+    }
+
+    say!("\n{}\n", compare::render_table(&runs));
+    let succeeded = runs.iter().filter(|run| run.metadata.is_ok()).count();
+    match compare::distinct_answers(&runs) {
+        0 => say!("No model extracted any metadata."),
+        1 if succeeded > 1 => say!("All {} models that succeeded agree.", succeeded),
+        1 => {}
+        answers => say!("The {} models that succeeded gave {} different answers.", succeeded, answers),
+    }
+    say!("Latency includes loading a model that Ollama did not have in memory yet.");
+    Ok(())
+}
+
+/// Report entry for a file that was deliberately not renamed
+fn skipped(source: &str, reason: &str) -> report::Entry {
+    report::Entry {
+        source: source.to_string(),
+        status: report::Status::Skipped,
+        new_name: None,
+        message: Some(reason.to_string()),
+    }
+}
+
+/// Report entries for the apply phase, in plan order: renamed up to a failure,
+/// failed from there on
+fn applied_entries(
+    plans: &[renamer::RenamePlan],
+    applied: &std::result::Result<Vec<renamer::RenameRecord>, renamer::ApplyError>,
+) -> Vec<report::Entry> {
+    let renamed = renamer::completed(applied).len();
+    let failure = applied.as_ref().err().map(|e| format!("{:#}", e.error));
+    plans
+        .iter()
+        .enumerate()
+        .map(|(i, plan)| {
+            if i < renamed {
+                report::Entry {
+                    source: plan.source.clone(),
+                    status: report::Status::Renamed,
+                    new_name: Some(plan.proposed_name()),
+                    message: None,
+                }
+            } else {
+                report::Entry {
+                    source: plan.source.clone(),
+                    status: report::Status::Failed,
+                    new_name: None,
+                    message: failure.clone(),
+                }
+            }
+        })
+        .collect()
+}
+
+/// Write the run report and the --summary-json statistics, if requested, with
+/// the files in the order they were given
+/// In --json mode, every entry is also printed to stdout
+fn write_report(args: &Args, usage: &summary::Usage, inputs: &[String], mut entries: Vec<report::Entry>) -> Result<()> {
+    entries.sort_by_key(|entry| inputs.iter().position(|input| *input == entry.source));
+    if ui::json_mode() {
+        entries.iter().for_each(json::print_entry);
+    }
+
+    if let Some(path) = &args.summary_json {
+        summary::write_summary(path, &entries, usage)?;
+        say!("\nSummary written to {}", path);
+    }
+
+    let Some(path) = &args.report else {
+        return Ok(());
+    };
+
+    report::write_report(path, &entries)?;
+    say!("\nReport written to {}", path);
+
+    Ok(())
+}
+
+/// Add renamed files to the library index, if one was requested
+fn record_index(
+    path: Option<&str>,
+    plans: &[renamer::RenamePlan],
+    renamed: &[renamer::RenameRecord],
+) -> Result<()> {
+    let Some(path) = path else {
+        return Ok(());
+    };
+
+    let entries: Vec<_> = plans
+        .iter()
+        .zip(renamed)
+        .filter_map(|(plan, record)| Some((record.clone(), plan.metadata.clone()?)))
+        .collect();
+    index::record_renames(path, &entries)
+}
+
+/// Rename every file in an index to match the current naming options
+/// The stored metadata is reused, so no PDF is read and no LLM is needed
+fn apply_index(
+    index_path: &str,
+    args: &Args,
+    naming: &filename::NamingOptions,
+    transfer: renamer::Transfer,
+    rename_log: Option<&history::RenameLog>,
+    dry_run: bool,
+) -> Result<()> {
+    let mut plans = Vec::new();
+    for entry in index::read_index(index_path)? {
+        let source = entry.path.to_string_lossy().into_owned();
+        let Some(extension) = entry.path.is_file().then(|| filename::pdf_extension(&source)).flatten()
+        else {
+            say!("Skipping {} (no longer exists)", source);
+            continue;
+        };
+
+        let naming = filename::NamingOptions {
+            extension: if args.keep_extension_case { extension } else { "pdf" }.to_string(),
+            ..naming.clone()
+        };
+        let mut metadata = entry.metadata;
+        if naming.hash_prefix.is_some() {
+            metadata.content_hash = renamer::content_hash(&entry.path)?;
+        }
+        let new_filename = filename::generate_filename(&metadata, &naming);
+        if renamer::get_filename(&source)? == new_filename {
+            continue;
+        }
+
+        plans.push(renamer::RenamePlan {
+            source,
+            new_filename,
+            metadata: Some(metadata),
+            ..renamer::RenamePlan::default()
+        });
+    }
+
+    let suffix = renamer::DuplicateSuffix::for_format(&naming.format);
+    for collision in renamer::resolve_duplicates(&mut plans, suffix) {
+        ui::display_warning(&format!("Duplicate name: {}", collision));
+    }
+    plans.retain(|plan| match filename::check_required_match(&plan.new_filename, naming) {
+        Ok(()) => true,
+        Err(e) => {
+            say!("Skipping {} ({:#})", plan.source, e);
+            false
+        }
+    });
+
+    if plans.is_empty() {
+        say!("All indexed files already match the format.");
+        return Ok(());
+    }
+
+    let pairs = plans
+        .iter()
+        .map(|plan| Ok((renamer::get_filename(&plan.source)?, plan.proposed_name())))
+        .collect::<Result<Vec<_>>>()?;
+
+    if dry_run {
+        say!("\nDry run, nothing was renamed:");
+        for (original, proposed) in &pairs {
+            say!("  {} -> {}", original, proposed);
+        }
+        return Ok(());
+    }
+
+    if !args.yes && !ui::review_plan(&pairs)? {
+        ui::display_cancelled();
+        return Ok(());
+    }
+
+    // The metadata comes from the index, so no model was involved
+    let applied = apply(&plans, transfer, args.record_original, args.copy_on_success_to.as_deref(), rename_log, "");
+    record_index(Some(index_path), &plans, renamer::completed(&applied))?;
+    applied?;
+    Ok(())
+}
+
+/// Append the outcome of an apply phase to the rename log, if one was requested
+fn log_renames(
+    log: Option<&history::RenameLog>,
+    model: &str,
+    plans: &[renamer::RenamePlan],
+    applied: &std::result::Result<Vec<renamer::RenameRecord>, renamer::ApplyError>,
+) -> Result<()> {
+    match log {
+        Some(log) => log.record(model, plans, applied),
+        None => Ok(()),
+    }
+}
+
+/// Check the complete plan for conflicts, then perform every rename in it and
+/// log the outcome to `log`, whether or not it succeeded
+/// A failure stops the phase; the renames completed before it are returned in the error
+/// With `record_original`, each file's original name is stored on it, with
+/// `mirror` each renamed file is copied there, and saved abstracts get their
+/// sidecar file; failing at any of these, or at the log, is only a warning,
+/// since the rename itself went through
+fn apply(
+    plans: &[renamer::RenamePlan],
+    transfer: renamer::Transfer,
+    record_original: bool,
+    mirror: Option<&str>,
+    log: Option<&history::RenameLog>,
+    model: &str,
+) -> std::result::Result<Vec<renamer::RenameRecord>, renamer::ApplyError> {
+    let applied = match renamer::check_plan(plans) {
+        Ok(()) => renamer::apply_plan(plans, transfer),
+        Err(error) => Err(renamer::ApplyError {
+            completed: Vec::new(),
+            error,
+        }),
+    };
+    if let Err(e) = log_renames(log, model, plans, &applied) {
+        ui::display_error(&format!("{:#}", e));
+    }
+
+    for (plan, record) in plans.iter().zip(renamer::completed(&applied)) {
+        let original_filename = renamer::get_filename(&plan.source).unwrap_or_else(|_| plan.source.clone());
+        ui::display_success(&original_filename, &record.to.display().to_string());
+        if record_original {
+            if let Err(e) = provenance::record_original(record) {
+                ui::display_error(&format!("{:#}", e));
+            }
+        }
+
+        if let Some(mirror) = mirror {
+            match renamer::mirror_file(record, std::path::Path::new(mirror)) {
+                Ok(renamer::Mirrored::Copied(target)) => say!("  Mirrored to {}", target.display()),
+                Ok(renamer::Mirrored::AlreadyPresent(target)) => {
+                    say!("  Already in the mirror: {}", target.display())
+                }
+                Err(e) => ui::display_warning(&format!("Could not mirror {}: {:#}", record.to.display(), e)),
+            }
+        }
+
+        if let Some(abstract_text) = &plan.abstract_text {
+            match renamer::write_abstract(&record.to, abstract_text) {
+                Ok(sidecar) => say!("  Abstract saved to {}", sidecar.display()),
+                Err(e) => ui::display_error(&format!("{:#}", e)),
+            }
+        }
+    }
+
+    applied
+}
+
+/// Run the extraction pipeline for a single PDF and decide its new name:
+/// extract text, extract metadata and confirm the proposed name.
+/// Nothing is renamed here; the result is a plan entry for the apply phase.
+/// Only `Confirmation::Each` prompts here. Otherwise the proposed name is planned
+/// directly; with `Confirmation::Never` only if the metadata passes the
+/// plausibility check
+/// Answering "yes/no to all" switches `options.confirmation` for the rest of the run
+/// Returns None if the user cancelled
+fn plan_file(file_path: &str, options: &mut RunOptions) -> Result<Option<renamer::RenamePlan>> {
     // Validate that the file exists and is a PDF
-    if !args.file_path.ends_with(".pdf") {
+    let Some(extension) = filename::pdf_extension(file_path) else {
         anyhow::bail!("File must be a PDF (*.pdf)");
+    };
+
+    let mut naming = filename::NamingOptions {
+        extension: if options.keep_extension_case {
+            extension.to_string()
+        } else {
+            "pdf".to_string()
+        },
+        ..options.naming.clone()
+    };
+
+    // A collection's own settings come before the defaults and the user's
+    // settings, but never before environment variables or the command line
+    let mut model = options.model.clone();
+    if let Some((path, config)) = config::find_for(file_path)? {
+        say!("\nUsing settings from {}", path.display());
+        config.apply(&mut naming, &options.explicit);
+        if let Some(config_model) = config.model(&options.explicit) {
+            model = config_model.to_string();
+        }
     }
+    let naming = &naming;
+    let extract = &llm::ExtractOptions {
+        include_publisher: naming.format.contains("{publisher}"),
+        ..options.extract.clone()
+    };
 
-    let original_filename = renamer::get_filename(&args.file_path)?;
+    let original_filename = renamer::get_filename(file_path)?;
 
-    // Step 1: Detect which Ollama model to use
-    let spinner = ui::create_spinner("Detecting available Ollama model...");
-    let model = llm::detect_ollama_model()
-        .context("Failed to detect Ollama model")?;
-    ui::finish_spinner(spinner, &format!("Using model: {}", model));
+    // In repair mode, parts of the current name that already follow the
+    // format are kept, and a complete name needs no extraction at all
+    let existing = if options.fill_missing {
+        filename::parse_existing(&original_filename, &naming.format)
+    } else {
+        Default::default()
+    };
+    if options.fill_missing {
+        let missing = filename::missing_fields(&naming.format, &existing);
+        if missing.is_empty() {
+            say!("\n{} already has every part of the format, skipping", original_filename);
+            return Ok(None);
+        }
+        say!("\n{} is missing: {}", original_filename, missing.join(", "));
+    }
 
-    println!("\nAnalyzing PDF...");
+    // Step 2 and 3: Extract metadata from a copied citation, or from the PDF's text using the LLM
+    // The PDF is parsed once for all of its readers, and not at all for a citation
+    let (mut metadata, page_text, title_hint, document) = if let Some(citation) = &options.citation {
+        say!("\nReading the citation from the clipboard:\n  {}", citation);
+        let spinner = ui::create_spinner("Extracting metadata from the citation...");
+        let started = std::time::Instant::now();
+        let extracted = llm::extract_metadata_from_citation(citation, &options.ollama_url, &model, extract);
+        options.usage.record(&model, started.elapsed());
+        let mut metadata = extracted.context("Failed to extract metadata from the clipboard citation")?;
+        ui::finish_spinner(spinner, "Metadata extracted successfully");
+        metadata.doi = pdf::detect_doi(citation).unwrap_or_default();
+        (metadata, String::new(), None, None)
+    } else {
+        say!("\nAnalyzing PDF...");
 
-    // Step 2: Extract text from PDF
-    let pdf_text = match pdf::extract_pdf_text(&args.file_path) {
-        Ok(text) => text,
-        Err(e) => {
-            ui::display_error(&format!("{:#}", e));
+        // Step 2: Extract text from PDF
+        let read = pdf::load_document(file_path)
+            .and_then(|document| Ok((pdf::extract_pdf_text(&document, &options.text)?, document)));
+        let (mut pdf_text, document) = match read {
+            Ok(read) => read,
+            Err(e) if options.confirmation != Confirmation::Each => return Err(e),
+            Err(e) => {
+                ui::display_error(&format!("{:#}", e));
 
-            // Ask if user wants to enter metadata manually
-            if ui::ask_manual_metadata()? {
-                println!("\nManual metadata entry is not yet implemented.");
-                println!("This feature will be added in a future version.");
-                anyhow::bail!("Manual metadata entry not available");
-            } else {
-                ui::display_cancelled();
-                return Ok(());
+                // Ask if user wants to enter metadata manually
+                if ui::ask_manual_metadata()? {
+                    say!("\nManual metadata entry is not yet implemented.");
+                    say!("This feature will be added in a future version.");
+                    anyhow::bail!("Manual metadata entry not available");
+                } else {
+                    ui::display_cancelled();
+                    return Ok(None);
+                }
+            }
+        };
+
+        // Papers of another kind are skipped before spending an LLM call on them
+        if let Some(only_type) = options.only_type {
+            let found = pdf::detect_doc_type(&pdf_text.text);
+            if found != Some(only_type) {
+                let found = found.map_or("undetermined", pdf::DocType::as_str);
+                say!("\n{} is not a {} ({}), skipping", original_filename, only_type.as_str(), found);
+                options.skip_reason = Some("different document type");
+                return Ok(None);
             }
         }
+
+        // The largest text on the title page is usually the title; pass it along as a hint,
+        // or the title from the outline where the page can't be laid out
+        let title_hint = pdf::largest_text_block(&document, pdf_text.title_page)
+            .or_else(|| pdf::extract_outline_title(&document));
+
+        // Step 3: Extract metadata using LLM, unless every field was given
+        let mut metadata = if options.overrides.is_complete() {
+            say!("Author, year and title were given, not asking the LLM");
+            llm::PaperMetadata::default()
+        } else {
+            let spinner = ui::create_spinner("Extracting metadata using LLM...");
+            let started = std::time::Instant::now();
+            let extracted = llm::extract_metadata_with_ollama(
+                &pdf_text.text,
+                title_hint.as_deref(),
+                &options.ollama_url,
+                &model,
+                extract,
+            );
+            options.usage.record(&model, started.elapsed());
+            let mut metadata = extracted.context("Failed to extract metadata using LLM")?;
+            ui::finish_spinner(spinner, "Metadata extracted successfully");
+
+            // An unreliable answer gets another try with more of the paper, up to --context-passes
+            let mut passes = 1;
+            while passes < options.context_passes {
+                let unreliable = llm::implausible_fields(&metadata);
+                if unreliable.is_empty() {
+                    break;
+                }
+                // Stop once there is no more text to send
+                let Ok(wider) = pdf::extract_pdf_text(&document, &options.text.widened(passes)) else {
+                    break;
+                };
+                if wider.text.len() <= pdf_text.text.len() {
+                    break;
+                }
+
+                passes += 1;
+                let spinner = ui::create_spinner(&format!(
+                    "Unreliable {}, asking again with more text (pass {} of {})...",
+                    unreliable.join(", "),
+                    passes,
+                    options.context_passes
+                ));
+                let started = std::time::Instant::now();
+                let extracted = llm::extract_metadata_with_ollama(
+                    &wider.text,
+                    title_hint.as_deref(),
+                    &options.ollama_url,
+                    &model,
+                    extract,
+                );
+                options.usage.record(&model, started.elapsed());
+                match extracted {
+                    Ok(extracted) => {
+                        ui::finish_spinner(spinner, "Metadata extracted again");
+                        metadata = extracted;
+                        pdf_text = wider;
+                    }
+                    // Keep the earlier answer rather than lose the file
+                    Err(e) => {
+                        spinner.finish_and_clear();
+                        say!("Pass {} failed, keeping the previous answer ({:#})", passes, e);
+                        break;
+                    }
+                }
+            }
+            if passes > 1 {
+                let outcome = if llm::implausible_fields(&metadata).is_empty() { "reliable" } else { "still unreliable" };
+                say!("Metadata {} after {} passes", outcome, passes);
+            }
+            metadata
+        };
+        // DOIs are read off the page rather than asked of the LLM
+        metadata.doi = pdf_text.doi.unwrap_or_default();
+        (metadata, pdf_text.text, title_hint, Some(document))
     };
+    // A title in the document outline costs no LLM call; it hints and backs up the LLM's
+    let outline_title = document.as_ref().and_then(pdf::extract_outline_title);
+
+    // Preprint versions and arXiv categories are stamped on the page or in the download name,
+    // so they are detected directly rather than asked of the LLM
+    metadata.version = pdf::detect_version(&page_text, &original_filename).unwrap_or_default();
+    metadata.category = pdf::detect_category(&page_text).unwrap_or_default();
+    metadata.doc_type = doc_type(options.citation.as_deref().unwrap_or(&page_text));
+
+    // Sources by precedence: parts of the existing name kept by --fill-missing,
+    // then the LLM and the embedded metadata (in the order chosen), then the
+    // title from the PDF's outline, the year from the filename, and finally asking the user
+    let mut sources = sources::FieldSources::default();
+    if options.citation.is_some() {
+        for field in ["author", "year", "title"] {
+            sources.set(field, sources::Source::Clipboard);
+        }
+    }
+    // Kept for --interactive-title-selection, before the other sources get a say
+    let extracted_title = metadata.title.clone();
+    let extracted_from = sources.get("title").unwrap_or(sources::Source::Llm);
+    let embedded = document.as_ref().and_then(pdf::embedded_metadata);
+    if let Some(embedded) = &embedded {
+        sources::merge_embedded(&mut metadata, embedded, options.precedence, &mut sources);
+    }
+    if let Some(title) = &outline_title {
+        sources::merge_outline_title(&mut metadata, title, &mut sources);
+    }
+
+    // Where the LLM and the embedded metadata give the same title at different lengths,
+    // the tie-break rather than the precedence decides
+    if let Some(embedded) = &embedded {
+        let titles = [extracted_title.as_str(), embedded.title.as_str()];
+        if let Some(index) = filename::break_title_tie(&metadata, titles, naming) {
+            metadata.title = titles[index].to_string();
+            if index == 1 {
+                metadata.evidence.remove("title");
+            }
+            sources.set("title", [extracted_from, sources::Source::Embedded][index]);
+        }
+    }
+
+    // Where the sources disagree on the title, the user has the final say
+    if options.select_title && options.overrides.title.is_none() {
+        let candidates = sources::title_candidates(&[
+            (extracted_from, extracted_title.as_str()),
+            (sources::Source::Embedded, embedded.as_ref().map_or("", |embedded| embedded.title.as_str())),
+            (sources::Source::Outline, outline_title.as_deref().unwrap_or_default()),
+            (sources::Source::Heading, title_hint.as_deref().unwrap_or_default()),
+        ]);
+        if candidates.len() > 1 {
+            match ui::select_title(&candidates)? {
+                Some(index) => {
+                    metadata.title = candidates[index].title.clone();
+                    sources.set("title", candidates[index].sources[0]);
+                }
+                None => {
+                    metadata.title = ui::edit_title(&metadata.title)?;
+                    sources.set("title", sources::Source::User);
+                }
+            }
+            metadata.title_slug.clear();
+            metadata.evidence.remove("title");
+        }
+    }
+
+    // The publisher named in a copyright line or the document information is
+    // more reliable than the LLM's reading; its answer is normalized the same way
+    metadata.publisher = pdf::detect_publisher(&page_text)
+        .or_else(|| embedded.map(|embedded| embedded.publisher).filter(|name| !name.is_empty()))
+        .or_else(|| pdf::normalize_publisher(&metadata.publisher))
+        .unwrap_or_else(|| match metadata.publisher.trim() {
+            placeholder if ["unknown", "n/a", "none"].contains(&placeholder.to_lowercase().as_str()) => String::new(),
+            publisher => publisher.to_string(),
+        });
+    filename::apply_existing(&mut metadata, &existing, options.title_case_from_source);
+    for field in ["author", "year", "title"] {
+        // A title kept only for the filename leaves the extracted one in place
+        let only_filename = field == "title" && !metadata.title_slug.is_empty();
+        if existing.contains_key(field) && !only_filename {
+            sources.set(field, sources::Source::Filename);
+        }
+    }
+
+    if !llm::is_valid_year(&metadata.year) && options.year_from_filename {
+        if let Some(year) = pdf::year_from_filename(&original_filename) {
+            say!("\nNo usable year from the LLM ('{}'), using {} from the filename", metadata.year, year);
+            metadata.year = year;
+            sources.set("year", sources::Source::Filename);
+        }
+    }
+
+    // Values given on the command line beat every extracted one
+    options.overrides.apply(&mut metadata, &mut sources);
+
+    // A field the LLM left blank is asked for interactively; without
+    // anyone to ask, the file fails and the message names the field
+    let empty = llm::empty_fields(&metadata);
+    if !empty.is_empty() {
+        match options.missing_field {
+            MissingField::Error => {
+                anyhow::bail!("LLM could not extract the {} of the paper", empty.join(", "))
+            }
+            MissingField::Skip => {
+                say!("\nNo {} found for {}, skipping", empty.join(", "), original_filename);
+                return Ok(None);
+            }
+            MissingField::Prompt | MissingField::Placeholder => {}
+        }
+    }
+    for field in empty {
+        let value = if options.missing_field == MissingField::Placeholder {
+            say!("\nNo {} found, using '{}'", field, MISSING_FIELD_PLACEHOLDER);
+            sources.set(field, sources::Source::Placeholder);
+            MISSING_FIELD_PLACEHOLDER.to_string()
+        } else {
+            ui::display_error(&format!("The LLM could not find the {}, please enter it", field));
+            sources.set(field, sources::Source::User);
+            match field {
+                "author" => ui::edit_author("")?,
+                "year" => ui::edit_year("")?,
+                _ => ui::edit_title("")?,
+            }
+        };
+        match field {
+            "author" => metadata.first_author = value,
+            "year" => metadata.year = value,
+            _ => metadata.title = value,
+        }
+    }
+
+    // An author field holding a whole author list would balloon the filename
+    if let Err(e) = llm::check_author(&metadata.first_author, extract.max_author_tokens) {
+        if !options.confirmation.prompts() {
+            return Err(e);
+        }
+
+        ui::display_error(&format!("{:#}", e));
+        metadata.first_author = ui::edit_author(&metadata.first_author)?;
+        metadata.evidence.remove("first_author");
+        sources.set("author", sources::Source::User);
+    }
+
+    // A year outside the accepted window is more likely misread than a real outlier
+    let year_out_of_range = llm::year_out_of_range(&metadata.year, options.min_year, options.max_year);
+    if year_out_of_range {
+        if !options.confirmation.prompts() {
+            say!("\nYear {} of {} is out of range, skipping", metadata.year, original_filename);
+            options.skip_reason = Some("year out of range");
+            return Ok(None);
+        }
+        ui::display_warning(&format!(
+            "Year {} is outside the accepted range and may be misextracted",
+            metadata.year
+        ));
+    }
+
+    if options.show_sources {
+        say!("\nField sources: {}", sources);
+    }
 
-    // Step 3: Extract metadata using LLM
-    let spinner = ui::create_spinner("Extracting metadata using LLM...");
-    let mut metadata = llm::extract_metadata_with_ollama(&pdf_text, &model)
-        .context("Failed to extract metadata using LLM")?;
-    ui::finish_spinner(spinner, "Metadata extracted successfully");
+    if naming.hash_prefix.is_some() {
+        metadata.content_hash = renamer::content_hash(std::path::Path::new(file_path))?;
+    }
 
     // Display the extracted metadata
-    ui::display_metadata(&metadata.first_author, &metadata.year, &metadata.title);
+    ui::display_metadata(&metadata);
+
+    // A deep folder can push the full path past the platform's limit even when
+    // the name alone fits; the title gives way, never the folders
+    let mut target_dir = std::path::absolute(file_path)
+        .with_context(|| format!("Failed to resolve path: {}", file_path))?
+        .parent()
+        .map(std::path::Path::to_path_buf)
+        .unwrap_or_default();
+    if options.author_subdir {
+        target_dir.push(filename::author_folder(&metadata, naming));
+    }
+    let dir_bytes = target_dir.as_os_str().len() + 1;
+    let shortened;
+    let naming = match filename::fit_title_to_path(&metadata, naming, dir_bytes, filename::MAX_PATH_BYTES)? {
+        Some(max_title_length) => {
+            ui::display_warning(&format!(
+                "The full path would exceed {} bytes; shortening the title to {} characters",
+                filename::MAX_PATH_BYTES,
+                max_title_length
+            ));
+            shortened = filename::NamingOptions {
+                max_title_length: Some(max_title_length),
+                ..naming.clone()
+            };
+            &shortened
+        }
+        None => naming,
+    };
+
+    // Step 4: Generate proposed filename, letting the post-process command
+    // have the final say; it declining the name skips the file
+    let propose = |metadata: &llm::PaperMetadata| -> Result<Option<String>> {
+        let generated = filename::generate_filename(metadata, naming);
+        match &options.post_process_cmd {
+            Some(command) => hook::post_process(command, &generated, metadata),
+            None => Ok(Some(generated)),
+        }
+    };
+    let declined = || {
+        say!("\nPost-process command rejected {}, skipping", original_filename);
+        Ok(None)
+    };
+
+    let Some(mut proposed_filename) = propose(&metadata)? else {
+        return declined();
+    };
 
-    // Step 4: Generate proposed filename
-    let mut proposed_filename = filename::generate_filename(&metadata);
+    let abstract_text = Some(metadata.abstract_text.trim().to_string())
+        .filter(|text| extract.include_abstract && !text.is_empty());
+    let author_subdir = options.author_subdir;
+    let make_plan = |new_filename: String,
+                     metadata: &llm::PaperMetadata,
+                     sources: &sources::FieldSources| renamer::RenamePlan {
+        source: file_path.to_string(),
+        new_filename,
+        subdir: author_subdir.then(|| filename::author_folder(metadata, naming)),
+        abstract_text: abstract_text.clone(),
+        metadata: Some(metadata.clone()),
+        sources: Some(sources.clone()),
+        ..renamer::RenamePlan::default()
+    };
+
+    // Placeholders the user asked for are not held against the metadata
+    let unreliable = year_out_of_range
+        || llm::implausible_fields(&metadata)
+            .into_iter()
+            .any(|field| sources.get(field) != Some(sources::Source::Placeholder));
+    let ask = match options.confirmation {
+        Confirmation::Each => true,
+        Confirmation::LowConfidenceOnly => unreliable,
+        _ => false,
+    };
+    if !ask {
+        // Without a human in the loop, only rename when the result looks sane
+        if options.confirmation == Confirmation::Never && unreliable {
+            anyhow::bail!(
+                "Extracted metadata looks unreliable (year: '{}'). Run without --yes to review it.",
+                metadata.year
+            );
+        }
+
+        if !filename::validate_filename(&proposed_filename, naming.max_filename_bytes) {
+            anyhow::bail!("Invalid filename generated: {}", proposed_filename);
+        }
+        filename::check_required_match(&proposed_filename, naming)?;
+
+        if options.confirmation == Confirmation::LowConfidenceOnly {
+            options.auto_planned.push(file_path.to_string());
+        }
+        return Ok(Some(make_plan(proposed_filename, &metadata, &sources)));
+    }
 
     // Step 5: Get user confirmation
     loop {
-        let choice = ui::confirm_rename(&original_filename, &proposed_filename)?;
+        // A citation is read with its own prompt, which isn't editable
+        let edit_prompt = options.prompt_debug && options.citation.is_none();
+        let choice = ui::confirm_rename(&original_filename, &proposed_filename, edit_prompt)?;
 
         match choice {
-            ui::UserChoice::Yes => {
+            ui::UserChoice::Yes | ui::UserChoice::YesToAll => {
                 // Validate the filename
-                if !filename::validate_filename(&proposed_filename) {
-                    ui::display_error("Invalid filename. Please try again.");
+                if !filename::validate_filename(&proposed_filename, naming.max_filename_bytes) {
+                    ui::display_error(&format!(
+                        "Invalid filename. It must end in .pdf, contain no path separators and be at most {} bytes long.",
+                        naming.max_filename_bytes
+                    ));
+                    continue;
+                }
+                if let Err(e) = filename::check_required_match(&proposed_filename, naming) {
+                    ui::display_error(&format!("{:#}. Edit the name or its fields.", e));
                     continue;
                 }
 
-                // Perform the rename
-                let new_path = renamer::rename_file(&args.file_path, &proposed_filename)
-                    .context("Failed to rename file")?;
+                let plan = make_plan(proposed_filename.clone(), &metadata, &sources);
 
-                ui::display_success(&original_filename, &new_path.display().to_string());
-                break;
+                // Catch an existing target now, while the name can still be edited;
+                // a citation key in use instead gets the next free letter later
+                if plan.target().exists() && !filename::is_citekey(&naming.format) {
+                    ui::display_error(&format!(
+                        "Target file already exists: {}. Choose a different name.",
+                        plan.target().display()
+                    ));
+                    continue;
+                }
+
+                if choice == ui::UserChoice::YesToAll {
+                    options.confirmation = Confirmation::AcceptAll;
+                }
+                return Ok(Some(plan));
             }
             ui::UserChoice::No => {
                 ui::display_cancelled();
-                break;
+                return Ok(None);
+            }
+            ui::UserChoice::NoToAll => {
+                ui::display_cancelled();
+                options.confirmation = Confirmation::DeclineAll;
+                return Ok(None);
+            }
+            ui::UserChoice::Abort => {
+                options.confirmation = Confirmation::Aborted;
+                return Ok(None);
+            }
+            ui::UserChoice::RetryModel | ui::UserChoice::EditPrompt => {
+                if choice == ui::UserChoice::RetryModel {
+                    let models = llm::installed_models(&options.ollama_url, &extract.client)?;
+                    model = ui::select_model(&models, &model)?;
+                } else {
+                    let current = match &options.extract.prompt_template {
+                        Some(template) => template.clone(),
+                        None => llm::metadata_prompt(extract),
+                    };
+                    let edited = match ui::edit_prompt(&current) {
+                        Ok(edited) => edited,
+                        Err(e) => {
+                            ui::display_error(&format!("{:#}", e));
+                            continue;
+                        }
+                    };
+                    if !edited.contains(llm::PAPER_TEXT) {
+                        ui::display_warning(&format!(
+                            "The edited prompt no longer contains {}, where the paper text goes; keeping the previous prompt",
+                            llm::PAPER_TEXT
+                        ));
+                        continue;
+                    }
+                    // The edited prompt stays in use for retries and the remaining files
+                    options.extract.prompt_template = Some(edited);
+                }
+                let extract = &llm::ExtractOptions {
+                    prompt_template: options.extract.prompt_template.clone(),
+                    ..extract.clone()
+                };
+
+                let spinner = ui::create_spinner(&format!("Extracting metadata using {}...", model));
+                let started = std::time::Instant::now();
+                let retried = match &options.citation {
+                    Some(citation) => {
+                        llm::extract_metadata_from_citation(citation, &options.ollama_url, &model, extract)
+                    }
+                    None => llm::extract_metadata_with_ollama(
+                        &page_text,
+                        title_hint.as_deref(),
+                        &options.ollama_url,
+                        &model,
+                        extract,
+                    ),
+                };
+                options.usage.record(&model, started.elapsed());
+                let mut retried = match retried {
+                    Ok(retried) => retried,
+                    Err(e) => {
+                        spinner.finish_and_clear();
+                        ui::display_error(&format!("{} could not extract the metadata: {:#}", model, e));
+                        continue;
+                    }
+                };
+                ui::finish_spinner(spinner, "Metadata extracted successfully");
+
+                // Only the model's own answers are replaced: fields detected on the
+                // page or hashed from the file stay, as do any the new model left blank
+                for field in llm::empty_fields(&retried) {
+                    match field {
+                        "author" => retried.first_author = metadata.first_author.clone(),
+                        "year" => retried.year = metadata.year.clone(),
+                        _ => retried.title = metadata.title.clone(),
+                    }
+                }
+                metadata = llm::PaperMetadata {
+                    version: std::mem::take(&mut metadata.version),
+                    category: std::mem::take(&mut metadata.category),
+                    doc_type: std::mem::take(&mut metadata.doc_type),
+                    publisher: std::mem::take(&mut metadata.publisher),
+                    doi: std::mem::take(&mut metadata.doi),
+                    content_hash: std::mem::take(&mut metadata.content_hash),
+                    ..retried
+                };
+                options.overrides.apply(&mut metadata, &mut sources);
+
+                // Display updated metadata
+                ui::display_metadata(&metadata);
+
+                // Regenerate filename with updated metadata
+                let Some(regenerated) = propose(&metadata)? else {
+                    return declined();
+                };
+                proposed_filename = regenerated;
             }
             ui::UserChoice::Edit => {
                 // Let user edit the filename
                 proposed_filename = ui::edit_filename(&proposed_filename)?;
 
-                // Ensure it still ends with .pdf
-                if !proposed_filename.ends_with(".pdf") {
-                    proposed_filename.push_str(".pdf");
-                }
+                // Exactly one extension, spelled like generated names, whatever was typed
+                proposed_filename = filename::normalize_extension(&proposed_filename, &naming.extension);
             }
             ui::UserChoice::EditAuthor => {
                 // Let user edit the author
                 metadata.first_author = ui::edit_author(&metadata.first_author)?;
+                metadata.evidence.remove("first_author");
 
                 // Display updated metadata
-                ui::display_metadata(&metadata.first_author, &metadata.year, &metadata.title);
+                ui::display_metadata(&metadata);
 
                 // Regenerate filename with updated metadata
-                proposed_filename = filename::generate_filename(&metadata);
+                let Some(regenerated) = propose(&metadata)? else {
+                    return declined();
+                };
+                proposed_filename = regenerated;
             }
             ui::UserChoice::EditYear => {
                 // Let user edit the year
                 metadata.year = ui::edit_year(&metadata.year)?;
+                metadata.evidence.remove("year");
 
                 // Display updated metadata
-                ui::display_metadata(&metadata.first_author, &metadata.year, &metadata.title);
+                ui::display_metadata(&metadata);
 
                 // Regenerate filename with updated metadata
-                proposed_filename = filename::generate_filename(&metadata);
+                let Some(regenerated) = propose(&metadata)? else {
+                    return declined();
+                };
+                proposed_filename = regenerated;
             }
             ui::UserChoice::EditTitle => {
                 // Let user edit the title
                 metadata.title = ui::edit_title(&metadata.title)?;
+                metadata.title_slug.clear();
+                metadata.evidence.remove("title");
 
                 // Display updated metadata
-                ui::display_metadata(&metadata.first_author, &metadata.year, &metadata.title);
+                ui::display_metadata(&metadata);
 
                 // Regenerate filename with updated metadata
-                proposed_filename = filename::generate_filename(&metadata);
+                let Some(regenerated) = propose(&metadata)? else {
+                    return declined();
+                };
+                proposed_filename = regenerated;
             }
         }
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_definition() {
+        // Catches conflicts and requirements that point at arguments missing
+        // from a subcommand, which clap otherwise only reports at runtime
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_env_variable_beats_settings_file() {
+        // Only this test reads PAPER_RENAMER_NON_LATIN and PAPER_RENAMER_MODEL
+        std::env::set_var("PAPER_RENAMER_NON_LATIN", "translit");
+        std::env::set_var("PAPER_RENAMER_MODEL", "qwen2.5");
+        let matches = Args::command().try_get_matches_from(["paper-renamer", "paper.pdf"]).unwrap();
+        std::env::remove_var("PAPER_RENAMER_NON_LATIN");
+        std::env::remove_var("PAPER_RENAMER_MODEL");
+        let args = Args::from_arg_matches(&matches).unwrap();
+        let explicit = explicit_ids(&matches);
+
+        let mut naming = filename::NamingOptions {
+            non_latin: args.non_latin,
+            ..filename::NamingOptions::default()
+        };
+        // The same holds for the user's settings file, which takes the same keys
+        let config = config::DirConfig {
+            model: Some("llama3.2".to_string()),
+            non_latin: Some(filename::NonLatin::Romaji),
+            title_acronym: Some(true),
+            ..config::DirConfig::default()
+        };
+        config.apply(&mut naming, &explicit);
+
+        assert_eq!(naming.non_latin, filename::NonLatin::Translit);
+        assert_eq!(config.model(&explicit), None);
+        assert_eq!(args.model.as_deref(), Some("qwen2.5"));
+        // Options left at their default still come from the file
+        assert!(naming.title_acronym);
+    }
+
+    #[test]
+    fn test_apply_logs_every_rename() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let plans: Vec<renamer::RenamePlan> = ["a.pdf", "b.pdf"]
+            .iter()
+            .zip(["smith-2020-deep-learning.pdf", "jones-2021-graphs.pdf"])
+            .map(|(original, new_filename)| {
+                let source = temp_dir.path().join(original);
+                std::fs::File::create(&source).unwrap();
+                renamer::RenamePlan {
+                    source: source.to_string_lossy().into_owned(),
+                    new_filename: new_filename.to_string(),
+                    ..renamer::RenamePlan::default()
+                }
+            })
+            .collect();
+        let log = history::RenameLog {
+            path: temp_dir.path().join("renames.log").to_string_lossy().into_owned(),
+            format: history::LogFormat::Plain,
+        };
+
+        let renamed = apply(&plans, renamer::Transfer::Rename, false, None, Some(&log), "llama3.2").unwrap();
+        assert_eq!(renamed.len(), 2);
+
+        let contents = std::fs::read_to_string(&log.path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains(" renamed ") && lines[0].ends_with("smith-2020-deep-learning.pdf (model: llama3.2)"));
+        assert!(lines[1].ends_with("jones-2021-graphs.pdf (model: llama3.2)"));
+    }
+
+    #[test]
+    fn test_applied_entries_after_failure() {
+        let plans: Vec<renamer::RenamePlan> = ["a.pdf", "b.pdf"]
+            .iter()
+            .map(|name| renamer::RenamePlan {
+                source: format!("/papers/{}", name),
+                new_filename: format!("new-{}", name),
+                ..renamer::RenamePlan::default()
+            })
+            .collect();
+        let applied = Err(renamer::ApplyError {
+            completed: vec![renamer::RenameRecord {
+                from: std::path::PathBuf::from("/papers/a.pdf"),
+                to: std::path::PathBuf::from("/papers/new-a.pdf"),
+            }],
+            error: anyhow::anyhow!("Failed to rename /papers/b.pdf"),
+        });
+
+        let entries = applied_entries(&plans, &applied);
+        assert_eq!(entries[0].status, report::Status::Renamed);
+        assert_eq!(entries[0].new_name.as_deref(), Some("new-a.pdf"));
+        assert_eq!(entries[1].status, report::Status::Failed);
+        assert_eq!(entries[1].new_name, None);
+        assert_eq!(entries[1].message.as_deref(), Some("Failed to rename /papers/b.pdf"));
+    }
 }
@@ -1,68 +1,907 @@
+use crate::llm;
 use anyhow::{Context, Result};
-use pdf_extract::extract_text;
+use clap::ValueEnum;
+use pdf_extract::{
+    decode_text_string, Document, MediaBox, Object, OutputDev, OutputError, PlainTextOutput, Transform,
+};
+use regex::Regex;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-/// Extracts text from a PDF file, focusing on the first few pages
-/// which typically contain the paper's metadata
-pub fn extract_pdf_text(file_path: &str) -> Result<String> {
+/// A first page with less text than this is considered a cover sheet
+const COVER_PAGE_MAX_CHARS: usize = 400;
+
+/// Default minimum amount of extracted text worth sending to the LLM
+pub const DEFAULT_MIN_TEXT_LENGTH: usize = 200;
+
+/// Default budget of estimated tokens sent to the LLM, enough for the title
+/// page of nearly every paper
+pub const DEFAULT_MAX_TOKENS: usize = 750;
+
+/// Default number of pages read from the start of each PDF: room for a cover
+/// sheet, the title page and the page after it
+pub const DEFAULT_MAX_PAGES: u32 = 3;
+
+/// Phrases stamped on the cover sheets JSTOR and Taylor & Francis add to downloads
+/// Looser phrases such as "provided by" or "downloaded from" also turn up in
+/// funding notes and on the article's own first page, so they are not used
+const COVER_PAGE_PHRASES: &[&str] = &[
+    "this content downloaded from",
+    "your use of the jstor archive",
+    "about.jstor.org/terms",
+    "this article was downloaded by",
+    "full terms & conditions of access and use",
+];
+
+/// Publishers recognised in copyright lines and document information, with
+/// the patterns (matched case-insensitively, as whole words) of their common forms
+const PUBLISHERS: &[(&str, &str)] = &[
+    ("ACM", r"acm|association for computing machinery"),
+    ("IEEE", r"ieee|institute of electrical and electronics engineers"),
+    ("Springer", r"springer(?:[- ]verlag| nature)?"),
+    ("Elsevier", r"elsevier"),
+    ("Wiley", r"(?:john )?wiley"),
+    ("MIT Press", r"mit press"),
+    ("Cambridge University Press", r"cambridge university press"),
+    ("Oxford University Press", r"oxford university press"),
+    ("Taylor & Francis", r"taylor (?:&|and) francis"),
+    ("SAGE", r"sage publications"),
+    ("AAAI", r"aaai|association for the advancement of artificial intelligence"),
+    ("ACL", r"association for computational linguistics"),
+    ("PMLR", r"pmlr|proceedings of machine learning research"),
+    ("MDPI", r"mdpi"),
+    ("PLOS", r"plos"),
+    ("APS", r"american physical society"),
+    ("IOP Publishing", r"iop publishing"),
+];
+
+/// What kind of document a paper is, for the {type} placeholder and --only-type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DocType {
+    Journal,
+    Conference,
+    Preprint,
+    Thesis,
+}
+
+impl DocType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DocType::Journal => "journal",
+            DocType::Conference => "conference",
+            DocType::Preprint => "preprint",
+            DocType::Thesis => "thesis",
+        }
+    }
+}
+
+/// Phrases on the first pages that give away the document type (lowercase),
+/// checked in this order: a thesis or a paper under submission may well cite a
+/// conference, and published papers are often shared with an arXiv stamp
+const DOC_TYPE_CUES: &[(DocType, &[&str])] = &[
+    (
+        DocType::Thesis,
+        &[
+            "phd thesis",
+            "ph.d. thesis",
+            "doctoral thesis",
+            "doctoral dissertation",
+            "master's thesis",
+            "master thesis",
+            "a dissertation submitted",
+            "partial fulfillment of the requirements",
+            "partial fulfilment of the requirements",
+        ],
+    ),
+    (DocType::Preprint, &["submitted to", "under review", "preprint"]),
+    (
+        DocType::Conference,
+        &["proceedings of", "conference on", "symposium on", "workshop on", "annual meeting of"],
+    ),
+    (DocType::Journal, &["journal of", "transactions on", "issn", "manuscript received"]),
+    (DocType::Preprint, &["arxiv:", "biorxiv", "medrxiv"]),
+];
+
+/// Classify the document from header and footer cues on its first pages
+/// ("Proceedings of", "PhD Thesis", "Submitted to", ...); None if nothing gives it away
+pub fn detect_doc_type(text: &str) -> Option<DocType> {
+    let lower = text.to_lowercase();
+    DOC_TYPE_CUES
+        .iter()
+        .find(|(_, cues)| cues.iter().any(|cue| lower.contains(cue)))
+        .map(|(doc_type, _)| *doc_type)
+}
+
+/// Words marking a line of the page as the one naming the publisher
+const PUBLISHER_CUES: &[&str] = &[
+    "©",
+    "(c)",
+    "copyright",
+    "published by",
+    "publisher",
+    "isbn",
+    "issn",
+    "doi",
+    "reference format",
+    "proceedings",
+    "transactions",
+];
+
+/// Default share of implausible words above which extracted text counts as garbled
+pub const DEFAULT_GIBBERISH_THRESHOLD: f64 = 0.5;
+
+/// A text with at least this many Latin words is expected to contain some
+/// common function words; without any, its words are taken as scrambled
+const FUNCTION_WORD_SAMPLE: usize = 40;
+
+/// Very common short words of English and the other major languages papers
+/// are written in. A broken font mapping turns them into nonsense, so their
+/// complete absence from a long text gives it away
+const FUNCTION_WORDS: &[&str] = &[
+    "the", "of", "and", "to", "in", "is", "for", "that", "with", "on", "as", "we", "this", "by", "are", "be",
+    "from", "an", "our", "which", "it", "or", "at", "can", "not", "der", "die", "und", "das", "ist", "mit",
+    "le", "la", "les", "et", "des", "est", "une", "el", "los", "en", "y", "con", "di", "il", "che", "um",
+];
+
+/// Options controlling how text is pulled out of a PDF
+#[derive(Debug, Clone, Default)]
+pub struct TextOptions {
+    /// Always skip the first page, even if it doesn't look like a cover sheet
+    pub skip_cover: bool,
+    /// Treat extraction as failed when fewer non-whitespace characters than this are found
+    pub min_text_length: usize,
+    /// Cut the text to about this many tokens; 0 sends all of it
+    pub max_tokens: usize,
+    /// Only read this many pages from the start of the document; 0 reads them all
+    pub max_pages: u32,
+    /// Reject text whose share of implausible words is above this; 0 turns the check off
+    pub gibberish_threshold: f64,
+    /// Send this many pages from the title page on, each cut to `max_tokens` on its
+    /// own, so a long title page can't crowd out the next; None cuts the text as a whole
+    pub merge_pages: Option<u32>,
+}
+
+impl TextOptions {
+    /// Options for the `pass`-th retry of a low-confidence extraction: every
+    /// retry reads twice the tokens and pages of the one before. Limits of 0
+    /// (everything) stay as they are
+    pub fn widened(&self, pass: u32) -> TextOptions {
+        TextOptions {
+            max_tokens: self.max_tokens.saturating_mul(2usize.saturating_pow(pass)),
+            max_pages: self.max_pages.saturating_mul(2u32.saturating_pow(pass)),
+            ..self.clone()
+        }
+    }
+}
+
+/// Text extracted from a PDF, ready to be sent to the LLM
+#[derive(Debug, Clone)]
+pub struct PdfText {
+    /// Text of the title page onwards, truncated to what the LLM needs
+    pub text: String,
+    /// 1-based number of the page the text starts at (the title page)
+    pub title_page: u32,
+    /// DOI found in the text, which may lie past the part sent to the LLM
+    /// (many publishers print it at the foot of the title page)
+    pub doi: Option<String>,
+}
+
+/// Parse a PDF once for every reader below, decrypting it if it only has an
+/// owner password. Attached files (such as the datasets bundled with
+/// supplementary material) are never loaded, so huge PDFs cost little more
+/// than small ones
+pub fn load_document(file_path: &str) -> Result<Document> {
     // Suppress both stdout and stderr output from pdf_extract crate
     // The crate outputs debug information that clutters the terminal
-    let text = suppress_output(|| extract_text(file_path))
-        .context("Failed to extract text from PDF")?;
+    let load = || -> Result<Document, OutputError> {
+        let mut doc = Document::load_filtered(file_path, skip_attachment)?;
+        if doc.is_encrypted() {
+            doc.decrypt("")?;
+        }
+        Ok(doc)
+    };
+    suppress_output(load).context("Failed to extract text from PDF")
+}
+
+/// Extracts text from a PDF, focusing on the first few pages
+/// which typically contain the paper's metadata
+/// A library cover sheet on page one is skipped so the real title page is used
+pub fn extract_pdf_text(doc: &Document, options: &TextOptions) -> Result<PdfText> {
+    // Merged pages are read even past --pages, with one more for a cover sheet
+    let max_pages = match options.merge_pages {
+        Some(merge) if options.max_pages > 0 => options.max_pages.max(merge + 1),
+        _ => options.max_pages,
+    };
+    let pages = suppress_output(|| extract_pages(doc, max_pages));
 
-    if text.trim().is_empty() {
-        anyhow::bail!("No text could be extracted from the PDF. The file may be a scanned image.");
+    let start = first_content_page(&pages, options.skip_cover);
+    let text = clean_text(&pages[start..].concat());
+
+    // With next to no text the LLM only guesses, so don't ask it
+    check_text_length(&text, options.min_text_length)?;
+
+    // Nor when a broken font mapping turned the text into nonsense
+    check_gibberish(&text, options.gibberish_threshold)?;
+
+    // The start of the paper contains the metadata; sending only that
+    // keeps requests fast and within the model's context
+    let limit = |text: &str| -> String {
+        if options.max_tokens > 0 {
+            truncate_to_tokens(text, options.max_tokens).to_string()
+        } else {
+            text.to_string()
+        }
+    };
+    let truncated = match options.merge_pages {
+        Some(merge) => pages[start..]
+            .iter()
+            .take(merge as usize)
+            .map(|page| limit(&clean_text(page)))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        None => limit(&text),
+    };
+
+    Ok(PdfText {
+        text: truncated,
+        title_page: start as u32 + 1,
+        doi: detect_doi(&text),
+    })
+}
+
+/// Extract the text of the first `max_pages` pages (all of them if 0), one
+/// string per page. Only these pages are laid out. Extraction stops at the
+/// first page that can't be read
+fn extract_pages(doc: &Document, max_pages: u32) -> Vec<String> {
+    let count = doc.get_pages().len() as u32;
+    let last = if max_pages == 0 { count } else { count.min(max_pages) };
+    (1..=last)
+        .map_while(|page| {
+            let mut text = String::new();
+            pdf_extract::output_doc_page(doc, &mut PlainTextOutput::new(&mut text), page).ok()?;
+            Some(text)
+        })
+        .collect()
+}
+
+/// Load filter dropping embedded file streams, which pages never draw from
+fn skip_attachment(id: (u32, u16), object: &mut Object) -> Option<((u32, u16), Object)> {
+    match object {
+        Object::Stream(stream) if stream.dict.type_is(b"EmbeddedFile") => None,
+        _ => Some((id, object.clone())),
     }
+}
 
-    // Return first ~3000 characters which should contain metadata
-    // This reduces the amount of text we need to send to the LLM
-    let truncated = if text.len() > 3000 {
-        &text[..3000]
-    } else {
-        &text
+/// Width in pixels pages are rendered at: sharp enough for OCR and vision models
+#[cfg(feature = "render")]
+pub const RENDER_WIDTH: i32 = 1600;
+
+/// Rasterize one page of a PDF (0-based `page_index`) at `RENDER_WIDTH` pixels wide
+/// Every feature that looks at pages as images goes through this. Pdfium is
+/// loaded at run time: a Pdfium library next to the executable if there is one,
+/// otherwise the system's
+#[cfg(feature = "render")]
+pub fn render_page(file_path: &str, page_index: u32) -> Result<image::DynamicImage> {
+    use pdfium_render::prelude::{PdfRenderConfig, Pdfium};
+
+    let exe_dir = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(std::path::Path::to_path_buf))
+        .unwrap_or_default();
+    let bindings = Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(&exe_dir))
+        .or_else(|_| Pdfium::bind_to_system_library())
+        .context("Failed to load the Pdfium library, which rendering pages needs")?;
+    let pdfium = Pdfium::new(bindings);
+
+    let document = pdfium
+        .load_pdf_from_file(file_path, None)
+        .with_context(|| format!("Failed to open {}", file_path))?;
+    let page = u16::try_from(page_index)
+        .ok()
+        .and_then(|index| document.pages().get(index).ok())
+        .with_context(|| format!("{} has no page {}", file_path, page_index + 1))?;
+
+    let config = PdfRenderConfig::new()
+        .set_target_width(RENDER_WIDTH)
+        .set_maximum_height(RENDER_WIDTH * 2);
+    let bitmap = page
+        .render_with_config(&config)
+        .with_context(|| format!("Failed to render page {} of {}", page_index + 1, file_path))?;
+    Ok(bitmap.as_image())
+}
+
+/// Cut text to about `max_tokens` tokens, always at a character boundary
+/// Tokens are estimated rather than counted: roughly four ASCII characters
+/// make a token, while other characters (accents, CJK) cost about one each
+fn truncate_to_tokens(text: &str, max_tokens: usize) -> &str {
+    // Costs are in quarter tokens so ASCII characters stay integral
+    let budget = max_tokens * 4;
+    let mut spent = 0;
+
+    for (index, c) in text.char_indices() {
+        spent += if c.is_ascii() { 1 } else { 4 };
+        if spent > budget {
+            return &text[..index];
+        }
+    }
+    text
+}
+
+/// Error for a PDF that yields no or too little text, typically a scanned
+/// image that needs OCR. Callers can tell it apart from a broken file with
+/// `downcast_ref`
+#[derive(Debug, Clone, PartialEq)]
+pub struct InsufficientText {
+    /// Non-whitespace characters extracted
+    pub found: usize,
+    /// The required minimum (--min-text-length)
+    pub minimum: usize,
+}
+
+impl std::fmt::Display for InsufficientText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.found == 0 {
+            write!(f, "No text could be extracted from the PDF. The file may be a scanned image.")
+        } else {
+            write!(
+                f,
+                "Only {} characters of text could be extracted from the PDF (minimum {}). The file may be a scanned image.",
+                self.found, self.minimum
+            )
+        }
+    }
+}
+
+impl std::error::Error for InsufficientText {}
+
+/// Check that enough text was extracted to identify the paper
+fn check_text_length(text: &str, min_length: usize) -> Result<()> {
+    let found = text.chars().filter(|c| !c.is_whitespace()).count();
+    if found == 0 || found < min_length {
+        return Err(InsufficientText {
+            found,
+            minimum: min_length,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Error for a PDF whose text layer decodes to nonsense, typically because its
+/// fonts map glyphs to the wrong characters. Like a scan, it needs OCR
+#[derive(Debug, Clone, PartialEq)]
+pub struct GarbledText {
+    /// Share of implausible words found
+    pub score: f64,
+    /// The allowed share (--gibberish-threshold)
+    pub threshold: f64,
+}
+
+impl std::fmt::Display for GarbledText {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "The PDF's text looks scrambled ({:.0}% of the words are implausible, more than the {:.0}% allowed by --gibberish-threshold). \
+             Its fonts probably map characters wrongly; the file needs OCR.",
+            self.score * 100.0,
+            self.threshold * 100.0
+        )
+    }
+}
+
+impl std::error::Error for GarbledText {}
+
+/// Share of a text's words that can't be real words: ones containing control,
+/// replacement or private-use characters, mostly symbols, or long runs of
+/// Latin letters without a vowel. A long Latin text without a single common
+/// function word ("the", "of", "und", ...) has all its Latin words counted
+pub fn gibberish_score(text: &str) -> f64 {
+    let words: Vec<&str> = text
+        .split_whitespace()
+        .map(|word| word.trim_matches(|c: char| c.is_ascii_punctuation()))
+        .filter(|word| !word.is_empty())
+        .collect();
+    if words.is_empty() {
+        return 0.0;
+    }
+
+    let broken_char = |c: char| {
+        c == '\u{FFFD}' || ('\u{E000}'..='\u{F8FF}').contains(&c) || (c.is_control() && !c.is_whitespace())
+    };
+    let implausible = |word: &str| {
+        let chars = word.chars().count();
+        let symbols = word.chars().filter(|c| !c.is_alphanumeric()).count();
+        let latin = word.chars().all(|c| c.is_ascii_alphabetic());
+        let vowel = word.chars().any(|c| "aeiouyAEIOUY".contains(c));
+        word.chars().any(broken_char) || symbols * 2 > chars || (latin && chars >= 5 && !vowel)
     };
 
-    Ok(truncated.to_string())
+    let latin: Vec<String> = words
+        .iter()
+        .filter(|word| word.chars().all(|c| c.is_ascii_alphabetic()))
+        .map(|word| word.to_lowercase())
+        .collect();
+    let scrambled_latin = latin.len() >= FUNCTION_WORD_SAMPLE
+        && !latin.iter().any(|word| FUNCTION_WORDS.contains(&word.as_str()));
+
+    let bad = words
+        .iter()
+        .filter(|word| implausible(word) || (scrambled_latin && word.chars().all(|c| c.is_ascii_alphabetic())))
+        .count();
+    bad as f64 / words.len() as f64
 }
 
-/// Suppress both stdout and stderr output during function execution
-/// This is used to hide all debug output from the pdf_extract crate
-fn suppress_output<F, T>(func: F) -> T
-where
-    F: FnOnce() -> T,
-{
-    #[cfg(unix)]
-    {
+/// Check that the extracted text reads as text rather than scrambled glyphs
+fn check_gibberish(text: &str, threshold: f64) -> Result<()> {
+    if threshold <= 0.0 {
+        return Ok(());
+    }
+    let score = gibberish_score(text);
+    if score > threshold {
+        return Err(GarbledText { score, threshold }.into());
+    }
+    Ok(())
+}
+
+/// Metadata the authoring tool stored in the PDF's document information
+/// dictionary. Fields that are missing or look like tool defaults are empty
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EmbeddedMetadata {
+    pub first_author: String,
+    pub first_author_full: String,
+    pub year: String,
+    pub title: String,
+    /// A known publisher named in the Subject, Creator or Producer entry
+    pub publisher: String,
+}
+
+/// Read the Title, Author and CreationDate entries of a PDF
+/// Returns None if the file has no information dictionary
+pub fn embedded_metadata(doc: &Document) -> Option<EmbeddedMetadata> {
+    let info = match doc.trailer.get(b"Info").ok()? {
+        Object::Reference(id) => doc.get_object(*id).ok()?,
+        object => object,
+    };
+    let info = info.as_dict().ok()?;
+
+    let entry = |key: &[u8]| {
+        info.get(key)
+            .ok()
+            .and_then(|value| decode_text_string(value).ok())
+            .map(|text| clean_text(text.trim()))
+            .unwrap_or_default()
+    };
+
+    let mut embedded = parse_embedded(&entry(b"Title"), &entry(b"Author"), &entry(b"CreationDate"));
+    embedded.publisher = [b"Subject".as_slice(), b"Creator", b"Producer"]
+        .iter()
+        .find_map(|key| normalize_publisher(&entry(key)))
+        .unwrap_or_default();
+    Some(embedded)
+}
+
+/// Turn raw information dictionary entries into usable metadata
+pub fn parse_embedded(title: &str, author: &str, creation_date: &str) -> EmbeddedMetadata {
+    // Word processors and TeX fill the title with the source file's name
+    let lower = title.to_lowercase();
+    let placeholder_title = lower.is_empty()
+        || lower.starts_with("microsoft word")
+        || lower == "untitled"
+        || [".pdf", ".dvi", ".doc", ".docx", ".tex"].iter().any(|ext| lower.ends_with(ext));
+
+    // Authors are listed as "First Last, First Last" or "Last, First; Last, First"
+    let first_entry = author.split([';', '&']).next().unwrap_or("");
+    let first_entry = first_entry.split(" and ").next().unwrap_or("").trim();
+    let mut parts = first_entry.split(',').map(str::trim);
+    let (first_author, first_author_full) = match (parts.next(), parts.next()) {
+        (Some(last), Some(given)) if !last.contains(' ') && !given.is_empty() => {
+            (last.to_string(), format!("{} {}", given, last))
+        }
+        (Some(name), _) if !name.is_empty() => (
+            name.split_whitespace().last().unwrap_or("").to_string(),
+            name.to_string(),
+        ),
+        _ => (String::new(), String::new()),
+    };
+
+    // Dates look like D:20170612143000Z
+    let year = creation_date
+        .strip_prefix("D:")
+        .and_then(|date| date.get(..4))
+        .filter(|year| llm::is_valid_year(year))
+        .unwrap_or("")
+        .to_string();
+
+    EmbeddedMetadata {
+        first_author,
+        first_author_full,
+        year,
+        title: if placeholder_title { String::new() } else { title.to_string() },
+        publisher: String::new(),
+    }
+}
+
+/// Most bookmarks looked at for the title; a malformed outline can link back to itself
+const MAX_BOOKMARKS: usize = 100;
+
+/// Bookmarks that name a part of the paper rather than the paper itself
+const SECTION_BOOKMARKS: &[&str] = &[
+    "abstract",
+    "acknowledgements",
+    "acknowledgments",
+    "appendix",
+    "bibliography",
+    "contents",
+    "cover",
+    "front matter",
+    "introduction",
+    "preface",
+    "references",
+    "table of contents",
+    "title page",
+];
+
+/// Read the paper's title from the document outline (bookmarks): the first
+/// top-level bookmark that isn't a section heading such as "Introduction" or
+/// "1 Background". Structured PDFs sometimes carry the title there even when
+/// the title page is a scan. Returns None if there is no such bookmark
+pub fn extract_outline_title(doc: &Document) -> Option<String> {
+    let (_, outlines) = doc.dereference(doc.catalog().ok()?.get(b"Outlines").ok()?).ok()?;
+    let mut next = outlines.as_dict().ok()?.get(b"First").ok();
+
+    for _ in 0..MAX_BOOKMARKS {
+        let (_, bookmark) = doc.dereference(next?).ok()?;
+        let bookmark = bookmark.as_dict().ok()?;
+        let title = bookmark
+            .get(b"Title")
+            .ok()
+            .and_then(|title| decode_text_string(title).ok())
+            .map(|title| clean_text(title.trim()))
+            .unwrap_or_default();
+        if is_title_bookmark(&title) {
+            return Some(title);
+        }
+        next = bookmark.get(b"Next").ok();
+    }
+    None
+}
+
+/// Whether a bookmark could be the paper's title: at least two words, not a
+/// known section name, and not numbered like a section ("2.1 Setup", "IV. Results")
+fn is_title_bookmark(title: &str) -> bool {
+    let words: Vec<&str> = title.split_whitespace().collect();
+    let Some(first) = words.first() else {
+        return false;
+    };
+    let numbered = first.starts_with(|c: char| c.is_ascii_digit())
+        || (first.ends_with('.') && first.trim_end_matches('.').chars().all(|c| "IVXLC".contains(c)));
+    let lower = title.to_lowercase();
+    let section = SECTION_BOOKMARKS.contains(&lower.as_str())
+        || ["chapter ", "section ", "appendix "].iter().any(|prefix| lower.starts_with(prefix));
+
+    words.len() >= 2 && !numbered && !section
+}
+
+/// Find the contiguous run of text set in the largest font on a page
+/// On most paper templates this is the title, so it makes a strong hint
+/// for the LLM. Returns None if the page can't be laid out or has no such run
+pub fn largest_text_block(doc: &Document, page: u32) -> Option<String> {
+    let mut collector = FontSizeCollector::default();
+    suppress_output(|| pdf_extract::output_doc_page(doc, &mut collector, page)).ok()?;
+    collector.largest().map(|title| clean_text(&title))
+}
+
+/// Undo common PDF text extraction artifacts
+/// Typographic ligatures are expanded, curly quotes and dashes become ASCII,
+/// and private-use glyphs and invisible characters are dropped
+pub fn clean_text(text: &str) -> String {
+    let mut cleaned = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            'ﬀ' => cleaned.push_str("ff"),
+            'ﬁ' => cleaned.push_str("fi"),
+            'ﬂ' => cleaned.push_str("fl"),
+            'ﬃ' => cleaned.push_str("ffi"),
+            'ﬄ' => cleaned.push_str("ffl"),
+            'ﬅ' | 'ﬆ' => cleaned.push_str("st"),
+            '‘' | '’' | '‚' | '‛' | '′' => cleaned.push('\''),
+            '“' | '”' | '„' | '‟' | '″' => cleaned.push('"'),
+            '‐' | '‑' | '‒' | '–' | '—' | '―' | '−' => cleaned.push('-'),
+            '\u{00A0}' | '\u{2007}' | '\u{202F}' => cleaned.push(' '),
+            // Soft hyphens, zero-width characters and byte order marks
+            '\u{00AD}' | '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' => {}
+            // Private-use glyphs are font-specific and carry no meaning
+            '\u{E000}'..='\u{F8FF}' | '\u{F0000}'..='\u{10FFFF}' => {}
+            _ => cleaned.push(c),
+        }
+    }
+
+    cleaned
+}
+
+/// Collects text on a page into runs of characters sharing the same font size
+/// Word gaps are detected from character positions, like pdf_extract's plain text output
+#[derive(Debug, Default)]
+struct FontSizeCollector {
+    runs: Vec<(f64, String)>,
+    /// Position where the previous character ended
+    last_end: f64,
+    last_y: f64,
+    /// Set at the start of each word, where a gap may need a space
+    word_start: bool,
+}
+
+impl FontSizeCollector {
+    /// Sizes closer than this are treated as the same font size
+    const SIZE_TOLERANCE: f64 = 0.5;
+
+    fn push_char(&mut self, size: f64, text: &str) {
+        match self.runs.last_mut() {
+            Some((run_size, run)) if (*run_size - size).abs() < Self::SIZE_TOLERANCE => {
+                run.push_str(text)
+            }
+            _ => self.runs.push((size, text.to_string())),
+        }
+    }
+
+    fn push_space(&mut self) {
+        if let Some((_, run)) = self.runs.last_mut() {
+            if !run.ends_with(' ') {
+                run.push(' ');
+            }
+        }
+    }
+
+    /// The largest-font run with some real words in it, whitespace collapsed
+    fn largest(&self) -> Option<String> {
+        self.runs
+            .iter()
+            .filter(|(_, run)| run.chars().filter(|c| c.is_alphanumeric()).count() >= 3)
+            .fold(None, |best: Option<&(f64, String)>, candidate| match best {
+                Some(best) if best.0 >= candidate.0 => Some(best),
+                _ => Some(candidate),
+            })
+            .map(|(_, run)| run.split_whitespace().collect::<Vec<_>>().join(" "))
+    }
+}
+
+impl OutputDev for FontSizeCollector {
+    fn begin_page(&mut self, _page_num: u32, _media_box: &MediaBox, _art_box: Option<(f64, f64, f64, f64)>) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_page(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn output_character(&mut self, trm: &Transform, width: f64, _spacing: f64, font_size: f64, char: &str) -> Result<(), OutputError> {
+        // The text matrix scales the nominal font size to its rendered size
+        let size = font_size * (trm.m11 * trm.m22 - trm.m12 * trm.m21).abs().sqrt();
+        let (x, y) = (trm.m31, trm.m32);
+
+        if self.word_start && (x > self.last_end + size * 0.1 || (y - self.last_y).abs() > size * 0.5) {
+            self.push_space();
+        }
+
+        self.push_char(size, char);
+        self.word_start = false;
+        self.last_end = x + width * size;
+        self.last_y = y;
+        Ok(())
+    }
+
+    fn begin_word(&mut self) -> Result<(), OutputError> {
+        self.word_start = true;
+        Ok(())
+    }
+
+    fn end_word(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+
+    fn end_line(&mut self) -> Result<(), OutputError> {
+        Ok(())
+    }
+}
+
+/// Index of the first page worth sending to the LLM
+/// Page one is skipped when forced or when it looks like a cover sheet,
+/// as long as there is another page to fall back on
+fn first_content_page(pages: &[String], force_skip: bool) -> usize {
+    if pages.len() < 2 {
+        return 0;
+    }
+
+    if force_skip || is_cover_page(&pages[0]) {
+        1
+    } else {
+        0
+    }
+}
+
+/// Check whether a page looks like a cover/separator sheet rather than a title page:
+/// very little text, or a JSTOR or Taylor & Francis download stamp
+fn is_cover_page(text: &str) -> bool {
+    let text = text.trim();
+    if text.chars().count() < COVER_PAGE_MAX_CHARS {
+        return true;
+    }
+
+    let lower = text.to_lowercase();
+    COVER_PAGE_PHRASES.iter().any(|phrase| lower.contains(phrase))
+}
+
+/// Detect a preprint version marker such as the `v2` in `arXiv:2001.01234v2`
+/// The PDF text is checked first (arXiv stamps the version in the margin),
+/// then the original filename. Returns e.g. `Some("v2")`
+pub fn detect_version(text: &str, filename: &str) -> Option<String> {
+    let arxiv_id = Regex::new(r"(?:\d{4}\.\d{4,5}|[a-z\-]+(?:\.[A-Z]{2})?/\d{7})(v\d+)\b").unwrap();
+
+    [text, filename].iter().find_map(|source| {
+        arxiv_id
+            .captures(source)
+            .map(|captures| captures[1].to_string())
+    })
+}
+
+/// Detect the primary arXiv category, such as `cs.LG`, from the stamp arXiv
+/// puts in the margin (`arXiv:2001.01234v2 [cs.LG] 3 Jan 2020`). Old-style
+/// IDs carry the archive in the ID itself (`hep-th/9901001`)
+pub fn detect_category(text: &str) -> Option<String> {
+    let stamp = Regex::new(r"arXiv:\s*(?:\d{4}\.\d{4,5}|[a-z\-]+(?:\.[A-Z]{2})?/\d{7})(?:v\d+)?\s*\[([a-z\-]+(?:\.[A-Za-z\-]+)?)\]").unwrap();
+    let old_style_id = Regex::new(r"arXiv:\s*([a-z\-]+(?:\.[A-Z]{2})?)/\d{7}").unwrap();
+
+    [stamp, old_style_id]
+        .iter()
+        .find_map(|regex| regex.captures(text).map(|captures| captures[1].to_string()))
+}
+
+/// Find the paper's DOI, such as `10.1145/3292500.3330701`, in the page text
+/// The first one is taken, as the paper's own DOI comes before those of references
+pub fn detect_doi(text: &str) -> Option<String> {
+    let doi = Regex::new(r"\b10\.\d{4,9}/[-._;()/:A-Za-z0-9]+").unwrap();
+    doi.find(text)
+        .map(|found| found.as_str().trim_end_matches(['.', ',', ';', ':', ')']).to_string())
+}
+
+/// The usual short name of a known publisher mentioned in `text`,
+/// e.g. `ACM` for "Association for Computing Machinery"
+pub fn normalize_publisher(text: &str) -> Option<String> {
+    PUBLISHERS.iter().find_map(|(name, pattern)| {
+        let regex = Regex::new(&format!(r"(?i)\b(?:{})\b", pattern)).unwrap();
+        regex.is_match(text).then(|| name.to_string())
+    })
+}
+
+/// Detect the publisher from a copyright line, reference format or similar
+/// footer on the page (`© 2017 IEEE`, `ACM ISBN 978-1-4503-...`)
+/// Only lines with such a cue count, so affiliations and citations are ignored
+pub fn detect_publisher(text: &str) -> Option<String> {
+    text.lines()
+        .filter(|line| {
+            let lower = line.to_lowercase();
+            PUBLISHER_CUES.iter().any(|cue| lower.contains(cue))
+        })
+        .find_map(normalize_publisher)
+}
+
+/// Find a plausible publication year in a filename, e.g. `2020` in
+/// `neurips2020_submission.pdf`. Years must be between 1900 and next year,
+/// and the first part of a new-style arXiv ID (`2012.01234`) is not a year
+pub fn year_from_filename(filename: &str) -> Option<String> {
+    let candidate = Regex::new(r"(?:19|20)\d{2}").unwrap();
+    let next_year = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| 1970 + elapsed.as_secs() / 31_556_952 + 1)
+        .unwrap_or(u64::MAX);
+
+    let year = candidate
+        .find_iter(filename)
+        .filter(|found| {
+            let before = filename[..found.start()].chars().next_back();
+            let mut after = filename[found.end()..].chars();
+            let next = after.next();
+            let arxiv_id = next == Some('.') && after.next().is_some_and(|c| c.is_ascii_digit());
+
+            !before.is_some_and(|c| c.is_ascii_digit())
+                && !next.is_some_and(|c| c.is_ascii_digit())
+                && !arxiv_id
+        })
+        .map(|found| found.as_str())
+        .find(|year| year.parse::<u64>().is_ok_and(|year| year <= next_year))
+        .map(str::to_string);
+    year
+}
+
+/// Serializes `suppress_output`. The redirection swaps the process-wide stdout
+/// and stderr descriptors, so two overlapping calls (e.g. extractions on several
+/// threads) would save each other's /dev/null as the "original" and leave the
+/// terminal silenced for good
+#[cfg(unix)]
+static SUPPRESS_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+/// Stdout and stderr pointed somewhere else until dropped, which puts them back
+/// even if the function in between panics
+#[cfg(unix)]
+struct Redirect {
+    saved_stdout: i32,
+    saved_stderr: i32,
+}
+
+#[cfg(unix)]
+impl Redirect {
+    const STDOUT: i32 = 1;
+    const STDERR: i32 = 2;
+
+    /// Point stdout and stderr at `target`; None (and nothing changed) if the
+    /// current descriptors can't be saved
+    fn to(target: &std::fs::File) -> Option<Self> {
+        use std::io::Write;
         use std::os::unix::io::AsRawFd;
 
-        // Save the original stdout and stderr
-        let stdout_fd = std::io::stdout().as_raw_fd();
-        let stderr_fd = std::io::stderr().as_raw_fd();
-        let original_stdout = unsafe { libc::dup(stdout_fd) };
-        let original_stderr = unsafe { libc::dup(stderr_fd) };
+        // Output still buffered belongs on the terminal, not in /dev/null
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
 
-        // Redirect both stdout and stderr to /dev/null
-        let dev_null = std::fs::OpenOptions::new()
-            .write(true)
-            .open("/dev/null")
-            .expect("Failed to open /dev/null");
+        let saved_stdout = unsafe { libc::dup(Self::STDOUT) };
+        let saved_stderr = unsafe { libc::dup(Self::STDERR) };
+        if saved_stdout < 0 || saved_stderr < 0 {
+            unsafe {
+                libc::close(saved_stdout);
+                libc::close(saved_stderr);
+            }
+            return None;
+        }
 
         unsafe {
-            libc::dup2(dev_null.as_raw_fd(), stdout_fd);
-            libc::dup2(dev_null.as_raw_fd(), stderr_fd);
+            libc::dup2(target.as_raw_fd(), Self::STDOUT);
+            libc::dup2(target.as_raw_fd(), Self::STDERR);
         }
+        Some(Self {
+            saved_stdout,
+            saved_stderr,
+        })
+    }
+}
 
-        // Execute the function
-        let result = func();
+#[cfg(unix)]
+impl Drop for Redirect {
+    fn drop(&mut self) {
+        use std::io::Write;
 
-        // Restore original stdout and stderr
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
         unsafe {
-            libc::dup2(original_stdout, stdout_fd);
-            libc::dup2(original_stderr, stderr_fd);
-            libc::close(original_stdout);
-            libc::close(original_stderr);
+            libc::dup2(self.saved_stdout, Self::STDOUT);
+            libc::dup2(self.saved_stderr, Self::STDERR);
+            libc::close(self.saved_stdout);
+            libc::close(self.saved_stderr);
         }
+    }
+}
 
-        result
+/// Suppress both stdout and stderr output during function execution
+/// This is used to hide all debug output from the pdf_extract crate
+///
+/// The redirection is process-wide: calls are serialized, so it is safe to
+/// extract on several threads, but anything another thread prints meanwhile
+/// (e.g. a spinner) is discarded too. If /dev/null can't be opened or the
+/// descriptors can't be saved, the function runs with its output shown
+fn suppress_output<F, T>(func: F) -> T
+where
+    F: FnOnce() -> T,
+{
+    #[cfg(unix)]
+    {
+        // A panic while holding the lock leaves nothing half done, since
+        // `Redirect` restores the descriptors while unwinding
+        let _lock = SUPPRESS_LOCK.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        let Ok(dev_null) = std::fs::OpenOptions::new().write(true).open("/dev/null") else {
+            return func();
+        };
+        let _redirect = Redirect::to(&dev_null);
+        func()
     }
 
     #[cfg(not(unix))]
@@ -77,6 +916,406 @@ where
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_detect_version() {
+        assert_eq!(
+            detect_version("arXiv:1706.03762v5 [cs.CL] 6 Dec 2017", "paper.pdf"),
+            Some("v5".to_string())
+        );
+        assert_eq!(
+            detect_version("no stamp here", "2001.01234v2.pdf"),
+            Some("v2".to_string())
+        );
+        assert_eq!(
+            detect_version("arXiv:hep-th/9901001v3", "paper.pdf"),
+            Some("v3".to_string())
+        );
+        assert_eq!(detect_version("Published in 2020", "smith2020.pdf"), None);
+    }
+
+    #[test]
+    fn test_truncate_to_tokens() {
+        let ascii = "a".repeat(100);
+        assert_eq!(truncate_to_tokens(&ascii, 10), "a".repeat(40));
+        assert_eq!(truncate_to_tokens(&ascii, 50), ascii);
+
+        // Multi-byte characters cost a whole token each and are never split
+        assert_eq!(truncate_to_tokens("深度学习综述", 3), "深度学");
+        assert_eq!(truncate_to_tokens("aé", 1), "a");
+    }
+
+    #[test]
+    fn test_widened() {
+        let options = TextOptions {
+            max_tokens: 750,
+            max_pages: 3,
+            ..TextOptions::default()
+        };
+        assert_eq!(options.widened(0).max_tokens, 750);
+        assert_eq!(options.widened(2).max_tokens, 3000);
+        assert_eq!(options.widened(2).max_pages, 12);
+
+        // Reading everything can't be widened
+        let everything = TextOptions::default().widened(3);
+        assert_eq!((everything.max_tokens, everything.max_pages), (0, 0));
+    }
+
+    #[test]
+    fn test_parse_embedded() {
+        let embedded = parse_embedded("Attention Is All You Need", "Ashish Vaswani, Noam Shazeer", "D:20170612143000Z");
+        assert_eq!(embedded.first_author, "Vaswani");
+        assert_eq!(embedded.first_author_full, "Ashish Vaswani");
+        assert_eq!(embedded.year, "2017");
+        assert_eq!(embedded.title, "Attention Is All You Need");
+
+        let embedded = parse_embedded("Microsoft Word - draft3.docx", "Vaswani, Ashish; Shazeer, Noam", "");
+        assert_eq!(embedded.first_author, "Vaswani");
+        assert_eq!(embedded.first_author_full, "Ashish Vaswani");
+        assert_eq!(embedded.year, "");
+        assert_eq!(embedded.title, "");
+
+        assert_eq!(parse_embedded("main.pdf", "", "garbage"), EmbeddedMetadata::default());
+    }
+
+    #[test]
+    fn test_detect_publisher() {
+        let text = "Department of Computer Science\nIEEE Fellow\nCopyright 2019 by the Association for Computing Machinery";
+        assert_eq!(detect_publisher(text), Some("ACM".to_string()));
+        assert_eq!(detect_publisher("© Springer-Verlag Berlin Heidelberg 2009"), Some("Springer".to_string()));
+        // A publisher mentioned outside a copyright or reference line doesn't count
+        assert_eq!(detect_publisher("Jane Smith, IEEE Member"), None);
+        assert_eq!(detect_publisher("Copyright 2020 the authors"), None);
+
+        assert_eq!(normalize_publisher("Elsevier B.V."), Some("Elsevier".to_string()));
+        assert_eq!(normalize_publisher("Microsoft Word"), None);
+    }
+
+    #[test]
+    fn test_detect_doc_type() {
+        let detect = |text| detect_doc_type(text).map(DocType::as_str);
+        assert_eq!(detect("In Proceedings of the 36th International Conference on Machine Learning"), Some("conference"));
+        assert_eq!(detect("IEEE TRANSACTIONS ON PATTERN ANALYSIS AND MACHINE INTELLIGENCE"), Some("journal"));
+        assert_eq!(detect("A thesis submitted in partial fulfillment of the requirements for the degree"), Some("thesis"));
+        assert_eq!(detect("Preprint. Under review."), Some("preprint"));
+        assert_eq!(detect("Submitted to the Conference on Neural Information Processing Systems"), Some("preprint"));
+        // A published paper shared on arXiv keeps its venue
+        assert_eq!(detect("arXiv:1706.03762v5 [cs.CL]\nProceedings of NAACL-HLT 2019"), Some("conference"));
+        assert_eq!(detect("arXiv:1706.03762v5 [cs.CL] 6 Dec 2017"), Some("preprint"));
+        assert_eq!(detect("Attention Is All You Need"), None);
+    }
+
+    #[test]
+    fn test_detect_category() {
+        assert_eq!(
+            detect_category("arXiv:1706.03762v5 [cs.CL] 6 Dec 2017"),
+            Some("cs.CL".to_string())
+        );
+        assert_eq!(
+            detect_category("arXiv:2001.01234 [stat.ML]"),
+            Some("stat.ML".to_string())
+        );
+        assert_eq!(
+            detect_category("arXiv:hep-th/9901001v3 22 Jan 1999"),
+            Some("hep-th".to_string())
+        );
+        assert_eq!(detect_category("Published in 2020 [cs.LG]"), None);
+    }
+
+    #[test]
+    fn test_detect_doi() {
+        assert_eq!(
+            detect_doi("KDD '19, August 4-8, 2019. https://doi.org/10.1145/3292500.3330701."),
+            Some("10.1145/3292500.3330701".to_string())
+        );
+        assert_eq!(
+            detect_doi("DOI: 10.1016/j.cell.2020.01.001; received 2019"),
+            Some("10.1016/j.cell.2020.01.001".to_string())
+        );
+        assert_eq!(detect_doi("Version 10.2 of the toolkit"), None);
+    }
+
+    fn page(text: &str) -> String {
+        format!("{} {}", text, "Lorem ipsum dolor sit amet. ".repeat(20))
+    }
+
+    #[test]
+    fn test_year_from_filename() {
+        assert_eq!(year_from_filename("neurips2020_submission.pdf"), Some("2020".to_string()));
+        assert_eq!(year_from_filename("smith-1998-survey.pdf"), Some("1998".to_string()));
+        // arXiv IDs, longer numbers and future years are not publication years
+        assert_eq!(year_from_filename("2012.01234v2.pdf"), None);
+        assert_eq!(year_from_filename("scan_120194.pdf"), None);
+        assert_eq!(year_from_filename("draft-2099.pdf"), None);
+        assert_eq!(year_from_filename("2012.01234-icml2021.pdf"), Some("2021".to_string()));
+        assert_eq!(year_from_filename("paper.pdf"), None);
+    }
+
+    #[test]
+    fn test_is_cover_page() {
+        assert!(is_cover_page("University Library\n\nDigitized copy"));
+        assert!(is_cover_page(&page("This content downloaded from 10.0.0.1 on Mon, 1 Jan 2020")));
+        assert!(!is_cover_page(&page("Attention Is All You Need\nAshish Vaswani")));
+        assert!(is_cover_page(&page("This article was downloaded by: [University of Oslo]")));
+    }
+
+    #[test]
+    fn test_title_page_with_funding_note_is_not_cover() {
+        let title_page = page(
+            "Attention Is All You Need\nAshish Vaswani, Google Brain\n\
+             Funding provided by the National Science Foundation. Data downloaded from the \
+             WMT 2014 website, brought to you by the workshop organizers. For personal use only.",
+        );
+        assert!(!is_cover_page(&title_page));
+        assert_eq!(first_content_page(&[title_page.clone(), page("1 Introduction")], false), 0);
+    }
+
+    #[test]
+    fn test_first_content_page() {
+        let title_page = page("Attention Is All You Need");
+        let cover = "Institutional Repository".to_string();
+
+        assert_eq!(first_content_page(&[cover.clone(), title_page.clone()], false), 1);
+        assert_eq!(first_content_page(&[title_page.clone(), title_page.clone()], false), 0);
+        assert_eq!(first_content_page(&[title_page.clone(), title_page.clone()], true), 1);
+
+        // Never skip the only page
+        assert_eq!(first_content_page(&[cover], true), 0);
+    }
+
+    #[test]
+    fn test_clean_text() {
+        assert_eq!(clean_text("Eﬃcient ﬁne-tuning of ﬂows"), "Efficient fine-tuning of flows");
+        assert_eq!(clean_text("“Attention” isn’t all — or is it?"), "\"Attention\" isn't all - or is it?");
+        assert_eq!(clean_text("Deep\u{00A0}Learn\u{00AD}ing\u{E03A}"), "Deep Learning");
+        assert_eq!(clean_text("Über naïve Bayes"), "Über naïve Bayes");
+    }
+
+    #[test]
+    fn test_gibberish_score() {
+        let english = "Attention Is All You Need. The dominant sequence transduction models are based on \
+                       complex recurrent or convolutional neural networks that include an encoder and a decoder.";
+        assert!(gibberish_score(english) < 0.1);
+        assert!(gibberish_score("深度学习综述 摘要 本文介绍了深度学习的发展") < 0.1);
+
+        // Private-use glyphs and symbol soup from a broken font mapping
+        assert!(gibberish_score("\u{E001}\u{E002}\u{E003} \u{E004}\u{E005} #$%& !\"#$ title") > 0.5);
+
+        // Letters shifted by a wrong mapping: no function word anywhere
+        let shifted = "7KH GRPLQDQW VHTXHQFH WUDQVGXFWLRQ PRGHOV DUH EDVHG RQ FRPSOHA ".repeat(6);
+        assert!(gibberish_score(&shifted) > 0.5);
+
+        assert_eq!(gibberish_score(""), 0.0);
+    }
+
+    #[test]
+    fn test_check_gibberish() {
+        let garbled = "\u{E001}\u{E002} \u{E003}\u{E004} words";
+        let error = check_gibberish(garbled, 0.5).unwrap_err();
+        assert!(error.downcast_ref::<GarbledText>().is_some());
+        // 0 turns the check off
+        assert!(check_gibberish(garbled, 0.0).is_ok());
+    }
+
+    #[test]
+    fn test_check_text_length() {
+        let short = "Scanned by\n  Library";
+        assert!(check_text_length(short, 200).is_err());
+        assert!(check_text_length(short, 16).is_ok());
+        assert!(check_text_length(&"word ".repeat(50), 200).is_ok());
+
+        let error = check_text_length(" \n ", 0).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<InsufficientText>(),
+            Some(&InsufficientText { found: 0, minimum: 0 })
+        );
+    }
+
+    #[test]
+    fn test_largest_font_run() {
+        let mut collector = FontSizeCollector::default();
+        for c in "arXiv stamp".chars() {
+            collector.push_char(9.0, &c.to_string());
+        }
+        for word in ["Attention", "Is", "All", "You", "Need"] {
+            for c in word.chars() {
+                collector.push_char(17.2, &c.to_string());
+            }
+            collector.push_space();
+        }
+        // A large decorative glyph with no real words is ignored
+        collector.push_char(40.0, "*");
+        for c in "Ashish Vaswani".chars() {
+            collector.push_char(12.0, &c.to_string());
+        }
+
+        assert_eq!(collector.largest(), Some("Attention Is All You Need".to_string()));
+        assert_eq!(FontSizeCollector::default().largest(), None);
+    }
+
+    /// Write a PDF with one line of Courier text per page
+    fn write_pdf(path: &std::path::Path, pages: &[&str]) {
+        use pdf_extract::content::{Content, Operation};
+        use pdf_extract::{dictionary, Stream};
+
+        let mut doc = Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let font_id = doc.add_object(dictionary! {
+            "Type" => "Font",
+            "Subtype" => "Type1",
+            "BaseFont" => "Courier",
+        });
+        let resources_id = doc.add_object(dictionary! { "Font" => dictionary! { "F1" => font_id } });
+
+        let kids: Vec<Object> = pages
+            .iter()
+            .map(|text| {
+                let content = Content {
+                    operations: vec![
+                        Operation::new("BT", vec![]),
+                        Operation::new("Tf", vec!["F1".into(), 12.into()]),
+                        Operation::new("Td", vec![72.into(), 720.into()]),
+                        Operation::new("Tj", vec![Object::string_literal(*text)]),
+                        Operation::new("ET", vec![]),
+                    ],
+                };
+                let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+                doc.add_object(dictionary! {
+                    "Type" => "Page",
+                    "Parent" => pages_id,
+                    "Contents" => content_id,
+                })
+                .into()
+            })
+            .collect();
+
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Count" => kids.len() as i64,
+                "Kids" => kids,
+                "Resources" => resources_id,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            }),
+        );
+        let catalog_id = doc.add_object(dictionary! { "Type" => "Catalog", "Pages" => pages_id });
+        doc.trailer.set("Root", catalog_id);
+        doc.save(path).unwrap();
+    }
+
+    #[test]
+    fn test_extract_pages_stops_at_limit() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("paper.pdf");
+        write_pdf(&path, &["Title page", "Introduction", "Appendix"]);
+        let doc = load_document(path.to_str().unwrap()).unwrap();
+
+        let pages = extract_pages(&doc, 2);
+        assert_eq!(pages.len(), 2);
+        assert!(pages[0].contains("Title page"));
+        assert!(pages[1].contains("Introduction"));
+
+        assert_eq!(extract_pages(&doc, 0).len(), 3);
+        assert_eq!(extract_pages(&doc, 10).len(), 3);
+    }
+
+    #[test]
+    fn test_extract_pdf_text_merge_pages() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("paper.pdf");
+        let title_page = format!("Title page {}", "words ".repeat(80));
+        write_pdf(&path, &[&title_page, "continued title", "Introduction"]);
+        let doc = load_document(path.to_str().unwrap()).unwrap();
+
+        let options = TextOptions {
+            max_tokens: 10,
+            max_pages: 1,
+            ..TextOptions::default()
+        };
+        let text = extract_pdf_text(&doc, &options).unwrap().text;
+        assert!(text.contains("Title page"));
+        assert!(!text.contains("continued"));
+
+        // Each merged page gets its own budget, read even past --pages
+        let options = TextOptions {
+            merge_pages: Some(2),
+            ..options
+        };
+        let text = extract_pdf_text(&doc, &options).unwrap().text;
+        assert!(text.contains("Title page"));
+        assert!(text.contains("continued title"));
+        assert!(!text.contains("Introduction"));
+    }
+
+    #[test]
+    fn test_is_title_bookmark() {
+        assert!(is_title_bookmark("Attention Is All You Need"));
+        assert!(!is_title_bookmark("Introduction"));
+        assert!(!is_title_bookmark("Table of Contents"));
+        assert!(!is_title_bookmark("1 Introduction"));
+        assert!(!is_title_bookmark("IV. Experimental Results"));
+        assert!(!is_title_bookmark("Chapter 3"));
+        assert!(!is_title_bookmark(""));
+    }
+
+    #[test]
+    fn test_extract_outline_title() {
+        use pdf_extract::dictionary;
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("paper.pdf");
+        write_pdf(&path, &["Title page"]);
+        let path = path.to_str().unwrap();
+        assert_eq!(extract_outline_title(&load_document(path).unwrap()), None);
+
+        // Bookmarks: "Abstract", then the title
+        let mut doc = Document::load(path).unwrap();
+        let outlines_id = doc.new_object_id();
+        let second_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Attention Is All You Need"),
+            "Parent" => outlines_id,
+        });
+        let first_id = doc.add_object(dictionary! {
+            "Title" => Object::string_literal("Abstract"),
+            "Parent" => outlines_id,
+            "Next" => second_id,
+        });
+        doc.objects.insert(
+            outlines_id,
+            Object::Dictionary(dictionary! { "Type" => "Outlines", "First" => first_id, "Last" => second_id }),
+        );
+        doc.catalog_mut().unwrap().set("Outlines", outlines_id);
+        doc.save(path).unwrap();
+
+        let title = extract_outline_title(&load_document(path).unwrap());
+        assert_eq!(title.as_deref(), Some("Attention Is All You Need"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_suppress_output_on_threads() {
+        let stdout_file = || {
+            let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+            assert_eq!(unsafe { libc::fstat(1, &mut stat) }, 0);
+            (stat.st_dev, stat.st_ino)
+        };
+        let before = stdout_file();
+
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    suppress_output(|| std::thread::sleep(std::time::Duration::from_millis(5)))
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        // Overlapping calls would leave stdout pointing at /dev/null
+        assert_eq!(stdout_file(), before);
+    }
+
     #[test]
     fn test_extract_pdf_text() {
         // This test requires a sample PDF file
@@ -0,0 +1,221 @@
+use crate::llm;
+use crate::renamer::{self, RenamePlan};
+use crate::report::{Entry, Status};
+use std::path::Path;
+
+/// How a file would fare if the batch were applied, most urgent first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PreviewStatus {
+    /// The rename would be refused (target exists, name taken twice, ...)
+    Conflict,
+    /// Extraction failed
+    Failed,
+    /// The metadata looks unreliable; check the name by hand
+    LowConfidence,
+    /// A probable duplicate that --quarantine-dir would move away
+    Duplicate,
+    /// Declined, already done or otherwise left alone
+    Skipped,
+    /// Would be renamed as shown
+    Ok,
+}
+
+impl PreviewStatus {
+    fn label(self) -> &'static str {
+        match self {
+            PreviewStatus::Conflict => "CONFLICT",
+            PreviewStatus::Failed => "FAILED",
+            PreviewStatus::LowConfidence => "LOW-CONF",
+            PreviewStatus::Duplicate => "DUPLICATE",
+            PreviewStatus::Skipped => "SKIPPED",
+            PreviewStatus::Ok => "OK",
+        }
+    }
+}
+
+/// One line of the dry-run table
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub status: PreviewStatus,
+    pub original: String,
+    pub proposed: String,
+    /// Where the metadata came from, e.g. "[llm]" (empty when nothing was extracted)
+    pub source: String,
+    pub note: String,
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
+/// Build the rows for a planned batch, sorted by status and then by name
+/// `entries` holds the files that were skipped or failed during planning
+pub fn rows(
+    plans: &[RenamePlan],
+    quarantined: &[RenamePlan],
+    quarantine_dir: Option<&str>,
+    entries: &[Entry],
+) -> Vec<Row> {
+    let mut rows: Vec<Row> = plans
+        .iter()
+        .zip(renamer::plan_conflicts(plans))
+        .map(|(plan, conflict)| {
+            let unreliable = plan.metadata.as_ref().map(llm::implausible_fields).unwrap_or_default();
+            let (status, note) = match conflict {
+                Some(conflict) => (PreviewStatus::Conflict, conflict),
+                None if !unreliable.is_empty() => {
+                    (PreviewStatus::LowConfidence, format!("check the {}", unreliable.join(", ")))
+                }
+                None => (PreviewStatus::Ok, String::new()),
+            };
+            Row {
+                status,
+                original: file_name(&plan.source),
+                proposed: plan.proposed_name(),
+                source: plan.sources.as_ref().map(|sources| sources.tag()).unwrap_or_default(),
+                note,
+            }
+        })
+        .collect();
+
+    rows.extend(quarantined.iter().map(|plan| Row {
+        status: PreviewStatus::Duplicate,
+        original: file_name(&plan.source),
+        proposed: format!("{}/", quarantine_dir.unwrap_or_default()),
+        source: String::new(),
+        note: "probable duplicate".to_string(),
+    }));
+
+    rows.extend(entries.iter().map(|entry| Row {
+        status: match entry.status {
+            Status::Failed => PreviewStatus::Failed,
+            _ => PreviewStatus::Skipped,
+        },
+        original: file_name(&entry.source),
+        proposed: String::new(),
+        source: String::new(),
+        note: entry.message.clone().unwrap_or_default(),
+    }));
+
+    rows.sort_by(|a, b| (a.status, &a.original).cmp(&(b.status, &b.original)));
+    rows
+}
+
+/// Lay out cells in columns under a header line and a rule, without trailing newline
+pub fn align_columns<const N: usize>(headers: [&str; N], cells: &[[&str; N]]) -> String {
+    let mut widths = headers.map(|header| header.chars().count());
+    for line in cells {
+        for (width, cell) in widths.iter_mut().zip(line) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let format_line = |line: [&str; N]| -> String {
+        let padded: Vec<String> = line
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{}{}", cell, " ".repeat(width - cell.chars().count())))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+
+    let mut table = format_line(headers);
+    table.push('\n');
+    let rules = widths.map(|width| "-".repeat(width));
+    table.push_str(&format_line(rules.each_ref().map(String::as_str)));
+    for line in cells {
+        table.push('\n');
+        table.push_str(&format_line(*line));
+    }
+    table
+}
+
+/// Lay the rows out as an aligned text table, followed by a count per status
+pub fn render_table(rows: &[Row]) -> String {
+    let cells: Vec<[&str; 5]> = rows
+        .iter()
+        .map(|row| [row.status.label(), &row.original, &row.proposed, &row.source, &row.note])
+        .collect();
+    let mut table = align_columns(["Status", "Original", "Proposed", "Source", "Note"], &cells);
+
+    let mut counts: Vec<String> = Vec::new();
+    for status in [
+        PreviewStatus::Ok,
+        PreviewStatus::LowConfidence,
+        PreviewStatus::Conflict,
+        PreviewStatus::Duplicate,
+        PreviewStatus::Skipped,
+        PreviewStatus::Failed,
+    ] {
+        let count = rows.iter().filter(|row| row.status == status).count();
+        if count > 0 {
+            counts.push(format!("{} {}", count, status.label().to_lowercase()));
+        }
+    }
+    table.push_str(&format!("\n\n{} files: {}", rows.len(), counts.join(", ")));
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::PaperMetadata;
+    use crate::sources::FieldSources;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_rows_sorted_by_status() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        for name in ["a.pdf", "b.pdf", "taken.pdf", "c.pdf"] {
+            File::create(dir.join(name)).unwrap();
+        }
+        let plan = |source: &str, new_filename: &str, year: &str| RenamePlan {
+            source: dir.join(source).to_string_lossy().into_owned(),
+            new_filename: new_filename.to_string(),
+            metadata: Some(PaperMetadata {
+                first_author: "Smith".to_string(),
+                year: year.to_string(),
+                title: "Deep Learning".to_string(),
+                ..PaperMetadata::default()
+            }),
+            sources: Some(FieldSources::default()),
+            ..RenamePlan::default()
+        };
+        let plans = vec![
+            plan("a.pdf", "smith-2020-deep-learning.pdf", "2020"),
+            plan("b.pdf", "smith-nd-deep-learning.pdf", "n.d."),
+            plan("c.pdf", "taken.pdf", "2020"),
+        ];
+        let entries = vec![Entry {
+            source: "d.pdf".to_string(),
+            status: Status::Failed,
+            new_name: None,
+            message: Some("No text".to_string()),
+        }];
+
+        let rows = rows(&plans, &[], None, &entries);
+        let statuses: Vec<_> = rows.iter().map(|row| row.status).collect();
+        assert_eq!(
+            statuses,
+            vec![
+                PreviewStatus::Conflict,
+                PreviewStatus::Failed,
+                PreviewStatus::LowConfidence,
+                PreviewStatus::Ok
+            ]
+        );
+        assert_eq!(rows[0].original, "c.pdf");
+        assert_eq!(rows[2].note, "check the year");
+
+        let table = render_table(&rows);
+        assert!(table.starts_with("Status    Original  Proposed"));
+        assert_eq!(rows[3].source, "[llm]");
+        assert_eq!(rows[1].source, "");
+        assert!(table.ends_with("4 files: 1 ok, 1 low-conf, 1 conflict, 1 failed"));
+    }
+}
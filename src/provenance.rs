@@ -0,0 +1,150 @@
+use crate::renamer::RenameRecord;
+use anyhow::{Context, Result};
+use std::path::Path;
+
+/// Extended attribute (Linux, macOS) or alternate data stream (Windows) that
+/// holds the name a file had before paper-renamer first renamed it
+#[cfg(target_os = "linux")]
+const ATTRIBUTE: &str = "user.paper-renamer.original";
+#[cfg(target_os = "macos")]
+const ATTRIBUTE: &str = "com.paper-renamer.original";
+#[cfg(windows)]
+const STREAM: &str = "paper-renamer.original";
+
+/// Remember the original name of a renamed file on the file itself
+/// A record that is already there wins, so the very first name survives later renames
+pub fn record_original(record: &RenameRecord) -> Result<()> {
+    if read_original(&record.to)?.is_some() {
+        return Ok(());
+    }
+    let original = record
+        .from
+        .file_name()
+        .context("Failed to get original filename")?
+        .to_string_lossy();
+    store_original(&record.to, &original)
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn c_path(path: &Path) -> Result<std::ffi::CString> {
+    use std::os::unix::ffi::OsStrExt;
+    std::ffi::CString::new(path.as_os_str().as_bytes())
+        .with_context(|| format!("Invalid path: {}", path.display()))
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn store_original(path: &Path, original: &str) -> Result<()> {
+    let c_path = c_path(path)?;
+    let name = std::ffi::CString::new(ATTRIBUTE)?;
+    let value = original.as_bytes();
+    let ptr = value.as_ptr().cast();
+
+    #[cfg(target_os = "linux")]
+    let result = unsafe { libc::setxattr(c_path.as_ptr(), name.as_ptr(), ptr, value.len(), 0) };
+    #[cfg(target_os = "macos")]
+    let result = unsafe { libc::setxattr(c_path.as_ptr(), name.as_ptr(), ptr, value.len(), 0, 0) };
+
+    if result != 0 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("Failed to store the original name on {}", path.display()));
+    }
+    Ok(())
+}
+
+/// The original name stored on a file, or None if it has no record
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn read_original(path: &Path) -> Result<Option<String>> {
+    #[cfg(target_os = "linux")]
+    const NO_ATTRIBUTE: i32 = libc::ENODATA;
+    #[cfg(target_os = "macos")]
+    const NO_ATTRIBUTE: i32 = libc::ENOATTR;
+
+    let c_path = c_path(path)?;
+    let name = std::ffi::CString::new(ATTRIBUTE)?;
+    let mut buffer = vec![0u8; 4096];
+    let ptr = buffer.as_mut_ptr().cast();
+
+    #[cfg(target_os = "linux")]
+    let size = unsafe { libc::getxattr(c_path.as_ptr(), name.as_ptr(), ptr, buffer.len()) };
+    #[cfg(target_os = "macos")]
+    let size = unsafe { libc::getxattr(c_path.as_ptr(), name.as_ptr(), ptr, buffer.len(), 0, 0) };
+
+    if size < 0 {
+        let error = std::io::Error::last_os_error();
+        if error.raw_os_error() == Some(NO_ATTRIBUTE) {
+            return Ok(None);
+        }
+        return Err(error).with_context(|| format!("Failed to read the original name of {}", path.display()));
+    }
+    buffer.truncate(size as usize);
+    Ok(Some(String::from_utf8_lossy(&buffer).into_owned()))
+}
+
+#[cfg(windows)]
+fn stream_path(path: &Path) -> std::path::PathBuf {
+    let mut stream = path.as_os_str().to_owned();
+    stream.push(format!(":{}", STREAM));
+    stream.into()
+}
+
+#[cfg(windows)]
+pub fn store_original(path: &Path, original: &str) -> Result<()> {
+    std::fs::write(stream_path(path), original)
+        .with_context(|| format!("Failed to store the original name on {}", path.display()))
+}
+
+/// The original name stored on a file, or None if it has no record
+#[cfg(windows)]
+pub fn read_original(path: &Path) -> Result<Option<String>> {
+    match std::fs::read_to_string(stream_path(path)) {
+        Ok(original) => Ok(Some(original)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("Failed to read the original name of {}", path.display())),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn store_original(_path: &Path, _original: &str) -> Result<()> {
+    anyhow::bail!("Recording the original name is not supported on this platform")
+}
+
+/// The original name stored on a file, or None if it has no record
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+pub fn read_original(_path: &Path) -> Result<Option<String>> {
+    anyhow::bail!("Recording the original name is not supported on this platform")
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_record_original_keeps_first_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let renamed = temp_dir.path().join("vaswani-2017-attention.pdf");
+        File::create(&renamed).unwrap();
+
+        // Not every filesystem takes user attributes (tmpfs on older kernels)
+        if let Err(e) = store_original(&renamed, "1706.03762.pdf") {
+            let unsupported = e
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|e| e.raw_os_error() == Some(libc::ENOTSUP));
+            assert!(unsupported, "{:#}", e);
+            return;
+        }
+        assert_eq!(read_original(&renamed).unwrap().as_deref(), Some("1706.03762.pdf"));
+
+        let record = RenameRecord {
+            from: temp_dir.path().join("vaswani-attention.pdf"),
+            to: renamed.clone(),
+        };
+        record_original(&record).unwrap();
+        assert_eq!(read_original(&renamed).unwrap().as_deref(), Some("1706.03762.pdf"));
+
+        let other = temp_dir.path().join("other.pdf");
+        File::create(&other).unwrap();
+        assert_eq!(read_original(&other).unwrap(), None);
+    }
+}
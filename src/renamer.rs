@@ -1,27 +1,162 @@
+use crate::filename;
+use crate::llm::PaperMetadata;
+use crate::sources::FieldSources;
 use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
 
+/// A completed rename, with both paths made absolute so the history log and
+/// undo have unambiguous data even when the input was a relative path
+#[derive(Debug, Clone, PartialEq)]
+pub struct RenameRecord {
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// How a planned file gets its new name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Transfer {
+    /// Rename the file in place
+    #[default]
+    Rename,
+    /// Copy the file to the new name and verify the copy, keeping the original
+    VerifiedCopy,
+    /// Copy and verify, then remove the original
+    VerifiedMove,
+}
+
 /// Rename a file to the new filename
-/// The new file will be in the same directory as the original file
-pub fn rename_file(original_path: &str, new_filename: &str) -> Result<PathBuf> {
-    let original = Path::new(original_path);
+/// The new filename is relative to the original's directory, which may include a subfolder
+pub fn rename_file(original_path: impl AsRef<Path>, new_filename: impl AsRef<Path>) -> Result<RenameRecord> {
+    let (original, new_path) = resolve_target(original_path, new_filename)?;
+
+    // Perform the rename
+    fs::rename(&original, &new_path).context("Failed to rename file")?;
+
+    Ok(RenameRecord {
+        from: original,
+        to: new_path,
+    })
+}
+
+/// Copy a file to the new filename and check that the copy's SHA-256 hash
+/// matches the original before (optionally) removing the original.
+/// On a mismatch both files are kept, so nothing can be lost.
+pub fn copy_file_verified(
+    original_path: impl AsRef<Path>,
+    new_filename: impl AsRef<Path>,
+    remove_original: bool,
+) -> Result<RenameRecord> {
+    let (original, new_path) = resolve_target(original_path, new_filename)?;
+
+    copy_with_times(&original, &new_path)?;
+
+    if file_hash(&original)? != file_hash(&new_path)? {
+        anyhow::bail!(
+            "Copy of {} does not match the original; both files were kept for inspection: {}",
+            original.display(),
+            new_path.display()
+        );
+    }
+
+    if remove_original {
+        fs::remove_file(&original)
+            .with_context(|| format!("Failed to remove original file: {}", original.display()))?;
+    }
+
+    Ok(RenameRecord {
+        from: original,
+        to: new_path,
+    })
+}
+
+/// Copy a file and flush the copy to disk
+fn copy_with_times(original: &Path, new_path: &Path) -> Result<()> {
+    fs::copy(original, new_path)
+        .with_context(|| format!("Failed to copy file to {}", new_path.display()))?;
+
+    // A copy is stamped with the current time; keep the original's timestamps
+    // so libraries sorted by date stay in order
+    let times = fs::metadata(original)
+        .and_then(|metadata| {
+            Ok(fs::FileTimes::new()
+                .set_accessed(metadata.accessed()?)
+                .set_modified(metadata.modified()?))
+        })
+        .with_context(|| format!("Failed to read timestamps of {}", original.display()))?;
+    fs::OpenOptions::new()
+        .write(true)
+        .open(new_path)
+        .and_then(|copy| {
+            copy.set_times(times)?;
+            copy.sync_all()
+        })
+        .with_context(|| format!("Failed to flush copy: {}", new_path.display()))
+}
+
+/// Where the mirror copy of a renamed file went (--copy-on-success-to)
+#[derive(Debug, PartialEq)]
+pub enum Mirrored {
+    Copied(PathBuf),
+    /// An identical file was already there, so nothing was copied
+    AlreadyPresent(PathBuf),
+}
+
+/// Copy a renamed file into the mirror directory, at its path relative to the
+/// original's folder so author folders carry over
+/// A different file already at that path is an error and is never overwritten
+pub fn mirror_file(record: &RenameRecord, mirror_dir: &Path) -> Result<Mirrored> {
+    let relative = record
+        .from
+        .parent()
+        .and_then(|folder| record.to.strip_prefix(folder).ok())
+        .or_else(|| record.to.file_name().map(Path::new))
+        .context("Failed to get filename")?;
+    let target = mirror_dir.join(relative);
+
+    if target.exists() {
+        if file_hash(&target)? == file_hash(&record.to)? {
+            return Ok(Mirrored::AlreadyPresent(target));
+        }
+        anyhow::bail!("A different file already exists in the mirror: {}", target.display());
+    }
+
+    if let Some(folder) = target.parent().filter(|folder| !folder.exists()) {
+        fs::create_dir_all(folder)
+            .with_context(|| format!("Failed to create directory: {}", folder.display()))?;
+    }
+    copy_with_times(&record.to, &target)?;
+
+    if file_hash(&record.to)? != file_hash(&target)? {
+        // A partial copy must not pass for the file next time
+        let _ = fs::remove_file(&target);
+        anyhow::bail!("Mirror copy of {} does not match the file", record.to.display());
+    }
+    Ok(Mirrored::Copied(target))
+}
+
+/// Resolve the absolute source and target paths of a rename, checking that
+/// the source is a file and the target is free, and creating the target's folder
+fn resolve_target(original_path: impl AsRef<Path>, new_filename: impl AsRef<Path>) -> Result<(PathBuf, PathBuf)> {
+    let original_path = original_path.as_ref();
+    let original = std::path::absolute(original_path)
+        .with_context(|| format!("Failed to resolve path: {}", original_path.display()))?;
 
     // Validate that the original file exists
     if !original.exists() {
-        anyhow::bail!("Original file does not exist: {}", original_path);
+        anyhow::bail!("Original file does not exist: {}", original_path.display());
     }
 
     if !original.is_file() {
-        anyhow::bail!("Path is not a file: {}", original_path);
+        anyhow::bail!("Path is not a file: {}", original_path.display());
     }
 
-    // Get the directory of the original file
+    // Create the new path in the same directory as the original
     let parent_dir = original
         .parent()
         .context("Failed to get parent directory")?;
-
-    // Create the new path in the same directory
     let new_path = parent_dir.join(new_filename);
 
     // Check if the target file already exists
@@ -32,22 +167,375 @@ pub fn rename_file(original_path: &str, new_filename: &str) -> Result<PathBuf> {
         );
     }
 
-    // Perform the rename
-    fs::rename(original, &new_path).context("Failed to rename file")?;
+    if let Some(folder) = new_path.parent().filter(|folder| !folder.exists()) {
+        fs::create_dir_all(folder)
+            .with_context(|| format!("Failed to create directory: {}", folder.display()))?;
+    }
+
+    Ok((original, new_path))
+}
+
+/// SHA-256 hash of a file's contents
+fn file_hash(path: &Path) -> Result<Vec<u8>> {
+    let mut file =
+        fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// SHA-256 hash of a file's contents as lowercase hex, for content-addressed names
+pub fn content_hash(path: &Path) -> Result<String> {
+    Ok(file_hash(path)?.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+/// A rename decided during the plan phase, not yet applied to the filesystem
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct RenamePlan {
+    /// Path of the file as given on the command line
+    pub source: String,
+    /// New filename (without directory), placed next to the source
+    pub new_filename: String,
+    /// Folder next to the source to move the file into (e.g. the first author's name)
+    pub subdir: Option<String>,
+    /// Abstract to save in a sidecar file next to the renamed PDF
+    pub abstract_text: Option<String>,
+    /// Metadata the new name was generated from, for the library index
+    pub metadata: Option<PaperMetadata>,
+    /// Where the author, year and title came from, for the dry-run table
+    pub sources: Option<FieldSources>,
+    /// Also rename the folder holding the file to the new name (without extension)
+    pub rename_folder: bool,
+}
+
+impl RenamePlan {
+    /// Full path the file will have after the rename
+    pub fn target(&self) -> PathBuf {
+        Path::new(&self.source)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join(self.relative_target())
+    }
+
+    /// Path the file will have after the rename, relative to the source's directory
+    pub fn relative_target(&self) -> PathBuf {
+        Path::new(self.subdir.as_deref().unwrap_or("")).join(&self.new_filename)
+    }
+
+    /// The new name as shown to the user, including the subfolder if there is one
+    pub fn proposed_name(&self) -> String {
+        self.relative_target().to_string_lossy().into_owned()
+    }
 
-    Ok(new_path)
+    /// New path of the containing folder, if this plan renames it
+    pub fn folder_target(&self) -> Option<PathBuf> {
+        if !self.rename_folder {
+            return None;
+        }
+
+        let folder = std::path::absolute(Path::new(&self.source).parent()?).ok()?;
+        let stem = Path::new(&self.new_filename).file_stem()?;
+        let target = folder.parent()?.join(stem);
+        (target != folder).then_some(target)
+    }
+
+    /// Path of the abstract sidecar, if this plan writes one
+    pub fn abstract_target(&self) -> Option<PathBuf> {
+        self.abstract_text
+            .as_ref()
+            .map(|_| abstract_path(&self.target()))
+    }
+}
+
+/// Sidecar file holding a paper's abstract: `<name>.abstract.txt` next to the PDF
+pub fn abstract_path(pdf_path: &Path) -> PathBuf {
+    pdf_path.with_extension("abstract.txt")
+}
+
+/// Save an abstract next to the renamed PDF
+pub fn write_abstract(pdf_path: &Path, abstract_text: &str) -> Result<PathBuf> {
+    let path = abstract_path(pdf_path);
+    fs::write(&path, format!("{}\n", abstract_text.trim()))
+        .with_context(|| format!("Failed to write abstract: {}", path.display()))?;
+    Ok(path)
+}
+
+/// How a duplicate name is told apart from the first file that claimed it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicateSuffix {
+    /// `name-2.pdf`, `name-3.pdf`, ...
+    Numbered,
+    /// `namea.pdf`, `nameb.pdf`, ..., `nameaa.pdf` for every paper sharing a
+    /// name, as BibTeX does with citation keys
+    Lettered,
+}
+
+impl DuplicateSuffix {
+    /// Letters for citation keys, numbers for every other template
+    pub fn for_format(format: &str) -> Self {
+        if filename::is_citekey(format) {
+            DuplicateSuffix::Lettered
+        } else {
+            DuplicateSuffix::Numbered
+        }
+    }
+
+    /// The suffix for the `n`th duplicate, counting from 0
+    fn suffix(self, n: usize) -> String {
+        match self {
+            DuplicateSuffix::Numbered => format!("-{}", n + 2),
+            DuplicateSuffix::Lettered => {
+                // Bijective base 26: a..z, then aa, ab, ...
+                let mut letters = Vec::new();
+                let mut n = n + 1;
+                while n > 0 {
+                    n -= 1;
+                    letters.push(b'a' + (n % 26) as u8);
+                    n /= 26;
+                }
+                letters.iter().rev().map(|&b| b as char).collect()
+            }
+        }
+    }
 }
 
-/// Get just the filename from a path
-pub fn get_filename(path: &str) -> Result<String> {
-    let path = Path::new(path);
+/// `filename` with a suffix before its extension
+fn with_suffix(filename: &str, suffix: &str) -> String {
+    match filename.rsplit_once('.') {
+        Some((stem, extension)) => format!("{}{}.{}", stem, suffix, extension),
+        None => format!("{}{}", filename, suffix),
+    }
+}
+
+/// Give every plan a unique target, keeping clear of existing files.
+/// Numbered: later duplicates get `name-2.pdf`, `name-3.pdf`, ...
+/// Lettered: see `resolve_lettered`.
+/// Returns a description of each collision that was resolved.
+pub fn resolve_duplicates(plans: &mut [RenamePlan], style: DuplicateSuffix) -> Vec<String> {
+    if style == DuplicateSuffix::Lettered {
+        return resolve_lettered(plans);
+    }
+
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+    let mut collisions = Vec::new();
+
+    for plan in plans.iter_mut() {
+        if claimed.insert(plan.target()) {
+            continue;
+        }
+
+        let original = plan.new_filename.clone();
+        for n in 0.. {
+            plan.new_filename = with_suffix(&original, &style.suffix(n));
+            let target = plan.target();
+            if !target.exists() && claimed.insert(target) {
+                break;
+            }
+        }
+
+        collisions.push(format!(
+            "{}: {} is already planned for another file, using {}",
+            plan.source, original, plan.new_filename
+        ));
+    }
+
+    collisions
+}
+
+/// Letter citation keys the way BibTeX does: when several papers of the batch
+/// share a key, each gets a letter (`smith2020a`, `smith2020b`, ...) in order
+/// of title, so the letters don't depend on the order files were given in.
+/// A key already taken on disk, plainly or with letters, continues with the
+/// first letter not in use, so later runs pick up where earlier ones left off
+fn resolve_lettered(plans: &mut [RenamePlan]) -> Vec<String> {
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    let mut group_of: HashMap<PathBuf, usize> = HashMap::new();
+    for (index, plan) in plans.iter().enumerate() {
+        let group = *group_of.entry(plan.target()).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[group].push(index);
+    }
+
+    // The plain keys stay reserved, so no letter lands on one of them
+    let mut claimed: HashSet<PathBuf> = group_of.into_keys().collect();
+    let mut collisions = Vec::new();
+
+    for mut members in groups {
+        let first = &plans[members[0]];
+        let key = first.new_filename.clone();
+        let target = first.target();
+        let is_member = |path: &Path| {
+            members
+                .iter()
+                .any(|&index| same_file(Path::new(&plans[index].source), path))
+        };
+        let taken_on_disk =
+            (target.exists() && !is_member(&target)) || target.with_file_name(with_suffix(&key, "a")).exists();
+        if members.len() == 1 && !taken_on_disk {
+            continue;
+        }
+
+        let title = |index: usize| {
+            let plan = &plans[index];
+            let title = plan.metadata.as_ref().map(|metadata| metadata.title.to_lowercase());
+            (title.unwrap_or_default(), plan.source.clone())
+        };
+        members.sort_by_cached_key(|&index| title(index));
+
+        let mut letters = (0..).map(|n| DuplicateSuffix::Lettered.suffix(n));
+        for index in members {
+            let plan = &mut plans[index];
+            for suffix in letters.by_ref() {
+                plan.new_filename = with_suffix(&key, &suffix);
+                let target = plan.target();
+                if !target.exists() && claimed.insert(target) {
+                    break;
+                }
+            }
+            let reason = if taken_on_disk { "is already in use" } else { "is shared with another paper" };
+            collisions.push(format!("{}: {} {}, using {}", plan.source, key, reason, plan.new_filename));
+        }
+    }
+
+    collisions
+}
+
+/// Whether two paths name the same existing file
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Error for a plan that can't be applied, listing every conflict
+/// (e.g. an existing target file)
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanConflicts(pub Vec<String>);
+
+impl std::fmt::Display for PlanConflicts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "No files were renamed because the plan has conflicts:\n  {}",
+            self.0.join("\n  ")
+        )
+    }
+}
+
+impl std::error::Error for PlanConflicts {}
+
+/// The conflict, if any, that stops each plan from being applied, in plan order
+pub fn plan_conflicts(plans: &[RenamePlan]) -> Vec<Option<String>> {
+    let mut claimed: HashSet<PathBuf> = HashSet::new();
+    plans
+        .iter()
+        .map(|plan| {
+            let source = Path::new(&plan.source);
+            let target = plan.target();
+
+            if !source.is_file() {
+                Some("original file no longer exists".to_string())
+            } else if !claimed.insert(target.clone()) {
+                Some(format!("another file is also being renamed to {}", target.display()))
+            } else if target.exists() {
+                Some(format!("target file already exists: {}", target.display()))
+            } else if let Some(folder) = plan.folder_target().filter(|f| f.exists() || !claimed.insert(f.clone())) {
+                Some(format!("target folder already exists: {}", folder.display()))
+            } else {
+                plan.abstract_target()
+                    .filter(|sidecar| sidecar.exists())
+                    .map(|sidecar| format!("abstract file already exists: {}", sidecar.display()))
+            }
+        })
+        .collect()
+}
+
+/// Check a complete plan for problems before anything is renamed
+/// Every conflict is reported at once so the whole batch can be fixed in one go
+pub fn check_plan(plans: &[RenamePlan]) -> Result<()> {
+    let conflicts: Vec<String> = plans
+        .iter()
+        .zip(plan_conflicts(plans))
+        .filter_map(|(plan, conflict)| Some(format!("{}: {}", plan.source, conflict?)))
+        .collect();
+
+    if !conflicts.is_empty() {
+        return Err(PlanConflicts(conflicts).into());
+    }
+
+    Ok(())
+}
+
+/// An apply phase that stopped at a failure
+/// The renames completed before it are kept, in plan order, so the log and the
+/// report still show which files were renamed
+#[derive(Debug)]
+pub struct ApplyError {
+    pub completed: Vec<RenameRecord>,
+    pub error: anyhow::Error,
+}
+
+impl From<ApplyError> for anyhow::Error {
+    fn from(failure: ApplyError) -> Self {
+        failure.error
+    }
+}
+
+/// The renames an apply phase completed, whether or not it failed later
+pub fn completed(applied: &std::result::Result<Vec<RenameRecord>, ApplyError>) -> &[RenameRecord] {
+    match applied {
+        Ok(records) => records,
+        Err(failure) => &failure.completed,
+    }
+}
+
+/// Apply a checked plan, returning a record of every rename
+/// Stops at the first failure, leaving the remaining files untouched
+pub fn apply_plan(plans: &[RenamePlan], transfer: Transfer) -> std::result::Result<Vec<RenameRecord>, ApplyError> {
+    let mut completed = Vec::new();
+    for plan in plans {
+        match apply_one(plan, transfer) {
+            Ok(record) => completed.push(record),
+            Err(error) => return Err(ApplyError { completed, error }),
+        }
+    }
+    Ok(completed)
+}
+
+fn apply_one(plan: &RenamePlan, transfer: Transfer) -> Result<RenameRecord> {
+    let mut record = match transfer {
+        Transfer::Rename => rename_file(&plan.source, plan.relative_target()),
+        Transfer::VerifiedCopy => copy_file_verified(&plan.source, plan.relative_target(), false),
+        Transfer::VerifiedMove => copy_file_verified(&plan.source, plan.relative_target(), true),
+    }
+    .with_context(|| format!("Failed to rename {}", plan.source))?;
+
+    if let Some(folder) = plan.folder_target() {
+        let current = record.to.parent().context("Failed to get parent directory")?;
+        fs::rename(current, &folder).with_context(|| {
+            format!("Failed to rename folder {} to {}", current.display(), folder.display())
+        })?;
+        record.to = folder.join(&plan.new_filename);
+    }
+
+    Ok(record)
+}
+
+/// Get just the filename from a path, for display and for deriving a new name
+/// Bytes that aren't valid UTF-8 show up as U+FFFD, so file operations must
+/// keep using the path itself
+pub fn get_filename(path: impl AsRef<Path>) -> Result<String> {
     let filename = path
+        .as_ref()
         .file_name()
         .context("Failed to get filename from path")?
-        .to_str()
-        .context("Filename contains invalid UTF-8")?;
+        .to_string_lossy();
 
-    Ok(filename.to_string())
+    Ok(filename.into_owned())
 }
 
 #[cfg(test)]
@@ -65,20 +553,336 @@ mod tests {
         assert_eq!(get_filename("file.pdf").unwrap(), "file.pdf");
     }
 
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_filename() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        // "café.pdf" in Latin-1, as old archives and some scanners write it
+        let original = temp_dir.path().join(OsStr::from_bytes(b"caf\xe9.pdf"));
+        if File::create(&original).is_err() {
+            // Filesystems that enforce UTF-8 names (e.g. on macOS) can't hold it
+            return;
+        }
+
+        assert_eq!(get_filename(&original).unwrap(), "caf\u{FFFD}.pdf");
+
+        let record = rename_file(&original, "dupont-2001-cafe.pdf").unwrap();
+        assert!(!original.exists());
+        assert_eq!(record.from, original);
+        assert!(record.to.exists());
+    }
+
     #[test]
     fn test_rename_file() {
         let temp_dir = TempDir::new().unwrap();
         let original_path = temp_dir.path().join("original.pdf");
         File::create(&original_path).unwrap();
 
-        let new_path = rename_file(
+        let record = rename_file(
             original_path.to_str().unwrap(),
             "renamed.pdf",
         )
         .unwrap();
 
-        assert!(new_path.exists());
+        assert!(record.to.exists());
         assert!(!original_path.exists());
-        assert_eq!(new_path.file_name().unwrap(), "renamed.pdf");
+        assert_eq!(record.from, original_path);
+        assert_eq!(record.to.file_name().unwrap(), "renamed.pdf");
+    }
+
+    #[test]
+    fn test_rename_file_records_absolute_paths() {
+        // A temp dir under the current directory gives a relative path to work with
+        let cwd = std::env::current_dir().unwrap();
+        let temp_dir = TempDir::new_in(&cwd).unwrap();
+        let relative = temp_dir.path().strip_prefix(&cwd).unwrap().join("relative.pdf");
+        File::create(&relative).unwrap();
+
+        let record = rename_file(relative.to_str().unwrap(), "renamed.pdf").unwrap();
+        assert_eq!(record.from, cwd.join(&relative));
+        assert_eq!(record.to, cwd.join(&relative).with_file_name("renamed.pdf"));
+        assert!(record.to.exists());
+    }
+
+    #[test]
+    fn test_check_plan_reports_all_conflicts_before_renaming() {
+        let temp_dir = TempDir::new().unwrap();
+        let first = temp_dir.path().join("first.pdf");
+        let second = temp_dir.path().join("second.pdf");
+        File::create(&first).unwrap();
+        File::create(&second).unwrap();
+        File::create(temp_dir.path().join("taken.pdf")).unwrap();
+
+        let plans = vec![
+            RenamePlan {
+                source: first.to_str().unwrap().to_string(),
+                new_filename: "free.pdf".to_string(),
+                ..RenamePlan::default()
+            },
+            RenamePlan {
+                source: second.to_str().unwrap().to_string(),
+                new_filename: "taken.pdf".to_string(),
+                ..RenamePlan::default()
+            },
+        ];
+
+        let error = check_plan(&plans).unwrap_err().to_string();
+        assert!(error.contains("taken.pdf"));
+        assert!(first.exists());
+        assert!(second.exists());
+    }
+
+    #[test]
+    fn test_resolve_duplicates() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = |name: &str| {
+            let path = temp_dir.path().join(name);
+            File::create(&path).unwrap();
+            path.to_str().unwrap().to_string()
+        };
+        // An unrelated file already holds the first suffix
+        File::create(temp_dir.path().join("same-2.pdf")).unwrap();
+
+        let mut plans: Vec<RenamePlan> = ["a.pdf", "b.pdf", "c.pdf", "d.pdf"]
+            .iter()
+            .zip(["same.pdf", "same.pdf", "other.pdf", "same.pdf"])
+            .map(|(name, new_filename)| RenamePlan {
+                source: source(name),
+                new_filename: new_filename.to_string(),
+                ..RenamePlan::default()
+            })
+            .collect();
+
+        // Duplicates within the plan are conflicts until resolved
+        assert!(check_plan(&plans).unwrap_err().to_string().contains("also being renamed"));
+
+        let collisions = resolve_duplicates(&mut plans, DuplicateSuffix::Numbered);
+        assert_eq!(collisions.len(), 2);
+        let names: Vec<&str> = plans.iter().map(|p| p.new_filename.as_str()).collect();
+        assert_eq!(names, ["same.pdf", "same-3.pdf", "other.pdf", "same-4.pdf"]);
+        check_plan(&plans).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_duplicates_lettered() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut plans: Vec<RenamePlan> = ["a.pdf", "b.pdf", "c.pdf", "d.pdf"]
+            .iter()
+            .zip(["smith2020deep.pdf", "smith2020deep.pdf", "jones2019graph.pdf", "smith2020deep.pdf"])
+            .map(|(name, new_filename)| RenamePlan {
+                source: temp_dir.path().join(name).to_string_lossy().into_owned(),
+                new_filename: new_filename.to_string(),
+                ..RenamePlan::default()
+            })
+            .collect();
+
+        let collisions = resolve_duplicates(&mut plans, DuplicateSuffix::for_format(filename::CITEKEY_FORMAT));
+        assert_eq!(collisions.len(), 3);
+        let names: Vec<&str> = plans.iter().map(|p| p.new_filename.as_str()).collect();
+        assert_eq!(names, ["smith2020deepa.pdf", "smith2020deepb.pdf", "jones2019graph.pdf", "smith2020deepc.pdf"]);
+
+        // After z come two letters
+        assert_eq!(DuplicateSuffix::Lettered.suffix(25), "z");
+        assert_eq!(DuplicateSuffix::Lettered.suffix(26), "aa");
+        assert_eq!(DuplicateSuffix::for_format(filename::DEFAULT_FORMAT), DuplicateSuffix::Numbered);
+    }
+
+    #[test]
+    fn test_resolve_lettered_by_title() {
+        let temp_dir = TempDir::new().unwrap();
+        let plan = |source: &str, title: &str| RenamePlan {
+            source: temp_dir.path().join(source).to_string_lossy().into_owned(),
+            new_filename: "smith2020.pdf".to_string(),
+            metadata: Some(PaperMetadata {
+                title: title.to_string(),
+                ..PaperMetadata::default()
+            }),
+            ..RenamePlan::default()
+        };
+
+        // Letters follow the titles, not the order the files came in
+        let mut plans = vec![
+            plan("1.pdf", "Zero-Shot Learning"),
+            plan("2.pdf", "Graph Networks"),
+            plan("3.pdf", "attention"),
+        ];
+        resolve_duplicates(&mut plans, DuplicateSuffix::Lettered);
+        let names: Vec<&str> = plans.iter().map(|p| p.new_filename.as_str()).collect();
+        assert_eq!(names, ["smith2020c.pdf", "smith2020b.pdf", "smith2020a.pdf"]);
+
+        // Existing keys on disk: a lone paper continues after the letters in use
+        File::create(temp_dir.path().join("smith2020a.pdf")).unwrap();
+        File::create(temp_dir.path().join("smith2020b.pdf")).unwrap();
+        let mut plans = vec![plan("4.pdf", "Deep Learning")];
+        let collisions = resolve_duplicates(&mut plans, DuplicateSuffix::Lettered);
+        assert_eq!(plans[0].new_filename, "smith2020c.pdf");
+        assert!(collisions[0].contains("already in use"));
+
+        // So does one whose plain key is taken
+        let temp_dir = TempDir::new().unwrap();
+        File::create(temp_dir.path().join("smith2020.pdf")).unwrap();
+        let mut plans = vec![RenamePlan {
+            source: temp_dir.path().join("5.pdf").to_string_lossy().into_owned(),
+            new_filename: "smith2020.pdf".to_string(),
+            ..RenamePlan::default()
+        }];
+        resolve_duplicates(&mut plans, DuplicateSuffix::Lettered);
+        assert_eq!(plans[0].new_filename, "smith2020a.pdf");
+
+        // A lone key that is free stays plain
+        let mut plans = vec![RenamePlan {
+            new_filename: "jones2019.pdf".to_string(),
+            ..plans[0].clone()
+        }];
+        assert!(resolve_duplicates(&mut plans, DuplicateSuffix::Lettered).is_empty());
+        assert_eq!(plans[0].new_filename, "jones2019.pdf");
+    }
+
+    #[test]
+    fn test_apply_plan() {
+        let temp_dir = TempDir::new().unwrap();
+        let original = temp_dir.path().join("original.pdf");
+        File::create(&original).unwrap();
+
+        let plans = vec![RenamePlan {
+            source: original.to_str().unwrap().to_string(),
+            new_filename: "renamed.pdf".to_string(),
+            ..RenamePlan::default()
+        }];
+
+        check_plan(&plans).unwrap();
+        let renamed = apply_plan(&plans, Transfer::Rename).unwrap();
+        assert_eq!(renamed.len(), 1);
+        assert_eq!(renamed[0].from, original);
+        assert_eq!(renamed[0].to, temp_dir.path().join("renamed.pdf"));
+        assert!(!original.exists());
+    }
+
+    #[test]
+    fn test_apply_plan_into_subdir() {
+        let temp_dir = TempDir::new().unwrap();
+        let original = temp_dir.path().join("original.pdf");
+        File::create(&original).unwrap();
+
+        let plans = vec![RenamePlan {
+            source: original.to_str().unwrap().to_string(),
+            new_filename: "smith-2020-deep-learning.pdf".to_string(),
+            subdir: Some("smith".to_string()),
+            ..RenamePlan::default()
+        }];
+        assert_eq!(
+            plans[0].proposed_name(),
+            Path::new("smith").join("smith-2020-deep-learning.pdf").to_string_lossy()
+        );
+
+        // The subfolder is created as needed
+        check_plan(&plans).unwrap();
+        let renamed = apply_plan(&plans, Transfer::Rename).unwrap();
+        assert_eq!(renamed[0].to, temp_dir.path().join("smith").join("smith-2020-deep-learning.pdf"));
+        assert!(renamed[0].to.is_file());
+    }
+
+    #[test]
+    fn test_copy_file_verified() {
+        let temp_dir = TempDir::new().unwrap();
+        let original = temp_dir.path().join("original.pdf");
+        fs::write(&original, b"%PDF-1.4 contents").unwrap();
+        let added = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_500_000_000);
+        File::options()
+            .write(true)
+            .open(&original)
+            .unwrap()
+            .set_modified(added)
+            .unwrap();
+
+        let record = copy_file_verified(original.to_str().unwrap(), "copy.pdf", false).unwrap();
+        assert_eq!(record.to, temp_dir.path().join("copy.pdf"));
+        assert!(original.exists());
+        assert_eq!(fs::read(&record.to).unwrap(), b"%PDF-1.4 contents");
+        // The copy keeps the original's modification time
+        assert_eq!(fs::metadata(&record.to).unwrap().modified().unwrap(), added);
+
+        let record = copy_file_verified(original.to_str().unwrap(), "moved.pdf", true).unwrap();
+        assert!(!original.exists());
+        assert_eq!(fs::read(record.to).unwrap(), b"%PDF-1.4 contents");
+    }
+
+    #[test]
+    fn test_mirror_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let library = temp_dir.path().join("library");
+        let mirror = temp_dir.path().join("nas");
+        fs::create_dir_all(library.join("vaswani")).unwrap();
+        let renamed = library.join("vaswani").join("vaswani-2017-attention.pdf");
+        fs::write(&renamed, b"%PDF-1.4 contents").unwrap();
+        let record = RenameRecord {
+            from: library.join("1706.03762.pdf"),
+            to: renamed,
+        };
+
+        // The author folder is mirrored too
+        let target = mirror.join("vaswani").join("vaswani-2017-attention.pdf");
+        assert_eq!(mirror_file(&record, &mirror).unwrap(), Mirrored::Copied(target.clone()));
+        assert_eq!(fs::read(&target).unwrap(), b"%PDF-1.4 contents");
+        assert_eq!(mirror_file(&record, &mirror).unwrap(), Mirrored::AlreadyPresent(target.clone()));
+
+        fs::write(&target, b"%PDF-1.4 other").unwrap();
+        assert!(mirror_file(&record, &mirror).is_err());
+        assert_eq!(fs::read(&target).unwrap(), b"%PDF-1.4 other");
+    }
+
+    #[test]
+    fn test_apply_plan_renames_folder() {
+        let temp_dir = TempDir::new().unwrap();
+        let folder = temp_dir.path().join("Attention Is All You Need");
+        fs::create_dir(&folder).unwrap();
+        let original = folder.join("paper.pdf");
+        File::create(&original).unwrap();
+
+        let plan = RenamePlan {
+            source: original.to_str().unwrap().to_string(),
+            new_filename: "vaswani-2017-attention.pdf".to_string(),
+            rename_folder: true,
+            ..RenamePlan::default()
+        };
+        let new_folder = temp_dir.path().join("vaswani-2017-attention");
+        assert_eq!(plan.folder_target(), Some(new_folder.clone()));
+
+        check_plan(std::slice::from_ref(&plan)).unwrap();
+        let renamed = apply_plan(&[plan], Transfer::Rename).unwrap();
+        assert_eq!(renamed[0].to, new_folder.join("vaswani-2017-attention.pdf"));
+        assert!(renamed[0].to.is_file());
+        assert!(!folder.exists());
+    }
+
+    #[test]
+    fn test_write_abstract_sidecar() {
+        let temp_dir = TempDir::new().unwrap();
+        let pdf = temp_dir.path().join("smith-2020-deep-learning.pdf");
+
+        let sidecar = write_abstract(&pdf, "  We study deep learning.\n").unwrap();
+        assert_eq!(sidecar, temp_dir.path().join("smith-2020-deep-learning.abstract.txt"));
+        assert_eq!(fs::read_to_string(sidecar).unwrap(), "We study deep learning.\n");
+    }
+
+    #[test]
+    fn test_check_plan_refuses_to_overwrite_abstract() {
+        let temp_dir = TempDir::new().unwrap();
+        let original = temp_dir.path().join("original.pdf");
+        File::create(&original).unwrap();
+        File::create(temp_dir.path().join("renamed.abstract.txt")).unwrap();
+
+        let plan = RenamePlan {
+            source: original.to_str().unwrap().to_string(),
+            new_filename: "renamed.pdf".to_string(),
+            abstract_text: Some("Abstract".to_string()),
+            ..RenamePlan::default()
+        };
+
+        assert!(check_plan(std::slice::from_ref(&plan)).is_err());
+        assert!(check_plan(&[RenamePlan { abstract_text: None, ..plan }]).is_ok());
     }
 }
@@ -0,0 +1,166 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::Path;
+
+/// What happened to a file during a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Renamed,
+    Skipped,
+    Failed,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Renamed => "renamed",
+            Status::Skipped => "skipped",
+            Status::Failed => "failed",
+        }
+    }
+}
+
+/// One row of the run report
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// Path of the file as given on the command line
+    pub source: String,
+    pub status: Status,
+    /// New filename, for renamed files
+    pub new_name: Option<String>,
+    /// Why the file was skipped or failed
+    pub message: Option<String>,
+}
+
+/// Write a human-readable report of a run
+/// The format follows the extension: `.csv` writes CSV, anything else Markdown
+pub fn write_report(path: &str, entries: &[Entry]) -> Result<()> {
+    let is_csv = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"));
+
+    let contents = if is_csv {
+        to_csv(entries)
+    } else {
+        to_markdown(entries)
+    };
+
+    fs::write(path, contents).with_context(|| format!("Failed to write report: {}", path))
+}
+
+fn original_name(entry: &Entry) -> String {
+    Path::new(&entry.source)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| entry.source.clone())
+}
+
+fn to_markdown(entries: &[Entry]) -> String {
+    let count = |status| entries.iter().filter(|e| e.status == status).count();
+
+    let mut output = String::from("# paper-renamer report\n\n");
+    output.push_str(&format!(
+        "{} files: {} renamed, {} skipped, {} failed\n\n",
+        entries.len(),
+        count(Status::Renamed),
+        count(Status::Skipped),
+        count(Status::Failed)
+    ));
+    output.push_str("| Original | Status | New name | Message |\n");
+    output.push_str("|----------|--------|----------|---------|\n");
+
+    for entry in entries {
+        output.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            markdown_cell(&original_name(entry)),
+            entry.status.as_str(),
+            markdown_cell(entry.new_name.as_deref().unwrap_or("")),
+            markdown_cell(entry.message.as_deref().unwrap_or(""))
+        ));
+    }
+
+    output
+}
+
+fn to_csv(entries: &[Entry]) -> String {
+    let mut output = String::from("original,status,new_name,message\n");
+
+    for entry in entries {
+        output.push_str(&format!(
+            "{},{},{},{}\n",
+            csv_field(&original_name(entry)),
+            entry.status.as_str(),
+            csv_field(entry.new_name.as_deref().unwrap_or("")),
+            csv_field(entry.message.as_deref().unwrap_or(""))
+        ));
+    }
+
+    output
+}
+
+/// Escape a value for a Markdown table cell
+fn markdown_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or line break
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            Entry {
+                source: "papers/a.pdf".to_string(),
+                status: Status::Renamed,
+                new_name: Some("smith-2020-deep-learning.pdf".to_string()),
+                message: None,
+            },
+            Entry {
+                source: "papers/b.pdf".to_string(),
+                status: Status::Failed,
+                new_name: None,
+                message: Some("No text, could not extract".to_string()),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_markdown_report() {
+        let report = to_markdown(&entries());
+        assert!(report.contains("2 files: 1 renamed, 0 skipped, 1 failed"));
+        assert!(report.contains("| a.pdf | renamed | smith-2020-deep-learning.pdf |  |"));
+        assert!(report.contains("| b.pdf | failed |  | No text, could not extract |"));
+    }
+
+    #[test]
+    fn test_csv_report() {
+        let report = to_csv(&entries());
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines[0], "original,status,new_name,message");
+        assert_eq!(lines[1], "a.pdf,renamed,smith-2020-deep-learning.pdf,");
+        assert_eq!(lines[2], "b.pdf,failed,,\"No text, could not extract\"");
+    }
+
+    #[test]
+    fn test_write_report_picks_format_from_extension() {
+        let temp_dir = TempDir::new().unwrap();
+        let csv_path = temp_dir.path().join("report.CSV");
+        let md_path = temp_dir.path().join("report.md");
+
+        write_report(csv_path.to_str().unwrap(), &entries()).unwrap();
+        write_report(md_path.to_str().unwrap(), &entries()).unwrap();
+
+        assert!(fs::read_to_string(csv_path).unwrap().starts_with("original,"));
+        assert!(fs::read_to_string(md_path).unwrap().starts_with("# paper-renamer report"));
+    }
+}
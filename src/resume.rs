@@ -0,0 +1,189 @@
+use crate::llm::PaperMetadata;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What has already happened to one input file of a batch
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    /// Extracted and confirmed, waiting for the apply phase
+    Planned {
+        new_filename: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        subdir: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        abstract_text: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        metadata: Option<Box<PaperMetadata>>,
+    },
+    /// Declined by the user
+    Skipped,
+    /// Renamed on disk
+    Renamed,
+}
+
+/// Progress of a batch run, persisted so an interrupted batch can be resumed
+/// The state file is keyed by the batch's input set, so unrelated runs
+/// never share state
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BatchState {
+    #[serde(skip)]
+    path: PathBuf,
+    entries: BTreeMap<String, Outcome>,
+}
+
+impl BatchState {
+    /// Load the saved state for this input set, or start fresh
+    /// Without `resume`, any previous progress for the same inputs is discarded
+    pub fn open(inputs: &[String], resume: bool) -> Result<Self> {
+        let path = state_path(inputs)?;
+        Self::open_at(path, resume)
+    }
+
+    fn open_at(path: PathBuf, resume: bool) -> Result<Self> {
+        let mut state = if resume && path.exists() {
+            let contents = fs::read_to_string(&path)
+                .with_context(|| format!("Failed to read batch state: {}", path.display()))?;
+            serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse batch state: {}", path.display()))?
+        } else {
+            BatchState::default()
+        };
+
+        state.path = path;
+        Ok(state)
+    }
+
+    /// The recorded outcome for an input, if it has been completed
+    /// A planned rename whose source is gone but whose target exists was
+    /// applied before the interruption, so it counts as renamed
+    pub fn outcome(&self, input: &str) -> Option<Outcome> {
+        let outcome = self.entries.get(input)?.clone();
+
+        if let Outcome::Planned { new_filename, subdir, .. } = &outcome {
+            let source = Path::new(input);
+            let folder = source.parent().unwrap_or(Path::new("")).join(subdir.as_deref().unwrap_or(""));
+            let target = folder.join(new_filename);
+            if !source.exists() && target.exists() {
+                return Some(Outcome::Renamed);
+            }
+        }
+
+        Some(outcome)
+    }
+
+    /// Record an outcome and save the state immediately
+    pub fn record(&mut self, input: &str, outcome: Outcome) -> Result<()> {
+        self.entries.insert(input.to_string(), outcome);
+        self.save()
+    }
+
+    /// Remove the state file once the batch has finished cleanly
+    pub fn finish(self) -> Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path).with_context(|| {
+                format!("Failed to remove batch state: {}", self.path.display())
+            })?;
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+        }
+
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(&self.path, contents)
+            .with_context(|| format!("Failed to write batch state: {}", self.path.display()))
+    }
+}
+
+/// Location of the state file for a given set of inputs
+/// The key is a hash of the sorted absolute input paths, so the same files
+/// given in a different order or from a different directory still match
+fn state_path(inputs: &[String]) -> Result<PathBuf> {
+    let mut absolute = inputs
+        .iter()
+        .map(|input| {
+            std::path::absolute(input)
+                .map(|path| path.to_string_lossy().into_owned())
+                .with_context(|| format!("Failed to resolve path: {}", input))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    absolute.sort();
+
+    let digest = Sha256::digest(absolute.join("\n").as_bytes());
+    let key: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+
+    let cache_dir = dirs::cache_dir().context("Failed to locate a cache directory")?;
+    Ok(cache_dir
+        .join("paper-renamer")
+        .join("batches")
+        .join(format!("{}.json", key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_state_path_ignores_input_order() {
+        let a = vec!["a.pdf".to_string(), "b.pdf".to_string()];
+        let b = vec!["b.pdf".to_string(), "a.pdf".to_string()];
+        let c = vec!["a.pdf".to_string()];
+
+        assert_eq!(state_path(&a).unwrap(), state_path(&b).unwrap());
+        assert_ne!(state_path(&a).unwrap(), state_path(&c).unwrap());
+    }
+
+    #[test]
+    fn test_batch_state_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let state_file = temp_dir.path().join("state.json");
+
+        let mut state = BatchState::open_at(state_file.clone(), true).unwrap();
+        state.record("skipped.pdf", Outcome::Skipped).unwrap();
+
+        let resumed = BatchState::open_at(state_file.clone(), true).unwrap();
+        assert_eq!(resumed.outcome("skipped.pdf"), Some(Outcome::Skipped));
+        assert_eq!(resumed.outcome("other.pdf"), None);
+
+        // Without --resume, earlier progress is ignored
+        let fresh = BatchState::open_at(state_file.clone(), false).unwrap();
+        assert_eq!(fresh.outcome("skipped.pdf"), None);
+
+        resumed.finish().unwrap();
+        assert!(!state_file.exists());
+    }
+
+    #[test]
+    fn test_planned_rename_already_applied() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = temp_dir.path().join("original.pdf");
+        File::create(temp_dir.path().join("renamed.pdf")).unwrap();
+
+        let mut state = BatchState::open_at(temp_dir.path().join("state.json"), true).unwrap();
+        let source = source.to_str().unwrap();
+        state
+            .record(
+                source,
+                Outcome::Planned {
+                    new_filename: "renamed.pdf".to_string(),
+                    subdir: None,
+                    abstract_text: None,
+                    metadata: None,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(state.outcome(source), Some(Outcome::Renamed));
+    }
+}
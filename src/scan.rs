@@ -0,0 +1,127 @@
+use crate::filename;
+use crate::pdf::{self, TextOptions};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Whether text could be extracted from a PDF
+#[derive(Debug, Clone, PartialEq)]
+pub enum TextStatus {
+    /// Enough text for metadata extraction
+    Ok,
+    /// No or too little text; the file probably needs OCR
+    Empty(String),
+    /// Text that decodes to nonsense (broken font mapping); also needs OCR
+    Garbled(String),
+    /// The file couldn't be read as a PDF
+    Error(String),
+}
+
+/// Check every PDF under `dir` (recursively) for extractable text
+/// Only text extraction runs: no LLM calls and no renames
+pub fn scan_directory(dir: &str, options: &TextOptions) -> Result<Vec<(PathBuf, TextStatus)>> {
+    let mut pdfs = Vec::new();
+    collect_pdfs(Path::new(dir), &mut pdfs)?;
+
+    Ok(pdfs
+        .into_iter()
+        .map(|path| {
+            let status = check_pdf(&path.to_string_lossy(), options);
+            (path, status)
+        })
+        .collect())
+}
+
+fn check_pdf(file_path: &str, options: &TextOptions) -> TextStatus {
+    match pdf::load_document(file_path).and_then(|doc| pdf::extract_pdf_text(&doc, options)) {
+        Ok(_) => TextStatus::Ok,
+        Err(e) if e.downcast_ref::<pdf::InsufficientText>().is_some() => TextStatus::Empty(e.to_string()),
+        Err(e) if e.downcast_ref::<pdf::GarbledText>().is_some() => TextStatus::Garbled(e.to_string()),
+        Err(e) => TextStatus::Error(format!("{:#}", e)),
+    }
+}
+
+/// Gather PDF paths in sorted order, descending into subdirectories
+fn collect_pdfs(dir: &Path, pdfs: &mut Vec<PathBuf>) -> Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            collect_pdfs(&path, pdfs)?;
+        } else if path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(filename::has_pdf_extension)
+        {
+            pdfs.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Print one line per PDF and then the files that need attention
+/// Returns the number of problem files
+pub fn print_scan(results: &[(PathBuf, TextStatus)]) -> usize {
+    for (path, status) in results {
+        match status {
+            TextStatus::Ok => println!("OK     {}", path.display()),
+            TextStatus::Empty(_) => println!("EMPTY  {}", path.display()),
+            TextStatus::Garbled(_) => println!("GARBLE {}", path.display()),
+            TextStatus::Error(_) => println!("ERROR  {}", path.display()),
+        }
+    }
+
+    let problems: Vec<_> = results
+        .iter()
+        .filter_map(|(path, status)| match status {
+            TextStatus::Ok => None,
+            TextStatus::Empty(message) | TextStatus::Garbled(message) | TextStatus::Error(message) => {
+                Some((path, message))
+            }
+        })
+        .collect();
+
+    println!(
+        "\n{} PDFs scanned: {} OK, {} problem files",
+        results.len(),
+        results.len() - problems.len(),
+        problems.len()
+    );
+    if !problems.is_empty() {
+        println!("\nFiles needing attention:");
+        for (path, message) in &problems {
+            println!("  {}: {}", path.display(), message);
+        }
+    }
+
+    problems.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_scan_directory() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir(root.join("nested")).unwrap();
+        fs::write(root.join("broken.pdf"), "not a pdf").unwrap();
+        fs::write(root.join("nested/also-broken.PDF"), "not a pdf").unwrap();
+        fs::write(root.join("notes.txt"), "ignored").unwrap();
+
+        let results = scan_directory(root.to_str().unwrap(), &TextOptions::default()).unwrap();
+        let names: Vec<_> = results
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(names, vec!["broken.pdf", "also-broken.PDF"]);
+        assert!(matches!(results[0].1, TextStatus::Error(_)));
+    }
+}
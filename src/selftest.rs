@@ -0,0 +1,142 @@
+use crate::filename::{self, NamingOptions};
+use crate::llm::{self, PaperMetadata};
+
+/// LLM answers as models really give them, with the filename each should produce
+/// under the default format
+const RESPONSES: &[(&str, &str, &str)] = &[
+    (
+        "plain JSON answer",
+        r#"{"first_author": "Vaswani", "first_author_full": "Ashish Vaswani", "year": "2017", "title": "Attention Is All You Need"}"#,
+        "vaswani-2017-attention-is-all-you-need.pdf",
+    ),
+    (
+        "code-fenced answer with accents",
+        "Here is the metadata:\n```json\n{\"first_author\": \"Müller\", \"year\": \"2020\", \"title\": \"Über die Grenzen des Lernens\"}\n```",
+        "müller-2020-über-die-grenzen-des-lernens.pdf",
+    ),
+];
+
+/// The outcome of one self-test check
+#[derive(Debug)]
+pub struct Check {
+    pub name: String,
+    /// What differed from the expected result, if anything
+    pub outcome: Result<(), String>,
+}
+
+fn expect_eq(actual: impl AsRef<str>, expected: &str) -> Result<(), String> {
+    let actual = actual.as_ref();
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("expected {:?}, got {:?}", expected, actual))
+    }
+}
+
+fn expect(condition: bool, failure: &str) -> Result<(), String> {
+    if condition {
+        Ok(())
+    } else {
+        Err(failure.to_string())
+    }
+}
+
+/// Run the naming pipeline over the bundled fixtures, without Ollama or a PDF
+/// The default naming options are used so the expected names don't depend on
+/// the user's settings
+pub fn run_checks() -> Vec<Check> {
+    let options = NamingOptions::default();
+    let vaswani = PaperMetadata {
+        first_author: "Vaswani".to_string(),
+        year: "2017".to_string(),
+        title: "Attention Is All You Need".to_string(),
+        ..PaperMetadata::default()
+    };
+    let citekey = NamingOptions {
+        format: filename::CITEKEY_FORMAT.to_string(),
+        ..options.clone()
+    };
+
+    let mut checks = vec![
+        (
+            "sanitize: spaces and punctuation".to_string(),
+            expect_eq(filename::sanitize("Deep Learning: A Survey (2nd ed.)"), "deep-learning-a-survey-2nd-ed"),
+        ),
+        (
+            "sanitize: accented letters".to_string(),
+            expect_eq(filename::sanitize("Jörg Müller"), "jörg-müller"),
+        ),
+        (
+            "generate_filename: default format".to_string(),
+            expect_eq(
+                filename::generate_filename(&vaswani, &options),
+                "vaswani-2017-attention-is-all-you-need.pdf",
+            ),
+        ),
+        (
+            "generate_filename: citekey preset".to_string(),
+            expect_eq(filename::generate_filename(&vaswani, &citekey), "vaswani2017attention.pdf"),
+        ),
+        (
+            "validate_filename: accepts a generated name".to_string(),
+            expect(
+                filename::validate_filename("vaswani-2017-attention.pdf", options.max_filename_bytes),
+                "a valid name was rejected",
+            ),
+        ),
+        (
+            "validate_filename: rejects path separators".to_string(),
+            expect(
+                !filename::validate_filename("../vaswani-2017.pdf", options.max_filename_bytes),
+                "a name leaving the folder was accepted",
+            ),
+        ),
+    ];
+
+    for (description, response, expected) in RESPONSES {
+        let outcome = llm::parse_metadata_response(response, false)
+            .map_err(|e| format!("{:#}", e))
+            .and_then(|metadata| {
+                let name = filename::generate_filename(&metadata, &options);
+                expect(
+                    filename::validate_filename(&name, options.max_filename_bytes),
+                    &format!("generated an invalid name: {}", name),
+                )?;
+                expect_eq(name, expected)
+            });
+        checks.push((format!("metadata to filename: {}", description), outcome));
+    }
+
+    checks
+        .into_iter()
+        .map(|(name, outcome)| Check { name, outcome })
+        .collect()
+}
+
+/// Print one line per check and a summary; returns the number of failed checks
+pub fn print_checks(checks: &[Check]) -> usize {
+    for check in checks {
+        match &check.outcome {
+            Ok(()) => println!("PASS  {}", check.name),
+            Err(e) => println!("FAIL  {}: {}", check.name, e),
+        }
+    }
+
+    let failed = checks.iter().filter(|check| check.outcome.is_err()).count();
+    println!("\n{} checks: {} passed, {} failed", checks.len(), checks.len() - failed, failed);
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checks_pass() {
+        let checks = run_checks();
+        assert!(checks.len() >= RESPONSES.len());
+        for check in &checks {
+            assert!(check.outcome.is_ok(), "{}: {:?}", check.name, check.outcome);
+        }
+    }
+}
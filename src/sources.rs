@@ -0,0 +1,370 @@
+use crate::llm::{self, PaperMetadata};
+use crate::pdf::EmbeddedMetadata;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Where the value of a metadata field came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// Extracted by the LLM from the paper's text
+    Llm,
+    /// The PDF's embedded document information
+    Embedded,
+    /// The PDF's outline (bookmarks)
+    Outline,
+    /// The largest text on the title page
+    Heading,
+    /// The original filename (--fill-missing or the year fallback)
+    Filename,
+    /// Typed in by the user
+    User,
+    /// A citation copied to the clipboard (--from-clipboard)
+    Clipboard,
+    /// Not found anywhere; filled in by --on-missing-field placeholder
+    Placeholder,
+    /// Given with --author, --year or --title
+    CommandLine,
+}
+
+impl Source {
+    /// Short lowercase name, for tagging a file in the dry-run table
+    pub fn tag(self) -> &'static str {
+        match self {
+            Source::Llm => "llm",
+            Source::Embedded => "embedded",
+            Source::Outline => "outline",
+            Source::Heading => "heading",
+            Source::Filename => "filename",
+            Source::User => "user",
+            Source::Clipboard => "clipboard",
+            Source::Placeholder => "placeholder",
+            Source::CommandLine => "cli",
+        }
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Source::Llm => "LLM",
+            Source::Embedded => "embedded PDF metadata",
+            Source::Outline => "PDF outline",
+            Source::Heading => "largest heading",
+            Source::Filename => "filename",
+            Source::User => "entered by you",
+            Source::Clipboard => "clipboard citation",
+            Source::Placeholder => "placeholder",
+            Source::CommandLine => "command line",
+        })
+    }
+}
+
+/// The source of each filename field ("author", "year", "title")
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSources(BTreeMap<&'static str, Source>);
+
+/// Every field starts out as extracted by the LLM
+impl Default for FieldSources {
+    fn default() -> Self {
+        Self(["author", "year", "title"].into_iter().map(|field| (field, Source::Llm)).collect())
+    }
+}
+
+impl FieldSources {
+    pub fn set(&mut self, field: &'static str, source: Source) {
+        self.0.insert(field, source);
+    }
+
+    pub fn get(&self, field: &str) -> Option<Source> {
+        self.0.get(field).copied()
+    }
+
+    /// The distinct sources of the author, year and title in that order,
+    /// e.g. "[llm]" or "[embedded+llm]"
+    pub fn tag(&self) -> String {
+        let mut tags: Vec<&str> = Vec::new();
+        for source in ["author", "year", "title"].iter().filter_map(|field| self.get(field)) {
+            if !tags.contains(&source.tag()) {
+                tags.push(source.tag());
+            }
+        }
+        format!("[{}]", tags.join("+"))
+    }
+}
+
+impl fmt::Display for FieldSources {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let parts: Vec<String> = ["author", "year", "title"]
+            .iter()
+            .filter_map(|field| Some(format!("{} from {}", field, self.get(field)?)))
+            .collect();
+        f.write_str(&parts.join(", "))
+    }
+}
+
+/// Values given on the command line for a stubborn file, which win over
+/// every other source
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldOverrides {
+    /// Last name, or full name of the first author
+    pub author: Option<String>,
+    pub year: Option<String>,
+    pub title: Option<String>,
+}
+
+impl FieldOverrides {
+    /// Whether every field is given, so there is nothing left to ask the LLM
+    pub fn is_complete(&self) -> bool {
+        self.author.is_some() && self.year.is_some() && self.title.is_some()
+    }
+
+    /// Put the given values in place of the extracted ones
+    /// A full name also sets the full-name field; a last name alone stands for both
+    pub fn apply(&self, metadata: &mut PaperMetadata, sources: &mut FieldSources) {
+        if let Some(author) = &self.author {
+            let author = author.trim();
+            metadata.first_author = author.split_whitespace().last().unwrap_or_default().to_string();
+            metadata.first_author_full = author.to_string();
+            metadata.evidence.remove("first_author");
+            sources.set("author", Source::CommandLine);
+        }
+        if let Some(year) = &self.year {
+            metadata.year = year.trim().to_string();
+            metadata.evidence.remove("year");
+            sources.set("year", Source::CommandLine);
+        }
+        if let Some(title) = &self.title {
+            metadata.title = title.trim().to_string();
+            metadata.title_slug.clear();
+            metadata.evidence.remove("title");
+            sources.set("title", Source::CommandLine);
+        }
+    }
+}
+
+/// Which of the LLM and the embedded metadata wins where both have a value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Precedence {
+    /// The LLM; embedded values only fill fields it left empty
+    #[default]
+    Llm,
+    /// The embedded metadata, wherever it has a value
+    Embedded,
+    /// The embedded title, with the author and year from the LLM: publisher metadata
+    /// often has an exact title but a whole author list in its Author entry
+    Hybrid,
+}
+
+/// Combine the LLM's metadata with the PDF's embedded metadata, field by field
+/// in the given precedence; an empty value never replaces a non-empty one
+pub fn merge_embedded(
+    metadata: &mut PaperMetadata,
+    embedded: &EmbeddedMetadata,
+    precedence: Precedence,
+    sources: &mut FieldSources,
+) {
+    let take = |field: &str, current: &str, candidate: &str| {
+        let embedded_wins = match precedence {
+            Precedence::Llm => false,
+            Precedence::Embedded => true,
+            Precedence::Hybrid => field == "title",
+        };
+        !candidate.is_empty() && (embedded_wins || current.trim().is_empty())
+    };
+
+    if take("author", &metadata.first_author, &embedded.first_author) {
+        metadata.first_author = embedded.first_author.clone();
+        metadata.first_author_full = embedded.first_author_full.clone();
+        metadata.evidence.remove("first_author");
+        sources.set("author", Source::Embedded);
+    }
+    if take("year", &metadata.year, &embedded.year) {
+        metadata.year = embedded.year.clone();
+        metadata.evidence.remove("year");
+        sources.set("year", Source::Embedded);
+    }
+    if take("title", &metadata.title, &embedded.title) {
+        metadata.title = embedded.title.clone();
+        metadata.evidence.remove("title");
+        sources.set("title", Source::Embedded);
+    }
+}
+
+/// Use a title from the PDF's outline (see `pdf::extract_outline_title`) where
+/// the title is missing or implausible, e.g. when the LLM only saw a scanned page
+pub fn merge_outline_title(metadata: &mut PaperMetadata, outline_title: &str, sources: &mut FieldSources) {
+    if outline_title.is_empty() || !llm::implausible_fields(metadata).contains(&"title") {
+        return;
+    }
+    metadata.title = outline_title.to_string();
+    metadata.title_slug.clear();
+    metadata.evidence.remove("title");
+    sources.set("title", Source::Outline);
+}
+
+/// A title proposed by one or more sources, for --interactive-title-selection
+#[derive(Debug, Clone, PartialEq)]
+pub struct TitleCandidate {
+    pub title: String,
+    pub sources: Vec<Source>,
+}
+
+/// The distinct titles the sources proposed, in the order given
+/// Runs of whitespace don't make a title different; empty titles are dropped
+pub fn title_candidates(proposed: &[(Source, &str)]) -> Vec<TitleCandidate> {
+    let mut candidates: Vec<TitleCandidate> = Vec::new();
+    for (source, title) in proposed {
+        let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+        if title.is_empty() {
+            continue;
+        }
+        match candidates.iter_mut().find(|candidate| candidate.title == title) {
+            Some(candidate) => candidate.sources.push(*source),
+            None => candidates.push(TitleCandidate {
+                title,
+                sources: vec![*source],
+            }),
+        }
+    }
+    candidates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pdf;
+
+    fn llm_metadata() -> PaperMetadata {
+        PaperMetadata {
+            first_author: "Vaswani".to_string(),
+            first_author_full: "Ashish Vaswani".to_string(),
+            year: String::new(),
+            title: "Attention Is All You Need".to_string(),
+            ..PaperMetadata::default()
+        }
+    }
+
+    fn embedded() -> EmbeddedMetadata {
+        EmbeddedMetadata {
+            first_author: "Shazeer".to_string(),
+            first_author_full: "Noam Shazeer".to_string(),
+            year: "2017".to_string(),
+            title: String::new(),
+            publisher: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_merge_embedded_fills_gaps_by_default() {
+        let mut metadata = llm_metadata();
+        let mut sources = FieldSources::default();
+        merge_embedded(&mut metadata, &embedded(), Precedence::Llm, &mut sources);
+
+        assert_eq!(metadata.first_author, "Vaswani");
+        assert_eq!(metadata.year, "2017");
+        assert_eq!(sources.get("author"), Some(Source::Llm));
+        assert_eq!(sources.get("year"), Some(Source::Embedded));
+        assert_eq!(
+            sources.to_string(),
+            "author from LLM, year from embedded PDF metadata, title from LLM"
+        );
+    }
+
+    #[test]
+    fn test_merge_embedded_preferred() {
+        let mut metadata = llm_metadata();
+        let mut sources = FieldSources::default();
+        merge_embedded(&mut metadata, &embedded(), Precedence::Embedded, &mut sources);
+
+        assert_eq!(metadata.first_author, "Shazeer");
+        assert_eq!(metadata.first_author_full, "Noam Shazeer");
+        // An empty embedded title never replaces the LLM's
+        assert_eq!(metadata.title, "Attention Is All You Need");
+        assert_eq!(sources.get("author"), Some(Source::Embedded));
+        assert_eq!(sources.get("title"), Some(Source::Llm));
+        assert_eq!(sources.tag(), "[embedded+llm]");
+        assert_eq!(FieldSources::default().tag(), "[llm]");
+    }
+
+    #[test]
+    fn test_merge_embedded_hybrid() {
+        // Publisher metadata with an exact title and the whole author list
+        let embedded = pdf::parse_embedded(
+            "Attention Is All You Need",
+            "Ashish Vaswani; Noam Shazeer; et al.",
+            "D:20170612143000Z",
+        );
+        let mut metadata = PaperMetadata {
+            year: "2017".to_string(),
+            title: "Attention is all you need".to_string(),
+            ..llm_metadata()
+        };
+        let mut sources = FieldSources::default();
+        merge_embedded(&mut metadata, &embedded, Precedence::Hybrid, &mut sources);
+
+        assert_eq!(metadata.title, "Attention Is All You Need");
+        assert_eq!(metadata.first_author, "Vaswani");
+        assert_eq!(sources.tag(), "[llm+embedded]");
+
+        // Without an author from the LLM, the first entry of the list is parsed out
+        let mut metadata = PaperMetadata {
+            first_author: String::new(),
+            first_author_full: String::new(),
+            ..llm_metadata()
+        };
+        merge_embedded(&mut metadata, &embedded, Precedence::Hybrid, &mut sources);
+        assert_eq!(metadata.first_author, "Vaswani");
+        assert_eq!(metadata.first_author_full, "Ashish Vaswani");
+    }
+
+    #[test]
+    fn test_title_candidates() {
+        let candidates = title_candidates(&[
+            (Source::Llm, "Attention is all you need"),
+            (Source::Embedded, "Attention Is All You Need"),
+            (Source::Outline, "Attention  Is All\nYou Need"),
+            (Source::Heading, ""),
+        ]);
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0].sources, vec![Source::Llm]);
+        assert_eq!(candidates[1].title, "Attention Is All You Need");
+        assert_eq!(candidates[1].sources, vec![Source::Embedded, Source::Outline]);
+    }
+
+    #[test]
+    fn test_merge_outline_title() {
+        let mut metadata = llm_metadata();
+        let mut sources = FieldSources::default();
+        merge_outline_title(&mut metadata, "Attention Is All You Need (Outline)", &mut sources);
+        // A plausible title is kept
+        assert_eq!(metadata.title, "Attention Is All You Need");
+
+        metadata.title = "--".to_string();
+        merge_outline_title(&mut metadata, "Attention Is All You Need", &mut sources);
+        assert_eq!(metadata.title, "Attention Is All You Need");
+        assert_eq!(sources.get("title"), Some(Source::Outline));
+    }
+
+    #[test]
+    fn test_field_overrides() {
+        let mut metadata = llm_metadata();
+        let mut sources = FieldSources::default();
+        let overrides = FieldOverrides {
+            author: Some("Noam Shazeer".to_string()),
+            year: Some(" 2017 ".to_string()),
+            title: None,
+        };
+        assert!(!overrides.is_complete());
+        overrides.apply(&mut metadata, &mut sources);
+
+        assert_eq!(metadata.first_author, "Shazeer");
+        assert_eq!(metadata.first_author_full, "Noam Shazeer");
+        assert_eq!(metadata.year, "2017");
+        assert_eq!(metadata.title, "Attention Is All You Need");
+        assert_eq!(
+            sources.to_string(),
+            "author from command line, year from command line, title from LLM"
+        );
+    }
+}
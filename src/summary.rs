@@ -0,0 +1,133 @@
+use crate::report::{Entry, Status};
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::time::{Duration, Instant};
+
+/// Timing of a run and of every metadata extraction in it
+#[derive(Debug, Clone)]
+pub struct Usage {
+    started: Instant,
+    /// Model and duration of each extraction, in order
+    extractions: Vec<(String, Duration)>,
+}
+
+impl Usage {
+    pub fn start() -> Self {
+        Self {
+            started: Instant::now(),
+            extractions: Vec::new(),
+        }
+    }
+
+    /// Note one call to the LLM, including retries with another model
+    pub fn record(&mut self, model: &str, elapsed: Duration) {
+        self.extractions.push((model.to_string(), elapsed));
+    }
+}
+
+/// Extractions done by one model
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelUsage {
+    pub extractions: usize,
+    pub average_extraction_seconds: f64,
+}
+
+/// Aggregate statistics of a batch, written by --summary-json
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Summary {
+    pub total_files: usize,
+    pub renamed: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub total_seconds: f64,
+    /// Over every extraction; null if the LLM was never asked
+    pub average_extraction_seconds: Option<f64>,
+    pub models: BTreeMap<String, ModelUsage>,
+}
+
+fn average(durations: &[Duration]) -> Option<f64> {
+    let total: Duration = durations.iter().sum();
+    (!durations.is_empty()).then(|| total.as_secs_f64() / durations.len() as f64)
+}
+
+/// Sum up a run from its report entries and extraction timings
+/// `elapsed` is the run's total time
+pub fn summarize(entries: &[Entry], usage: &Usage, elapsed: Duration) -> Summary {
+    let count = |status| entries.iter().filter(|entry| entry.status == status).count();
+
+    let mut by_model: BTreeMap<&str, Vec<Duration>> = BTreeMap::new();
+    for (model, duration) in &usage.extractions {
+        by_model.entry(model).or_default().push(*duration);
+    }
+    let all: Vec<Duration> = usage.extractions.iter().map(|(_, duration)| *duration).collect();
+
+    Summary {
+        total_files: entries.len(),
+        renamed: count(Status::Renamed),
+        skipped: count(Status::Skipped),
+        failed: count(Status::Failed),
+        total_seconds: elapsed.as_secs_f64(),
+        average_extraction_seconds: average(&all),
+        models: by_model
+            .into_iter()
+            .map(|(model, durations)| {
+                let usage = ModelUsage {
+                    extractions: durations.len(),
+                    average_extraction_seconds: average(&durations).unwrap_or_default(),
+                };
+                (model.to_string(), usage)
+            })
+            .collect(),
+    }
+}
+
+/// Write the summary of a finished run as one JSON object
+pub fn write_summary(path: &str, entries: &[Entry], usage: &Usage) -> Result<()> {
+    let summary = summarize(entries, usage, usage.started.elapsed());
+    let contents = serde_json::to_string_pretty(&summary).context("Failed to serialize summary")?;
+    fs::write(path, contents + "\n").with_context(|| format!("Failed to write summary: {}", path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(status: Status) -> Entry {
+        Entry {
+            source: "paper.pdf".to_string(),
+            status,
+            new_name: None,
+            message: None,
+        }
+    }
+
+    #[test]
+    fn test_summarize() {
+        let mut usage = Usage::start();
+        usage.record("llama3.2", Duration::from_secs(2));
+        usage.record("llama3.2", Duration::from_secs(4));
+        usage.record("qwen2.5:14b", Duration::from_secs(9));
+        let entries = [entry(Status::Renamed), entry(Status::Renamed), entry(Status::Failed)];
+
+        let summary = summarize(&entries, &usage, Duration::from_secs(20));
+        assert_eq!(summary.total_files, 3);
+        assert_eq!((summary.renamed, summary.skipped, summary.failed), (2, 0, 1));
+        assert_eq!(summary.total_seconds, 20.0);
+        assert_eq!(summary.average_extraction_seconds, Some(5.0));
+        assert_eq!(
+            summary.models["llama3.2"],
+            ModelUsage {
+                extractions: 2,
+                average_extraction_seconds: 3.0
+            }
+        );
+        assert_eq!(summary.models["qwen2.5:14b"].extractions, 1);
+
+        // Nothing extracted: no average rather than a division by zero
+        let summary = summarize(&[], &Usage::start(), Duration::ZERO);
+        assert_eq!(summary.average_extraction_seconds, None);
+        assert!(summary.models.is_empty());
+    }
+}
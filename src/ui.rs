@@ -1,34 +1,137 @@
-use anyhow::Result;
+use crate::llm::{self, PaperMetadata};
+use crate::sources::TitleCandidate;
+use anyhow::{Context, Result};
 use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
 use indicatif::{ProgressBar, ProgressStyle};
+use regex::Regex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// Set for --json, where stdout is reserved for machine-readable output
+static JSON_MODE: AtomicBool = AtomicBool::new(false);
+
+/// Switch progress output to stderr and hide spinners, so stdout carries only JSON
+pub fn set_json_mode(enabled: bool) {
+    JSON_MODE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn json_mode() -> bool {
+    JSON_MODE.load(Ordering::Relaxed)
+}
+
+/// Symbols and message templates for terminals or locales the defaults don't suit
+/// (see the user settings); the defaults reproduce the built-in output
+#[derive(Debug, Clone, PartialEq)]
+pub struct Messages {
+    /// Marks a rename or a finished step
+    pub success_symbol: String,
+    /// Marks errors and warnings
+    pub error_symbol: String,
+    /// Shown after a rename, with {old} and {new} for the names
+    pub success_message: String,
+    /// Shown for an error, with {message} for the error
+    pub error_message: String,
+}
+
+impl Default for Messages {
+    fn default() -> Self {
+        Self {
+            success_symbol: "✓".to_string(),
+            error_symbol: "⚠".to_string(),
+            success_message: "File renamed successfully!\n  {old} -> {new}".to_string(),
+            error_message: "Error: {message}".to_string(),
+        }
+    }
+}
+
+static MESSAGES: OnceLock<Messages> = OnceLock::new();
+
+/// Use these symbols and templates for the rest of the run; only the first call counts
+pub fn set_messages(messages: Messages) {
+    let _ = MESSAGES.set(messages);
+}
+
+fn messages() -> &'static Messages {
+    MESSAGES.get_or_init(Messages::default)
+}
+
+/// Check that a message template only uses the given placeholders, e.g. `{old}`
+pub fn check_template(template: &str, placeholders: &[&str]) -> Result<()> {
+    let placeholder = Regex::new(r"\{([^{}]*)\}").unwrap();
+    for found in placeholder.captures_iter(template) {
+        if !placeholders.contains(&&found[1]) {
+            let allowed: Vec<String> = placeholders.iter().map(|name| format!("{{{}}}", name)).collect();
+            anyhow::bail!("Unknown placeholder {} (use {})", &found[0], allowed.join(", "));
+        }
+    }
+    Ok(())
+}
+
+/// Put a symbol in front of a line, leaving no stray space for an empty one
+fn mark(symbol: &str, text: &str) -> String {
+    if symbol.is_empty() {
+        text.to_string()
+    } else {
+        format!("{} {}", symbol, text)
+    }
+}
+
+/// A line marked with the error symbol, for errors not shown by `display_error`
+pub fn error_line(text: &str) -> String {
+    mark(&messages().error_symbol, text)
+}
+
+/// Print progress for a human reader: to stdout, or to stderr in --json mode
+macro_rules! say {
+    ($($arg:tt)*) => {
+        if $crate::ui::json_mode() {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
 
 #[derive(Debug, PartialEq)]
 pub enum UserChoice {
     Yes,
+    YesToAll,
     No,
+    NoToAll,
+    Abort,
+    RetryModel,
     Edit,
     EditAuthor,
     EditYear,
     EditTitle,
+    EditPrompt,
 }
 
 /// Prompt the user to confirm the rename operation
-/// Returns the user's choice: Yes, No, Edit, EditAuthor, EditYear, or EditTitle
-pub fn confirm_rename(original: &str, proposed: &str) -> Result<UserChoice> {
-    println!("\nProposed filename: {}", proposed);
-    println!();
+/// Returns the user's choice: Yes, YesToAll, No, NoToAll, Abort, RetryModel, Edit, EditAuthor, EditYear, or EditTitle,
+/// or EditPrompt when `edit_prompt` offers it (--prompt-debug)
+pub fn confirm_rename(original: &str, proposed: &str, edit_prompt: bool) -> Result<UserChoice> {
+    say!("\nProposed filename: {}", proposed);
+    say!();
 
-    let choices = vec![
+    let mut choices = vec![
         "Yes - rename the file",
+        "Yes to all - rename this and every remaining file without asking",
         "No - cancel",
+        "No to all - skip this and every remaining file",
+        "Abort - stop the batch here without renaming anything",
+        "Retry with another model - extract the metadata again with a different model",
         "Edit filename - modify the complete filename",
         "Edit author - change the author name",
         "Edit year - change the publication year",
         "Edit title - change the paper title",
     ];
+    if edit_prompt {
+        choices.push("Edit prompt - change the LLM prompt in your editor and extract again");
+    }
 
     let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt(&format!(
+        .with_prompt(format!(
             "Would you like to rename '{}' to '{}'?",
             original, proposed
         ))
@@ -38,20 +141,73 @@ pub fn confirm_rename(original: &str, proposed: &str) -> Result<UserChoice> {
 
     Ok(match selection {
         0 => UserChoice::Yes,
-        1 => UserChoice::No,
-        2 => UserChoice::Edit,
-        3 => UserChoice::EditAuthor,
-        4 => UserChoice::EditYear,
-        5 => UserChoice::EditTitle,
+        1 => UserChoice::YesToAll,
+        2 => UserChoice::No,
+        3 => UserChoice::NoToAll,
+        4 => UserChoice::Abort,
+        5 => UserChoice::RetryModel,
+        6 => UserChoice::Edit,
+        7 => UserChoice::EditAuthor,
+        8 => UserChoice::EditYear,
+        9 => UserChoice::EditTitle,
+        10 => UserChoice::EditPrompt,
         _ => unreachable!(),
     })
 }
 
+/// Show every planned rename of a batch and ask whether to apply them all
+/// Takes (original, proposed) filename pairs
+pub fn review_plan(renames: &[(String, String)]) -> Result<bool> {
+    say!("\nPlanned renames:");
+    for (original, proposed) in renames {
+        say!("  {} -> {}", original, proposed);
+    }
+    say!();
+
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Rename all {} files?", renames.len()))
+        .default(false)
+        .interact()
+        .map_err(|e| e.into())
+}
+
+/// Ask whether to start a batch after the preflight check
+pub fn confirm_start(count: usize) -> Result<bool> {
+    Confirm::with_theme(&ColorfulTheme::default())
+        .with_prompt(format!("Start processing {} files?", count))
+        .default(true)
+        .interact()
+        .map_err(|e| e.into())
+}
+
+/// Format a duration for humans, e.g. `45s`, `3m 20s` or `1h 05m`
+pub fn format_duration(duration: std::time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    } else if secs >= 60 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// Read the text currently on the system clipboard
+pub fn clipboard_text() -> Result<String> {
+    let text = arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|e| anyhow::anyhow!("Failed to read the clipboard: {}", e))?;
+    if text.trim().is_empty() {
+        anyhow::bail!("The clipboard is empty; copy a citation first");
+    }
+    Ok(text.trim().to_string())
+}
+
 /// Prompt the user to edit the proposed filename
 /// Returns the edited filename
 /// The current filename is pre-filled for editing
 pub fn edit_filename(proposed: &str) -> Result<String> {
-    println!("\nEdit the filename below (current filename is pre-filled):");
+    say!("\nEdit the filename below (current filename is pre-filled):");
 
     let edited: String = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Filename")
@@ -62,6 +218,43 @@ pub fn edit_filename(proposed: &str) -> Result<String> {
     Ok(edited.trim().to_string())
 }
 
+/// Let the user pick the model to retry the extraction with
+/// The model that was just used is listed but not preselected
+pub fn select_model(models: &[String], current: &str) -> Result<String> {
+    let default = models.iter().position(|model| model != current).unwrap_or(0);
+    let items: Vec<String> = models
+        .iter()
+        .map(|model| if model == current { format!("{} (current)", model) } else { model.clone() })
+        .collect();
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which model should extract the metadata?")
+        .items(&items)
+        .default(default)
+        .interact()?;
+    Ok(models[selection].clone())
+}
+
+/// Let the user pick the title among the sources' candidates, or type their own
+/// Returns the index of the chosen candidate, or None to type one
+pub fn select_title(candidates: &[TitleCandidate]) -> Result<Option<usize>> {
+    let mut items: Vec<String> = candidates
+        .iter()
+        .map(|candidate| {
+            let sources: Vec<String> = candidate.sources.iter().map(ToString::to_string).collect();
+            format!("{}  ({})", candidate.title, sources.join(", "))
+        })
+        .collect();
+    items.push("Type my own title".to_string());
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("The sources disagree on the title. Which one is right?")
+        .items(&items)
+        .default(0)
+        .interact()?;
+    Ok((selection < candidates.len()).then_some(selection))
+}
+
 /// Ask if the user wants to enter metadata manually
 pub fn ask_manual_metadata() -> Result<bool> {
     Confirm::with_theme(&ColorfulTheme::default())
@@ -71,40 +264,71 @@ pub fn ask_manual_metadata() -> Result<bool> {
         .map_err(|e| e.into())
 }
 
+/// Show the LLM's answer as it came, for -v
+pub fn display_raw_response(response: &str) {
+    say!("\nRaw LLM response:\n{}\n", response);
+}
+
 /// Display metadata extracted from the PDF
-pub fn display_metadata(author: &str, year: &str, title: &str) {
-    println!("\nExtracted metadata:");
-    println!("  - First Author: {}", author);
-    println!("  - Year: {}", year);
-    println!("  - Title: {}", title);
+/// Where the LLM found a field is shown below it, when that was requested
+pub fn display_metadata(metadata: &PaperMetadata) {
+    let evidence = |field: &str| {
+        if let Some(note) = metadata.evidence.get(field) {
+            say!("      ↳ {}", note);
+        }
+    };
+
+    say!("\nExtracted metadata:");
+    say!("  - First Author: {}", metadata.first_author);
+    evidence("first_author");
+    say!("  - Year: {}", metadata.year);
+    evidence("year");
+    say!("  - Title: {}", metadata.title);
+    evidence("title");
 }
 
 /// Display success message
 pub fn display_success(old_name: &str, new_name: &str) {
-    println!("\n✓ File renamed successfully!");
-    println!("  {} -> {}", old_name, new_name);
+    let messages = messages();
+    let message = messages.success_message.replace("{old}", old_name).replace("{new}", new_name);
+    say!("\n{}", mark(&messages.success_symbol, &message));
+}
+
+/// Summarize a batch stopped with "Abort"; nothing was renamed
+pub fn display_aborted(confirmed: usize, skipped: usize, failed: usize, not_reached: usize) {
+    say!("\nBatch aborted. Nothing was renamed.");
+    say!("  Confirmed:   {}", confirmed);
+    say!("  Skipped:     {}", skipped);
+    say!("  Failed:      {}", failed);
+    say!("  Not reached: {}", not_reached);
 }
 
 /// Display cancellation message
 pub fn display_cancelled() {
-    println!("\nOperation cancelled.");
+    say!("\nOperation cancelled.");
 }
 
 /// Display error message
 pub fn display_error(error: &str) {
-    eprintln!("\n⚠ Error: {}", error);
+    eprintln!("\n{}", error_line(&messages().error_message.replace("{message}", error)));
+}
+
+/// Display a warning that doesn't stop the file
+pub fn display_warning(warning: &str) {
+    say!("\n{}", error_line(warning));
 }
 
 /// Prompt the user to edit the author
 /// Returns the edited author name
 /// The current author is pre-filled for editing
 pub fn edit_author(current: &str) -> Result<String> {
-    println!("\nEdit the author (last name only):");
+    say!("\nEdit the author (last name only):");
 
     let edited: String = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Author")
         .with_initial_text(current)
         .allow_empty(false)
+        .validate_with(|input: &String| validate_words(input))
         .interact_text()?;
 
     Ok(edited.trim().to_string())
@@ -114,12 +338,13 @@ pub fn edit_author(current: &str) -> Result<String> {
 /// Returns the edited year
 /// The current year is pre-filled for editing
 pub fn edit_year(current: &str) -> Result<String> {
-    println!("\nEdit the publication year:");
+    say!("\nEdit the publication year:");
 
     let edited: String = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Year")
         .with_initial_text(current)
         .allow_empty(false)
+        .validate_with(|input: &String| validate_year(input))
         .interact_text()?;
 
     Ok(edited.trim().to_string())
@@ -129,20 +354,73 @@ pub fn edit_year(current: &str) -> Result<String> {
 /// Returns the edited title
 /// The current title is pre-filled for editing
 pub fn edit_title(current: &str) -> Result<String> {
-    println!("\nEdit the paper title:");
+    say!("\nEdit the paper title:");
 
     let edited: String = Input::with_theme(&ColorfulTheme::default())
         .with_prompt("Title")
         .with_initial_text(current)
         .allow_empty(false)
+        .validate_with(|input: &String| validate_words(input))
         .interact_text()?;
 
     Ok(edited.trim().to_string())
 }
 
+/// Open the LLM prompt in the user's editor ($VISUAL, then $EDITOR)
+/// Returns the prompt as saved when the editor exits
+pub fn edit_prompt(current: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| if cfg!(windows) { "notepad" } else { "vi" }.to_string());
+
+    let file = tempfile::Builder::new()
+        .prefix("paper-renamer-prompt-")
+        .suffix(".txt")
+        .tempfile()
+        .context("Failed to create a file for the prompt")?;
+    std::fs::write(file.path(), current).context("Failed to write the prompt")?;
+
+    // The editor may come with arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().context("The editor command is empty")?;
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(file.path())
+        .status()
+        .with_context(|| format!("Failed to run the editor: {}", editor))?;
+    if !status.success() {
+        anyhow::bail!("The editor exited with {}", status);
+    }
+
+    std::fs::read_to_string(file.path()).context("Failed to read the edited prompt")
+}
+
+/// Accept a year only as four digits, e.g. `2020`
+fn validate_year(input: &str) -> Result<(), &'static str> {
+    if llm::is_valid_year(input.trim()) {
+        Ok(())
+    } else {
+        Err("Enter the year as four digits, e.g. 2020")
+    }
+}
+
+/// Accept an author or title only if something of it would survive in the filename:
+/// sanitizing keeps letters and digits of any script, so one of those is required
+fn validate_words(input: &str) -> Result<(), &'static str> {
+    if input.chars().any(char::is_alphanumeric) {
+        Ok(())
+    } else {
+        Err("Enter at least one letter or digit")
+    }
+}
+
 /// Create a spinner with a custom message
 /// Returns a ProgressBar that should be finished when the operation completes
 pub fn create_spinner(message: &str) -> ProgressBar {
+    if json_mode() {
+        return ProgressBar::hidden();
+    }
+
     let spinner = ProgressBar::new_spinner();
     spinner.set_style(
         ProgressStyle::default_spinner()
@@ -159,5 +437,46 @@ pub fn create_spinner(message: &str) -> ProgressBar {
 
 /// Finish a spinner with a success message
 pub fn finish_spinner(spinner: ProgressBar, message: &str) {
-    spinner.finish_with_message(format!("✓ {}", message));
+    spinner.finish_with_message(mark(&messages().success_symbol, message));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_validate_year() {
+        assert!(validate_year("2020").is_ok());
+        assert!(validate_year(" 1999 ").is_ok());
+        assert!(validate_year("twenty-twenty").is_err());
+        assert!(validate_year("２０２０").is_err());
+        assert!(validate_year("20").is_err());
+    }
+
+    #[test]
+    fn test_validate_words() {
+        assert!(validate_words("Müller").is_ok());
+        assert!(validate_words("深度学习").is_ok());
+        assert!(validate_words("--- ?!").is_err());
+    }
+
+    #[test]
+    fn test_messages() {
+        assert_eq!(mark("✓", "Done"), "✓ Done");
+        assert_eq!(mark("", "Done"), "Done");
+
+        let defaults = Messages::default();
+        assert!(check_template(&defaults.success_message, &["old", "new"]).is_ok());
+        assert!(check_template(&defaults.error_message, &["message"]).is_ok());
+        assert!(check_template("[OK] {old} => {new}", &["old", "new"]).is_ok());
+        assert!(check_template("{old} -> {neu}", &["old", "new"]).is_err());
+    }
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(45_900)), "45s");
+        assert_eq!(format_duration(Duration::from_secs(200)), "3m 20s");
+        assert_eq!(format_duration(Duration::from_secs(3900)), "1h 05m");
+    }
 }
@@ -0,0 +1,103 @@
+use anyhow::{Context, Result};
+use notify_debouncer_mini::notify::RecursiveMode;
+use notify_debouncer_mini::{new_debouncer, DebouncedEventKind};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long a file must stay untouched before it is considered fully written
+const DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Suffixes used by browsers and download managers for partial downloads
+const TEMPORARY_SUFFIXES: &[&str] = &[".crdownload", ".part", ".partial", ".download", ".tmp"];
+
+/// Watch a directory and call `process` for every new PDF that lands in it
+/// Events are debounced so files that are still being written are not
+/// processed mid-write. Runs until the watcher is closed or fails.
+/// `process` returns the path the file ended up at (if it was renamed),
+/// which is remembered so the tool doesn't pick up its own renames.
+pub fn watch_directory<F>(dir: &str, mut process: F) -> Result<()>
+where
+    F: FnMut(&Path) -> Result<Option<PathBuf>>,
+{
+    let dir = Path::new(dir);
+    if !dir.is_dir() {
+        anyhow::bail!("Watch path is not a directory: {}", dir.display());
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut debouncer =
+        new_debouncer(DEBOUNCE_TIMEOUT, tx).context("Failed to start file watcher")?;
+    debouncer
+        .watcher()
+        .watch(dir, RecursiveMode::NonRecursive)
+        .with_context(|| format!("Failed to watch directory: {}", dir.display()))?;
+
+    let mut produced: HashSet<PathBuf> = HashSet::new();
+
+    for result in rx {
+        let events = match result {
+            Ok(events) => events,
+            Err(e) => {
+                eprintln!("\n{}", crate::ui::error_line(&format!("Watch error: {}", e)));
+                continue;
+            }
+        };
+
+        for event in events {
+            // The file is still receiving writes; wait for it to settle
+            if event.kind == DebouncedEventKind::AnyContinuous {
+                continue;
+            }
+
+            let path = event.path;
+            if !path.is_file() || !is_candidate_pdf(&path) || produced.remove(&path) {
+                continue;
+            }
+
+            match process(&path) {
+                Ok(Some(new_path)) => {
+                    produced.insert(new_path);
+                }
+                Ok(None) => {}
+                Err(e) => crate::ui::display_error(&format!("{}: {:#}", path.display(), e)),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check whether a path looks like a finished PDF download
+/// Hidden files and partial downloads (e.g. `paper.pdf.crdownload`) are skipped
+fn is_candidate_pdf(path: &Path) -> bool {
+    let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+        return false;
+    };
+
+    if name.starts_with('.') {
+        return false;
+    }
+
+    if TEMPORARY_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)) {
+        return false;
+    }
+
+    crate::filename::has_pdf_extension(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_candidate_pdf() {
+        assert!(is_candidate_pdf(Path::new("/downloads/paper.pdf")));
+        assert!(is_candidate_pdf(Path::new("/downloads/Paper.PDF")));
+        assert!(!is_candidate_pdf(Path::new("/downloads/paper.pdf.crdownload")));
+        assert!(!is_candidate_pdf(Path::new("/downloads/paper.pdf.part")));
+        assert!(!is_candidate_pdf(Path::new("/downloads/.paper.pdf")));
+        assert!(!is_candidate_pdf(Path::new("/downloads/notes.txt")));
+    }
+}